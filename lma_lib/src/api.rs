@@ -1,21 +1,115 @@
+pub mod anilist;
+pub mod boxed;
+pub mod cached;
+pub mod kitsu;
 pub mod local;
 pub mod mal;
+pub mod mirror;
+pub mod shikimori;
+mod oauth;
+mod rate_limit;
+use crate::{RemoteEntries, SyncProgress};
+pub use oauth::DEFAULT_REDIRECT_PORT;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, fmt, path::PathBuf};
+use time::{Date, Month, OffsetDateTime};
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Debug)]
 pub enum ServiceType {
     MAL,
+    AniList,
+    Kitsu,
+    Shikimori,
     Local,
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+impl ServiceType {
+    pub const ALL: [Self; 5] = [Self::MAL, Self::AniList, Self::Kitsu, Self::Shikimori, Self::Local];
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::MAL => "MAL",
+            Self::AniList => "AniList",
+            Self::Kitsu => "Kitsu",
+            Self::Shikimori => "Shikimori",
+            Self::Local => "Local",
+        }
+    }
+
+    /// Case insensitive, same names as in the config
+    pub fn parse(service: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|service_type| service_type.as_str().eq_ignore_ascii_case(service.trim()))
+    }
+
+    /// Whose ids shows have, the offline database of Local is keyed by MAL ids
+    pub fn id_service(&self) -> Self {
+        match self {
+            Self::Local => Self::MAL,
+            service => service.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Debug)]
 pub struct ServiceTitle {
     pub service_id: usize,
     pub title: String,
+    pub media_type: Option<MediaType>,
+    pub year: Option<u32>,
+    pub episodes: Option<usize>,
+    pub nsfw: bool,
+}
+
+#[derive(Default, PartialEq, Eq, Debug)]
+pub struct ServiceSearchPage {
+    pub titles: Vec<ServiceTitle>,
+    /// Offset of the next page, `None` on the last one
+    pub next_offset: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MediaType {
+    TV,
+    Movie,
+    OVA,
+    ONA,
+    Special,
+    Music,
+    Other,
+}
+
+impl MediaType {
+    pub const ALL: [Self; 7] = [Self::TV, Self::Movie, Self::OVA, Self::ONA, Self::Special, Self::Music, Self::Other];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::TV => "TV",
+            Self::Movie => "Movie",
+            Self::OVA => "OVA",
+            Self::ONA => "ONA",
+            Self::Special => "Special",
+            Self::Music => "Music",
+            Self::Other => "Other",
+        }
+    }
+
+    /// Understands the names used by every service, like "tv", "TV_SHORT" or "tv_special"
+    pub fn parse(media_type: &str) -> Self {
+        match media_type.trim().to_lowercase().as_str() {
+            "tv" | "tv_short" => Self::TV,
+            "movie" => Self::Movie,
+            "ova" => Self::OVA,
+            "ona" => Self::ONA,
+            "special" | "tv_special" => Self::Special,
+            "music" => Self::Music,
+            _ => Self::Other,
+        }
+    }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
 pub struct ServiceEpisodeUser {
     pub status: Option<EpisodeStatus>,
     pub progress: Option<usize>,
@@ -28,7 +122,21 @@ pub struct ServiceEpisodeUser {
     pub comments: Option<String>,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ServiceListEntry {
+    pub service_id: usize,
+    pub title: String,
+    pub user: ServiceEpisodeUser,
+}
+
+#[derive(Default, PartialEq, Eq, Debug)]
+pub struct ServiceListPage {
+    pub entries: Vec<ServiceListEntry>,
+    /// Offset of the next page, `None` on the last one
+    pub next_offset: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum EpisodeStatus {
     None,
     Watching,
@@ -38,7 +146,32 @@ pub enum EpisodeStatus {
     PlanToWatch,
 }
 
-#[derive(PartialEq, Debug)]
+impl EpisodeStatus {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Watching => "watching",
+            Self::Completed => "completed",
+            Self::OnHold => "on_hold",
+            Self::Dropped => "dropped",
+            Self::PlanToWatch => "plan_to_watch",
+        }
+    }
+
+    /// Unknown values become `EpisodeStatus::None`
+    pub fn parse(status: &str) -> Self {
+        match status.trim().to_lowercase().replace(' ', "_").as_str() {
+            "watching" => Self::Watching,
+            "completed" => Self::Completed,
+            "on_hold" => Self::OnHold,
+            "dropped" => Self::Dropped,
+            "plan_to_watch" => Self::PlanToWatch,
+            _ => Self::None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct ServiceEpisodeDetails {
     pub number: Option<usize>,
     pub title: Option<String>,
@@ -51,7 +184,194 @@ pub struct ServiceEpisodeDetails {
     pub recap: Option<bool>,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+/// What a service supports, the UI leaves out the rest
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Capabilities {
+    /// Shows get picked from the service's search and their titles come from it
+    pub search: bool,
+    /// Titles, scores and filler flags of single episodes
+    pub episode_metadata: bool,
+    pub score: bool,
+    pub status: bool,
+    /// Start and finish dates
+    pub dates: bool,
+    pub rewatch: bool,
+    /// When the next episodes of airing shows come out
+    pub schedule: bool,
+    /// Prequels, sequels and other shows of the same franchise
+    pub relations: bool,
+}
+
+impl Capabilities {
+    pub const ALL: Self = Self {
+        search: true,
+        episode_metadata: true,
+        score: true,
+        status: true,
+        dates: true,
+        rewatch: true,
+        schedule: true,
+        relations: true,
+    };
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SeasonName {
+    Winter,
+    Spring,
+    Summer,
+    Fall,
+}
+
+impl SeasonName {
+    pub const ALL: [Self; 4] = [Self::Winter, Self::Spring, Self::Summer, Self::Fall];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Winter => "Winter",
+            Self::Spring => "Spring",
+            Self::Summer => "Summer",
+            Self::Fall => "Fall",
+        }
+    }
+
+    /// Case insensitive, "autumn" is fall too
+    pub fn parse(season: &str) -> Option<Self> {
+        match season.trim().to_lowercase().as_str() {
+            "winter" => Some(Self::Winter),
+            "spring" => Some(Self::Spring),
+            "summer" => Some(Self::Summer),
+            "fall" | "autumn" => Some(Self::Fall),
+            _ => None,
+        }
+    }
+}
+
+/// Quarter of a year in which shows start airing, like Spring 2024
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Season {
+    pub name: SeasonName,
+    pub year: u32,
+}
+
+impl Season {
+    pub const fn new(name: SeasonName, year: u32) -> Self {
+        Self { name, year }
+    }
+
+    /// Winter starts in January, like on every service
+    pub const fn of_date(date: Date) -> Self {
+        let name = match date.month() {
+            Month::January | Month::February | Month::March => SeasonName::Winter,
+            Month::April | Month::May | Month::June => SeasonName::Spring,
+            Month::July | Month::August | Month::September => SeasonName::Summer,
+            Month::October | Month::November | Month::December => SeasonName::Fall,
+        };
+        Self::new(name, date.year().unsigned_abs())
+    }
+
+    pub fn current() -> Self {
+        Self::of_date(OffsetDateTime::now_utc().date())
+    }
+
+    pub const fn next(self) -> Self {
+        match self.name {
+            SeasonName::Winter => Self::new(SeasonName::Spring, self.year),
+            SeasonName::Spring => Self::new(SeasonName::Summer, self.year),
+            SeasonName::Summer => Self::new(SeasonName::Fall, self.year),
+            SeasonName::Fall => Self::new(SeasonName::Winter, self.year + 1),
+        }
+    }
+
+    pub const fn previous(self) -> Self {
+        match self.name {
+            SeasonName::Winter => Self::new(SeasonName::Fall, self.year.saturating_sub(1)),
+            SeasonName::Spring => Self::new(SeasonName::Winter, self.year),
+            SeasonName::Summer => Self::new(SeasonName::Spring, self.year),
+            SeasonName::Fall => Self::new(SeasonName::Summer, self.year),
+        }
+    }
+}
+
+impl fmt::Display for Season {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name.as_str(), self.year)
+    }
+}
+
+/// Next episode of a show that's still airing
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ServiceAiring {
+    pub service_id: usize,
+    /// Not every service knows which episode it is
+    pub episode: Option<usize>,
+    pub airs_at: OffsetDateTime,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum RelationType {
+    Prequel,
+    Sequel,
+    SideStory,
+    ParentStory,
+    AlternativeVersion,
+    SpinOff,
+    Summary,
+    /// Adaptations, shared characters and anything else outside the franchise
+    Other,
+}
+
+impl RelationType {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Prequel => "Prequel",
+            Self::Sequel => "Sequel",
+            Self::SideStory => "Side story",
+            Self::ParentStory => "Parent story",
+            Self::AlternativeVersion => "Alternative version",
+            Self::SpinOff => "Spin-off",
+            Self::Summary => "Summary",
+            Self::Other => "Other",
+        }
+    }
+
+    /// Understands the names used by every service, like "SIDE_STORY", "Spin-off" or "spinoff"
+    pub fn parse(relation: &str) -> Self {
+        match relation.trim().to_lowercase().replace([' ', '-'], "_").as_str() {
+            "prequel" => Self::Prequel,
+            "sequel" => Self::Sequel,
+            "side_story" => Self::SideStory,
+            "parent_story" | "parent" | "full_story" => Self::ParentStory,
+            "alternative_version" | "alternative_setting" | "alternative" => {
+                Self::AlternativeVersion
+            }
+            "spin_off" | "spinoff" => Self::SpinOff,
+            "summary" => Self::Summary,
+            _ => Self::Other,
+        }
+    }
+
+    /// Shows related this way get grouped together in the list
+    pub const fn same_franchise(self) -> bool {
+        !matches!(self, Self::Other)
+    }
+}
+
+/// Show related to another one, like its sequel
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ServiceRelation {
+    pub relation: RelationType,
+    pub show: ServiceTitle,
+}
+
+/// Change that didn't reach one of the services
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ServiceFailure {
+    pub service: ServiceType,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
 pub struct AlternativeTitles {
     pub synonyms: Vec<String>,
     pub languages: HashMap<String, String>,
@@ -64,8 +384,15 @@ pub trait Service: Send + Sync {
     fn login(&mut self) -> impl std::future::Future<Output = Result<(), String>> + Send;
     fn auth(&mut self) -> impl std::future::Future<Output = ()> + Send;
     fn init_show(&mut self, id: usize) -> impl std::future::Future<Output = Result<(), String>> + Send;
-    fn search_title(&mut self, potential_title: &str) -> impl std::future::Future<Output = Result<Vec<ServiceTitle>, String>> + Send;
+    /// One page of search results, starting at `offset`
+    fn search_title(
+        &mut self,
+        potential_title: &str,
+        offset: usize,
+    ) -> impl std::future::Future<Output = Result<ServiceSearchPage, String>> + Send;
     fn get_title(&mut self, id: usize) -> impl std::future::Future<Output = Result<String, String>> + Send;
+    /// Title, type, year and episode count of the show, errors when the id doesn't exist
+    fn get_title_details(&mut self, id: usize) -> impl std::future::Future<Output = Result<ServiceTitle, String>> + Send;
     fn get_alternative_titles(
         &mut self,
         id: usize,
@@ -80,9 +407,74 @@ pub trait Service: Send + Sync {
         &mut self,
         id: usize,
     ) -> impl std::future::Future<Output = Result<Option<ServiceEpisodeUser>, String>> + Send;
-    /// Returns actual progress set on the service
-    fn set_progress(&mut self, id: usize, progress: usize) -> impl std::future::Future<Output = Result<usize, String>> + Send;
+    /// Details for many entries at once, `progress` advances after each of them
+    fn get_user_entries_details(
+        &mut self,
+        ids: &[usize],
+        progress: &SyncProgress,
+    ) -> impl std::future::Future<Output = Result<RemoteEntries, String>> + Send;
+    /// One page of the user's list on the service, starting at `offset`
+    fn get_user_list(
+        &mut self,
+        offset: usize,
+    ) -> impl std::future::Future<Output = Result<ServiceListPage, String>> + Send;
+    /// Only fields that are set get updated, a score of 0 and empty dates clear them.
+    /// Progress is handled by `set_progress`
+    fn update_user_entry(
+        &mut self,
+        id: usize,
+        entry: &ServiceEpisodeUser,
+    ) -> impl std::future::Future<Output = Result<(), String>> + Send;
+    /// Returns actual progress set on the service, the show is completed once `episode_count` is reached,
+    /// 0 when the count isn't known yet and None to look it up
+    fn set_progress(
+        &mut self,
+        id: usize,
+        progress: usize,
+        episode_count: Option<usize>,
+    ) -> impl std::future::Future<Output = Result<usize, String>> + Send;
     fn get_service_type(&self) -> ServiceType;
+    fn capabilities(&self) -> Capabilities;
     fn get_url(&self) -> Option<String>;
     fn is_logged_in(&self) -> bool;
+    /// Name of the account on the service, None when not logged in
+    fn get_username(&mut self) -> impl std::future::Future<Output = Result<Option<String>, String>> + Send;
+    /// Forgets the tokens, `login` can then be used with another account
+    fn logout(&mut self) -> impl std::future::Future<Output = Result<(), String>> + Send;
+    /// One page of shows that started airing in the season, starting at `offset`
+    fn get_season(
+        &mut self,
+        season: Season,
+        offset: usize,
+    ) -> impl std::future::Future<Output = Result<ServiceSearchPage, String>> + Send;
+    /// Next episodes of the shows, ones that aren't airing or have no known date are left out
+    fn get_airing_schedule(
+        &mut self,
+        ids: &[usize],
+    ) -> impl std::future::Future<Output = Result<Vec<ServiceAiring>, String>> + Send;
+    /// Anime related to the show, like its prequel and sequel
+    fn get_relations(
+        &mut self,
+        id: usize,
+    ) -> impl std::future::Future<Output = Result<Vec<ServiceRelation>, String>> + Send;
+    /// Services that log in with a username and password instead of a browser
+    fn needs_credentials(&self) -> bool {
+        false
+    }
+    /// Used by the next `login`
+    fn set_credentials(&mut self, _username: &str, _password: &str) {}
+    /// Port of the local server the browser gets redirected to, used by the next `auth` and `login`
+    fn set_redirect_port(&mut self, _port: u16) {}
+    /// Logs in with the address the browser ended up on, for when the redirect can't reach lma
+    fn login_with_redirect(&mut self, _redirect_url: &str) -> impl std::future::Future<Output = Result<(), String>> + Send {
+        async { Err(String::from("This service can't log in with a pasted address")) }
+    }
+    /// Failures of services other than the main one since the last call
+    fn take_failures(&mut self) -> Vec<ServiceFailure> {
+        Vec::new()
+    }
+    /// Makes the next metadata request for the show go to the service
+    fn invalidate_cache(&mut self, _id: usize) -> impl std::future::Future<Output = Result<(), String>> + Send {
+        async { Ok(()) }
+    }
 }
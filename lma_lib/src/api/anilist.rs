@@ -0,0 +1,1123 @@
+use super::oauth;
+use super::rate_limit::TokenBucket;
+use crate::{
+    AlternativeTitles, Capabilities, Credentials, EpisodeStatus, MediaType, RelationType,
+    RemoteEntries, Season, SeasonName, Service, ServiceAiring, ServiceEpisodeDetails,
+    ServiceEpisodeUser, ServiceListEntry, ServiceListPage, ServiceRelation, ServiceSearchPage,
+    ServiceTitle, ServiceType, SyncProgress,
+};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use time::OffsetDateTime;
+
+const API_URL: &str = "https://graphql.anilist.co";
+const AUTHORIZE_URL: &str = "https://anilist.co/api/v2/oauth/authorize";
+const CLIENT_ID_VAR: &str = "LMA_ANILIST_CLIENT_ID";
+/// Biggest page AniList allows
+const PAGE_SIZE: usize = 50;
+const SEARCH_PAGE_SIZE: usize = 20;
+/// AniList allows 90 requests per minute
+const RATE_LIMIT_BURST: u32 = 5;
+const RATE_LIMIT_PER_SECOND: f64 = 1.5;
+
+pub struct AniList {
+    client: reqwest::Client,
+    api_url: String,
+    token: Option<String>,
+    token_store: Credentials,
+    viewer: Option<Viewer>,
+    client_id: Option<String>,
+    url: Option<String>,
+    /// Sent with the login and expected back with the token
+    state: String,
+    redirect_port: u16,
+    rate_limiter: TokenBucket,
+}
+
+impl Service for AniList {
+    async fn new(cache_dir: PathBuf) -> Result<Self, String> {
+        Self::with_credentials(Credentials::plain(cache_dir, ServiceType::AniList.as_str())).await
+    }
+    async fn login(&mut self) -> Result<(), String> {
+        // no redirect is coming without a client
+        self.client_id()?;
+        let listener = oauth::bind(self.redirect_port).await?;
+        let params = oauth::wait_for_redirect(listener, "access_token", &self.state).await?;
+        self.save_token(&params)
+    }
+    async fn login_with_redirect(&mut self, redirect_url: &str) -> Result<(), String> {
+        let params = oauth::parse_redirect_url(redirect_url, "access_token", &self.state)?;
+        self.save_token(&params)
+    }
+    async fn auth(&mut self) {
+        self.state = oauth::new_state();
+        self.url = match (&self.token, self.client_id()) {
+            (None, Ok(client_id)) => Some(format!(
+                "{AUTHORIZE_URL}?client_id={client_id}&response_type=token&state={}",
+                self.state
+            )),
+            _ => None,
+        }
+    }
+    async fn init_show(&mut self, id: usize) -> Result<(), String> {
+        if self.get_user_entry_details(id).await?.is_none() {
+            self.save_entry(json!({ "mediaId": id, "status": "PLANNING" }))
+                .await?;
+        }
+        Ok(())
+    }
+    async fn search_title(
+        &mut self,
+        potential_title: &str,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        let data: PageData<SearchMedia> = self
+            .query(
+                "query ($search: String, $page: Int, $perPage: Int) {
+                    Page(page: $page, perPage: $perPage) {
+                        pageInfo { hasNextPage }
+                        media(search: $search, type: ANIME) { id title { romaji } format seasonYear episodes isAdult }
+                    }
+                }",
+                json!({
+                    "search": potential_title,
+                    "page": offset / SEARCH_PAGE_SIZE + 1,
+                    "perPage": SEARCH_PAGE_SIZE,
+                }),
+            )
+            .await
+            .map_err(|err| format!("AniList search result: {err}"))?;
+        let next_offset = data
+            .page
+            .page_info
+            .is_some_and(|info| info.has_next_page)
+            .then_some(offset + SEARCH_PAGE_SIZE);
+        let titles = data
+            .page
+            .media
+            .into_iter()
+            .map(SearchMedia::into_service_title)
+            .collect();
+        Ok(ServiceSearchPage {
+            titles,
+            next_offset,
+        })
+    }
+    async fn get_title(&mut self, id: usize) -> Result<String, String> {
+        Ok(self
+            .get_media(id)
+            .await
+            .map_err(|err| format!("Anime title: {err}"))?
+            .title
+            .romaji
+            .unwrap_or_default())
+    }
+    async fn get_title_details(&mut self, id: usize) -> Result<ServiceTitle, String> {
+        let data: MediaData<SearchMedia> = self
+            .query(
+                "query ($id: Int) {
+                    Media(id: $id, type: ANIME) { id title { romaji } format seasonYear episodes isAdult }
+                }",
+                json!({ "id": id }),
+            )
+            .await
+            .map_err(|err| format!("Anime details: {err}"))?;
+        Ok(data.media.into_service_title())
+    }
+    async fn get_alternative_titles(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<AlternativeTitles>, String> {
+        let media = self
+            .get_media(id)
+            .await
+            .map_err(|err| format!("Alternative anime titles: {err}"))?;
+        let languages = [("en", media.title.english), ("ja", media.title.native)]
+            .into_iter()
+            .filter_map(|(language, title)| title.map(|title| (language.to_owned(), title)))
+            .collect();
+        Ok(Some(AlternativeTitles {
+            synonyms: media.synonyms,
+            languages,
+        }))
+    }
+    async fn get_episodes(
+        &mut self,
+        _id: usize,
+        _precise_score: bool,
+    ) -> Result<Vec<ServiceEpisodeDetails>, String> {
+        // AniList has no per episode data
+        Ok(Vec::new())
+    }
+    async fn get_episode_count(&mut self, id: usize) -> Result<Option<usize>, String> {
+        Ok(self
+            .get_media(id)
+            .await
+            .map_err(|err| format!("Anime episode count: {err}"))?
+            .episodes)
+    }
+    async fn get_user_entry_details(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<ServiceEpisodeUser>, String> {
+        let data: MediaData<UserMedia> = self
+            .query(
+                &format!(
+                    "query ($id: Int) {{ Media(id: $id, type: ANIME) {{ mediaListEntry {{ {LIST_ENTRY_FIELDS} }} }} }}"
+                ),
+                json!({ "id": id }),
+            )
+            .await
+            .map_err(|err| format!("Anime details: {err}"))?;
+        Ok(data
+            .media
+            .media_list_entry
+            .map(ListEntry::into_service_user))
+    }
+    async fn get_user_entries_details(
+        &mut self,
+        ids: &[usize],
+        progress: &SyncProgress,
+    ) -> Result<RemoteEntries, String> {
+        let user_id = self.viewer_id().await?;
+        let mut entries = RemoteEntries::new();
+        for chunk in ids.chunks(PAGE_SIZE) {
+            let data: PageData<ListEntry> = self
+                .query(
+                    &format!(
+                        "query ($userId: Int, $ids: [Int], $perPage: Int) {{
+                            Page(perPage: $perPage) {{ mediaList(userId: $userId, type: ANIME, mediaId_in: $ids) {{ mediaId {LIST_ENTRY_FIELDS} }} }}
+                        }}"
+                    ),
+                    json!({ "userId": user_id, "ids": chunk, "perPage": PAGE_SIZE }),
+                )
+                .await
+                .map_err(|err| format!("User's list: {err}"))?;
+            for entry in data.page.media_list {
+                if let Some(media_id) = entry.media_id {
+                    entries.insert(media_id, entry.into_service_user());
+                }
+            }
+            chunk.iter().for_each(|id| progress.finish_one(*id));
+        }
+        Ok(entries)
+    }
+    async fn get_user_list(&mut self, offset: usize) -> Result<ServiceListPage, String> {
+        let user_id = self.viewer_id().await?;
+        let data: PageData<ListEntry> = self
+            .query(
+                &format!(
+                    "query ($userId: Int, $page: Int, $perPage: Int) {{
+                        Page(page: $page, perPage: $perPage) {{
+                            pageInfo {{ hasNextPage }}
+                            mediaList(userId: $userId, type: ANIME) {{ mediaId media {{ title {{ romaji }} }} {LIST_ENTRY_FIELDS} }}
+                        }}
+                    }}"
+                ),
+                json!({ "userId": user_id, "page": offset / PAGE_SIZE + 1, "perPage": PAGE_SIZE }),
+            )
+            .await
+            .map_err(|err| format!("User's list: {err}"))?;
+        let next_offset = data
+            .page
+            .page_info
+            .is_some_and(|info| info.has_next_page)
+            .then_some(offset + PAGE_SIZE);
+        let entries = data
+            .page
+            .media_list
+            .into_iter()
+            .filter_map(|mut entry| {
+                let service_id = entry.media_id?;
+                let title = entry.media.take()?.title.romaji?;
+                Some(ServiceListEntry {
+                    service_id,
+                    title,
+                    user: entry.into_service_user(),
+                })
+            })
+            .collect();
+        Ok(ServiceListPage {
+            entries,
+            next_offset,
+        })
+    }
+    async fn update_user_entry(
+        &mut self,
+        id: usize,
+        entry: &ServiceEpisodeUser,
+    ) -> Result<(), String> {
+        let mut variables = Map::new();
+        variables.insert(String::from("mediaId"), json!(id));
+        if let Some(status) = entry.status.and_then(to_anilist_status) {
+            variables.insert(String::from("status"), json!(status));
+        }
+        if let Some(score) = entry.score {
+            variables.insert(String::from("score"), json!(score));
+        }
+        if let Some(start_date) = &entry.start_date {
+            variables.insert(String::from("startedAt"), to_fuzzy_date(start_date));
+        }
+        if let Some(finish_date) = &entry.finish_date {
+            variables.insert(String::from("completedAt"), to_fuzzy_date(finish_date));
+        }
+        self.save_entry(Value::Object(variables)).await?;
+        Ok(())
+    }
+    async fn set_progress(
+        &mut self,
+        id: usize,
+        progress: usize,
+        episode_count: Option<usize>,
+    ) -> Result<usize, String> {
+        let current = self.get_user_entry_details(id).await?.unwrap_or_default();
+        let episode_count = match episode_count {
+            Some(episode_count) => Some(episode_count),
+            None => self.get_episode_count(id).await?,
+        }
+        .filter(|count| *count != 0)
+        .unwrap_or(usize::MAX);
+        let today = OffsetDateTime::now_utc().date().to_string();
+        let mut variables = json!({ "mediaId": id, "progress": progress });
+        if progress == 0 {
+            variables["status"] = json!("PLANNING");
+        } else if progress >= episode_count {
+            variables["status"] = json!("COMPLETED");
+            if current.finish_date.is_none() {
+                variables["completedAt"] = to_fuzzy_date(&today);
+            }
+        } else {
+            variables["status"] = json!("CURRENT");
+        }
+        if current.start_date.is_none() && progress == 1 {
+            variables["startedAt"] = to_fuzzy_date(&today);
+        }
+        let saved = self.save_entry(variables).await?;
+        Ok(saved.progress.unwrap_or(progress))
+    }
+    fn get_service_type(&self) -> ServiceType {
+        ServiceType::AniList
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            episode_metadata: false,
+            ..Capabilities::ALL
+        }
+    }
+    fn get_url(&self) -> Option<String> {
+        self.url.clone()
+    }
+    fn is_logged_in(&self) -> bool {
+        self.token.is_some()
+    }
+    async fn get_username(&mut self) -> Result<Option<String>, String> {
+        if !self.is_logged_in() {
+            return Ok(None);
+        }
+        Ok(Some(self.viewer().await?.name))
+    }
+    async fn logout(&mut self) -> Result<(), String> {
+        // AniList tokens can't be revoked, they only expire
+        self.token_store.delete()?;
+        self.token = None;
+        self.viewer = None;
+        self.url = Some(String::new());
+        Ok(())
+    }
+    async fn get_season(
+        &mut self,
+        season: Season,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        let data: PageData<SearchMedia> = self
+            .query(
+                "query ($season: MediaSeason, $seasonYear: Int, $page: Int, $perPage: Int) {
+                    Page(page: $page, perPage: $perPage) {
+                        pageInfo { hasNextPage }
+                        media(season: $season, seasonYear: $seasonYear, type: ANIME, sort: POPULARITY_DESC) {
+                            id title { romaji } format seasonYear episodes isAdult
+                        }
+                    }
+                }",
+                json!({
+                    "season": to_anilist_season(season.name),
+                    "seasonYear": season.year,
+                    "page": offset / SEARCH_PAGE_SIZE + 1,
+                    "perPage": SEARCH_PAGE_SIZE,
+                }),
+            )
+            .await
+            .map_err(|err| format!("Season {season}: {err}"))?;
+        let next_offset = data
+            .page
+            .page_info
+            .is_some_and(|info| info.has_next_page)
+            .then_some(offset + SEARCH_PAGE_SIZE);
+        let titles = data
+            .page
+            .media
+            .into_iter()
+            .map(SearchMedia::into_service_title)
+            .collect();
+        Ok(ServiceSearchPage {
+            titles,
+            next_offset,
+        })
+    }
+    async fn get_airing_schedule(&mut self, ids: &[usize]) -> Result<Vec<ServiceAiring>, String> {
+        let mut schedule = Vec::new();
+        for chunk in ids.chunks(PAGE_SIZE) {
+            let data: PageData<AiringMedia> = self
+                .query(
+                    "query ($ids: [Int], $perPage: Int) {
+                        Page(perPage: $perPage) { media(id_in: $ids, type: ANIME) { id nextAiringEpisode { episode airingAt } } }
+                    }",
+                    json!({ "ids": chunk, "perPage": PAGE_SIZE }),
+                )
+                .await
+                .map_err(|err| format!("Airing schedule: {err}"))?;
+            schedule.extend(data.page.media.into_iter().filter_map(|media| {
+                let next = media.next_airing_episode?;
+                Some(ServiceAiring {
+                    service_id: media.id,
+                    episode: next.episode,
+                    airs_at: OffsetDateTime::from_unix_timestamp(next.airing_at).ok()?,
+                })
+            }));
+        }
+        Ok(schedule)
+    }
+    async fn get_relations(&mut self, id: usize) -> Result<Vec<ServiceRelation>, String> {
+        let data: MediaData<RelationsMedia> = self
+            .query(
+                "query ($id: Int) {
+                    Media(id: $id, type: ANIME) { relations { edges {
+                        relationType node { id type title { romaji } format seasonYear episodes isAdult }
+                    } } }
+                }",
+                json!({ "id": id }),
+            )
+            .await
+            .map_err(|err| format!("Related anime: {err}"))?;
+        Ok(data
+            .media
+            .relations
+            .edges
+            .into_iter()
+            // manga adaptations are related too
+            .filter(|edge| edge.node.kind.as_deref() == Some("ANIME"))
+            .map(|edge| ServiceRelation {
+                relation: RelationType::parse(edge.relation_type.as_deref().unwrap_or_default()),
+                show: edge.node.media.into_service_title(),
+            })
+            .collect())
+    }
+    fn set_redirect_port(&mut self, port: u16) {
+        self.redirect_port = port;
+    }
+}
+
+impl AniList {
+    /// Keeps the token in `token_store` instead of the data directory
+    pub async fn with_credentials(token_store: Credentials) -> Result<Self, String> {
+        let token = token_store
+            .load()?
+            .map(|token| token.trim().to_owned())
+            .filter(|token| !token.is_empty());
+        Ok(Self::build(API_URL, token, token_store))
+    }
+
+    fn build(api_url: &str, token: Option<String>, token_store: Credentials) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_url: api_url.to_owned(),
+            token,
+            token_store,
+            viewer: None,
+            client_id: env::var(CLIENT_ID_VAR)
+                .ok()
+                .filter(|client_id| !client_id.trim().is_empty()),
+            url: Some(String::new()),
+            state: String::new(),
+            redirect_port: oauth::DEFAULT_REDIRECT_PORT,
+            rate_limiter: TokenBucket::new(RATE_LIMIT_BURST, RATE_LIMIT_PER_SECOND),
+        }
+    }
+
+    fn client_id(&self) -> Result<&str, String> {
+        self.client_id
+            .as_deref()
+            .ok_or_else(|| format!("Set {CLIENT_ID_VAR} to the id of your AniList API client"))
+    }
+
+    fn save_token(&mut self, params: &HashMap<String, String>) -> Result<(), String> {
+        let token = params
+            .get("access_token")
+            .cloned()
+            .ok_or("AniList didn't return a token")?;
+        self.token_store.save(&token)?;
+        self.token = Some(token);
+        self.viewer = None;
+        self.url = None;
+        Ok(())
+    }
+
+    async fn query<R: DeserializeOwned>(&self, query: &str, variables: Value) -> Result<R, String> {
+        self.rate_limiter.acquire().await;
+        let mut request = self
+            .client
+            .post(&self.api_url)
+            .json(&json!({ "query": query, "variables": variables }));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response: GraphQLResponse<R> = request
+            .send()
+            .await
+            .map_err(|err| err.to_string())?
+            .json()
+            .await
+            .map_err(|err| err.to_string())?;
+        if let Some(error) = response.errors.into_iter().next() {
+            return Err(error.message);
+        }
+        response.data.ok_or_else(|| String::from("Empty response"))
+    }
+
+    async fn get_media(&self, id: usize) -> Result<Media, String> {
+        let data: MediaData<Media> = self
+            .query(
+                "query ($id: Int) {
+                    Media(id: $id, type: ANIME) { title { romaji english native } synonyms episodes }
+                }",
+                json!({ "id": id }),
+            )
+            .await?;
+        Ok(data.media)
+    }
+
+    async fn save_entry(&self, variables: Value) -> Result<ListEntry, String> {
+        let data: SavedEntry = self
+            .query(
+                &format!(
+                    "mutation ($mediaId: Int, $status: MediaListStatus, $score: Float, $progress: Int,
+                        $startedAt: FuzzyDateInput, $completedAt: FuzzyDateInput) {{
+                        SaveMediaListEntry(mediaId: $mediaId, status: $status, score: $score, progress: $progress,
+                            startedAt: $startedAt, completedAt: $completedAt) {{ {LIST_ENTRY_FIELDS} }}
+                    }}"
+                ),
+                variables,
+            )
+            .await
+            .map_err(|err| format!("Update user's list: {err}"))?;
+        Ok(data.entry)
+    }
+
+    async fn viewer_id(&mut self) -> Result<usize, String> {
+        Ok(self.viewer().await?.id)
+    }
+
+    async fn viewer(&mut self) -> Result<Viewer, String> {
+        if let Some(viewer) = &self.viewer {
+            return Ok(viewer.clone());
+        }
+        let data: ViewerData = self
+            .query("query { Viewer { id name } }", json!({}))
+            .await
+            .map_err(|err| format!("AniList user: {err}"))?;
+        self.viewer = Some(data.viewer.clone());
+        Ok(data.viewer)
+    }
+}
+
+const LIST_ENTRY_FIELDS: &str = "status progress score(format: POINT_10) updatedAt repeat notes \
+    startedAt { year month day } completedAt { year month day }";
+
+#[derive(Deserialize)]
+struct GraphQLResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQLError>,
+}
+
+#[derive(Deserialize)]
+struct GraphQLError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct MediaData<T> {
+    #[serde(rename = "Media")]
+    media: T,
+}
+
+#[derive(Deserialize)]
+struct PageData<T> {
+    #[serde(rename = "Page")]
+    page: Page<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Page<T> {
+    page_info: Option<PageInfo>,
+    #[serde(default = "Vec::new")]
+    media: Vec<T>,
+    #[serde(default = "Vec::new")]
+    media_list: Vec<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+}
+
+#[derive(Deserialize)]
+struct ViewerData {
+    #[serde(rename = "Viewer")]
+    viewer: Viewer,
+}
+
+#[derive(Deserialize, Clone)]
+struct Viewer {
+    id: usize,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct SavedEntry {
+    #[serde(rename = "SaveMediaListEntry")]
+    entry: ListEntry,
+}
+
+#[derive(Deserialize, Default)]
+struct Title {
+    romaji: Option<String>,
+    english: Option<String>,
+    native: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchMedia {
+    id: usize,
+    title: Title,
+    format: Option<String>,
+    season_year: Option<u32>,
+    episodes: Option<usize>,
+    #[serde(default)]
+    is_adult: bool,
+}
+
+impl SearchMedia {
+    fn into_service_title(self) -> ServiceTitle {
+        ServiceTitle {
+            service_id: self.id,
+            title: self.title.romaji.unwrap_or_default(),
+            media_type: self.format.as_deref().map(MediaType::parse),
+            year: self.season_year,
+            episodes: self.episodes,
+            nsfw: self.is_adult,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AiringMedia {
+    id: usize,
+    next_airing_episode: Option<AiringEpisode>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AiringEpisode {
+    episode: Option<usize>,
+    /// Unix timestamp
+    airing_at: i64,
+}
+
+#[derive(Deserialize)]
+struct RelationsMedia {
+    relations: Relations,
+}
+
+#[derive(Deserialize)]
+struct Relations {
+    #[serde(default)]
+    edges: Vec<RelationEdge>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RelationEdge {
+    relation_type: Option<String>,
+    node: RelationNode,
+}
+
+#[derive(Deserialize)]
+struct RelationNode {
+    /// ANIME or MANGA
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    #[serde(flatten)]
+    media: SearchMedia,
+}
+
+#[derive(Deserialize)]
+struct Media {
+    title: Title,
+    #[serde(default)]
+    synonyms: Vec<String>,
+    episodes: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct ListMedia {
+    title: Title,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserMedia {
+    media_list_entry: Option<ListEntry>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct ListEntry {
+    media_id: Option<usize>,
+    media: Option<ListMedia>,
+    status: Option<String>,
+    progress: Option<usize>,
+    score: Option<f32>,
+    updated_at: Option<i64>,
+    repeat: Option<usize>,
+    notes: Option<String>,
+    started_at: Option<FuzzyDate>,
+    completed_at: Option<FuzzyDate>,
+}
+
+impl ListEntry {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn into_service_user(self) -> ServiceEpisodeUser {
+        ServiceEpisodeUser {
+            is_rewatching: self.status.as_deref().map(|status| status == "REPEATING"),
+            status: self.status.as_deref().map(to_episode_status),
+            progress: self.progress,
+            // POINT_10 scores are whole numbers, 0 means not scored
+            score: self
+                .score
+                .map(|score| score.round() as u8)
+                .filter(|score| *score != 0),
+            rewatch_count: self.repeat,
+            updated_at: self
+                .updated_at
+                .and_then(|timestamp| OffsetDateTime::from_unix_timestamp(timestamp).ok())
+                .and_then(|date| {
+                    date.format(&time::format_description::well_known::Rfc3339)
+                        .ok()
+                }),
+            start_date: self.started_at.and_then(FuzzyDate::into_date),
+            finish_date: self.completed_at.and_then(FuzzyDate::into_date),
+            comments: self.notes,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FuzzyDate {
+    year: Option<u16>,
+    month: Option<u8>,
+    day: Option<u8>,
+}
+
+impl FuzzyDate {
+    /// Partial dates can't be represented as YYYY-MM-DD
+    fn into_date(self) -> Option<String> {
+        Some(format!(
+            "{:04}-{:02}-{:02}",
+            self.year?, self.month?, self.day?
+        ))
+    }
+}
+
+fn to_fuzzy_date(date: &str) -> Value {
+    let mut parts = date.splitn(3, '-').map(|part| part.parse::<u16>().ok());
+    json!({
+        "year": parts.next().flatten(),
+        "month": parts.next().flatten(),
+        "day": parts.next().flatten(),
+    })
+}
+
+fn to_episode_status(status: &str) -> EpisodeStatus {
+    match status {
+        "CURRENT" | "REPEATING" => EpisodeStatus::Watching,
+        "COMPLETED" => EpisodeStatus::Completed,
+        "PAUSED" => EpisodeStatus::OnHold,
+        "DROPPED" => EpisodeStatus::Dropped,
+        "PLANNING" => EpisodeStatus::PlanToWatch,
+        _ => EpisodeStatus::None,
+    }
+}
+
+const fn to_anilist_season(season: SeasonName) -> &'static str {
+    match season {
+        SeasonName::Winter => "WINTER",
+        SeasonName::Spring => "SPRING",
+        SeasonName::Summer => "SUMMER",
+        SeasonName::Fall => "FALL",
+    }
+}
+
+const fn to_anilist_status(status: EpisodeStatus) -> Option<&'static str> {
+    match status {
+        EpisodeStatus::Watching => Some("CURRENT"),
+        EpisodeStatus::Completed => Some("COMPLETED"),
+        EpisodeStatus::OnHold => Some("PAUSED"),
+        EpisodeStatus::Dropped => Some("DROPPED"),
+        EpisodeStatus::PlanToWatch => Some("PLANNING"),
+        EpisodeStatus::None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server, ServerGuard};
+
+    #[tokio::test]
+    async fn test_search() {
+        let mut server = Server::new_async().await;
+        let mock = mock_query(
+            &mut server,
+            "search",
+            json!({ "Page": { "pageInfo": { "hasNextPage": true }, "media": [
+                { "id": 21, "title": { "romaji": "One Piece" }, "format": "TV", "seasonYear": 1999, "episodes": null, "isAdult": false },
+                { "id": 459, "title": { "romaji": "One Piece: Movie 1" }, "format": "MOVIE", "seasonYear": 2000, "episodes": 1, "isAdult": false },
+            ] } }),
+        )
+        .await;
+        let mut client = generate_test_client(&server, None);
+        let result = client.search_title("One Piece", 0).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(result.next_offset, Some(20));
+        let result = result.titles;
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[1].service_id, 459);
+        assert_eq!(result[1].media_type, Some(MediaType::Movie));
+        assert_eq!(result[0].title, "One Piece");
+        assert_eq!(result[0].year, Some(1999));
+    }
+
+    #[tokio::test]
+    async fn test_season() {
+        let mut server = Server::new_async().await;
+        let fixture = include_str!("../../fixtures/anilist/season.json");
+        let mock = mock_query(
+            &mut server,
+            "season: \\$season",
+            serde_json::from_str(fixture).unwrap(),
+        )
+        .await;
+        let mut client = generate_test_client(&server, None);
+        let season = Season::new(SeasonName::Fall, 2023);
+        let result = client.get_season(season, 0).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(result.next_offset, Some(20));
+        assert_eq!(result.titles.len(), 2);
+        assert_eq!(result.titles[0].title, "Sousou no Frieren");
+        assert_eq!(result.titles[1].episodes, Some(24));
+    }
+
+    #[tokio::test]
+    async fn test_airing_schedule() {
+        let mut server = Server::new_async().await;
+        let fixture = include_str!("../../fixtures/anilist/airing_schedule.json");
+        mock_query(
+            &mut server,
+            "nextAiringEpisode",
+            serde_json::from_str(fixture).unwrap(),
+        )
+        .await;
+        let mut client = generate_test_client(&server, None);
+        let result = client.get_airing_schedule(&[154_587, 21]).await;
+        assert_eq!(
+            result,
+            Ok(vec![ServiceAiring {
+                service_id: 154_587,
+                episode: Some(8),
+                airs_at: OffsetDateTime::from_unix_timestamp(1_698_418_800).unwrap(),
+            }]),
+            "Finished shows are left out"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_relations() {
+        let mut server = Server::new_async().await;
+        let fixture = include_str!("../../fixtures/anilist/relations.json");
+        mock_query(
+            &mut server,
+            "relationType",
+            serde_json::from_str(fixture).unwrap(),
+        )
+        .await;
+        let mut client = generate_test_client(&server, None);
+        let result = client.get_relations(101_348).await.unwrap();
+        assert_eq!(result.len(), 2, "The manga is left out");
+        assert_eq!(result[0].relation, RelationType::Sequel);
+        assert_eq!(result[0].show.service_id, 136_430);
+        assert_eq!(result[0].show.title, "Vinland Saga Season 2");
+        assert_eq!(result[1].relation, RelationType::SideStory);
+        assert_eq!(result[1].show.media_type, Some(MediaType::Special));
+    }
+
+    #[tokio::test]
+    async fn test_get_episode_count() {
+        let mut server = Server::new_async().await;
+        mock_query(
+            &mut server,
+            "episodes",
+            json!({ "Media": { "title": { "romaji": "Diamond no Ace: Second Season" }, "episodes": 51 } }),
+        )
+        .await;
+        let mut client = generate_test_client(&server, None);
+        assert_eq!(client.get_episode_count(30230).await, Ok(Some(51)));
+        assert_eq!(
+            client.get_title(30230).await.as_deref(),
+            Ok("Diamond no Ace: Second Season")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_title_details() {
+        let mut server = Server::new_async().await;
+        mock_query(
+            &mut server,
+            "isAdult",
+            json!({ "Media": {
+                "id": 30230,
+                "title": { "romaji": "Diamond no Ace: Second Season" },
+                "format": "TV",
+                "seasonYear": 2015,
+                "episodes": 51,
+                "isAdult": false
+            } }),
+        )
+        .await;
+        let mut client = generate_test_client(&server, None);
+        let details = client.get_title_details(30230).await.unwrap();
+        assert_eq!(details.media_type, Some(MediaType::TV));
+        assert_eq!(details.year, Some(2015));
+        assert_eq!(details.episodes, Some(51));
+    }
+
+    #[tokio::test]
+    async fn test_get_user_entry_details() {
+        let mut server = Server::new_async().await;
+        mock_query(
+            &mut server,
+            "mediaListEntry",
+            json!({ "Media": { "mediaListEntry": {
+                "status": "PAUSED",
+                "progress": 12,
+                "score": 7.0,
+                "updatedAt": 1_510_429_882,
+                "startedAt": { "year": 2023, "month": 1, "day": 5 },
+                "completedAt": { "year": null, "month": null, "day": null },
+            } } }),
+        )
+        .await;
+        let mut client = generate_test_client(&server, Some("token"));
+        let entry = client.get_user_entry_details(30230).await.unwrap().unwrap();
+        assert_eq!(entry.status, Some(EpisodeStatus::OnHold));
+        assert_eq!(entry.progress, Some(12));
+        assert_eq!(entry.score, Some(7));
+        assert_eq!(entry.start_date.as_deref(), Some("2023-01-05"));
+        assert_eq!(entry.finish_date, None);
+        assert_eq!(entry.updated_at.as_deref(), Some("2017-11-11T19:51:22Z"));
+    }
+
+    #[tokio::test]
+    async fn test_not_on_list() {
+        let mut server = Server::new_async().await;
+        mock_query(
+            &mut server,
+            "mediaListEntry",
+            json!({ "Media": { "mediaListEntry": null } }),
+        )
+        .await;
+        let mut client = generate_test_client(&server, Some("token"));
+        assert_eq!(client.get_user_entry_details(21).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_set_progress() {
+        let mut server = Server::new_async().await;
+        mock_query(
+            &mut server,
+            "mediaListEntry",
+            json!({ "Media": { "mediaListEntry": { "status": "CURRENT", "progress": 50 } } }),
+        )
+        .await;
+        mock_query(
+            &mut server,
+            "episodes",
+            json!({ "Media": { "title": {}, "episodes": 51 } }),
+        )
+        .await;
+        let save = server
+            .mock("POST", "/")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex(String::from("SaveMediaListEntry")),
+                Matcher::Regex(String::from("\"status\":\"COMPLETED\"")),
+            ]))
+            .with_body(
+                json!({ "data": { "SaveMediaListEntry": { "status": "COMPLETED", "progress": 51 } } })
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server, Some("token"));
+        assert_eq!(client.set_progress(30230, 51, None).await, Ok(51));
+        save.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_graphql_error() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/")
+            .with_body(json!({ "data": null, "errors": [{ "message": "Not Found." }] }).to_string())
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server, None);
+        let result = client.get_title(1).await;
+        assert_eq!(result, Err(String::from("Anime title: Not Found.")));
+    }
+
+    #[tokio::test]
+    async fn test_get_url() {
+        let mut client = generate_test_client(&Server::new_async().await, None);
+        client.auth().await;
+        let url = client.get_url().unwrap();
+        assert!(url.starts_with(AUTHORIZE_URL));
+        assert!(url.ends_with(&format!("&state={}", client.state)));
+        assert!(!client.is_logged_in());
+
+        let mut client = generate_test_client(&Server::new_async().await, Some("token"));
+        client.auth().await;
+        assert!(client.get_url().is_none());
+        assert!(client.is_logged_in());
+    }
+
+    #[tokio::test]
+    async fn test_login_without_client_id() {
+        let mut client = generate_test_client(&Server::new_async().await, None);
+        client.client_id = None;
+        client.auth().await;
+        assert_eq!(client.get_url(), None);
+        let error = client.login().await.unwrap_err();
+        assert!(error.contains(CLIENT_ID_VAR));
+    }
+
+    #[tokio::test]
+    async fn test_login_with_redirect() {
+        let token_store = Credentials::memory();
+        let mut client = AniList::build("", None, token_store.clone());
+        client.client_id = Some(String::from("1"));
+        client.auth().await;
+        assert!(client
+            .login_with_redirect("http://localhost:2525/")
+            .await
+            .is_err());
+        assert!(client
+            .login_with_redirect("http://localhost:2525/#access_token=token&state=other")
+            .await
+            .is_err());
+        client
+            .login_with_redirect(&format!(
+                "http://localhost:2525/#access_token=token&token_type=Bearer&state={}",
+                client.state
+            ))
+            .await
+            .unwrap();
+        assert!(client.is_logged_in());
+        assert_eq!(token_store.load(), Ok(Some(String::from("token"))));
+    }
+
+    #[tokio::test]
+    async fn test_logout() {
+        let mut server = Server::new_async().await;
+        let viewer = mock_query(
+            &mut server,
+            "Viewer",
+            json!({ "Viewer": { "id": 1, "name": "lma_user" } }),
+        )
+        .await;
+        let token_store = Credentials::memory();
+        token_store.save("token").unwrap();
+        let mut client = AniList::with_credentials(token_store.clone())
+            .await
+            .unwrap();
+        client.api_url = server.url();
+        client.client_id = Some(String::from("1"));
+        assert_eq!(
+            client.get_username().await,
+            Ok(Some(String::from("lma_user")))
+        );
+        viewer.assert_async().await;
+
+        client.logout().await.unwrap();
+        assert!(!client.is_logged_in());
+        assert_eq!(client.get_username().await, Ok(None));
+        assert_eq!(token_store.load(), Ok(None));
+        client.auth().await;
+        assert!(client.get_url().is_some());
+    }
+
+    #[test]
+    fn test_status_conversion() {
+        for status in [
+            EpisodeStatus::Watching,
+            EpisodeStatus::Completed,
+            EpisodeStatus::OnHold,
+            EpisodeStatus::Dropped,
+            EpisodeStatus::PlanToWatch,
+        ] {
+            let anilist_status = to_anilist_status(status).unwrap();
+            assert_eq!(to_episode_status(anilist_status), status);
+        }
+        assert_eq!(
+            to_fuzzy_date("2023-01-05"),
+            json!({ "year": 2023, "month": 1, "day": 5 })
+        );
+    }
+
+    async fn mock_query(server: &mut ServerGuard, query_part: &str, data: Value) -> mockito::Mock {
+        server
+            .mock("POST", "/")
+            .match_body(Matcher::Regex(String::from(query_part)))
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "data": data }).to_string())
+            .create_async()
+            .await
+    }
+
+    fn generate_test_client(server: &ServerGuard, token: Option<&str>) -> AniList {
+        let mut client = AniList::build(
+            &server.url(),
+            token.map(String::from),
+            Credentials::memory(),
+        );
+        client.client_id = Some(String::from("1"));
+        client
+    }
+}
@@ -0,0 +1,491 @@
+use super::{
+    anilist::AniList,
+    cached::{CacheTtl, Cached},
+    kitsu::Kitsu,
+    local::Local,
+    mal::{MalApp, MAL},
+    mirror::Mirror,
+    shikimori::Shikimori,
+};
+use crate::{
+    AlternativeTitles, Capabilities, CredentialStore, Credentials, RemoteEntries, Season, Service,
+    ServiceAiring, ServiceEpisodeDetails, ServiceEpisodeUser, ServiceFailure, ServiceListPage,
+    ServiceRelation, ServiceSearchPage, ServiceTitle, ServiceType, SyncProgress,
+};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use lib_mal::MALClient;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Service picked at runtime
+pub type AnyService = Box<dyn DynService>;
+
+/// Object safe version of `Service`, every service implements it
+pub trait DynService: Send + Sync {
+    fn login(&mut self) -> BoxFuture<'_, Result<(), String>>;
+    fn auth(&mut self) -> BoxFuture<'_, ()>;
+    fn init_show(&mut self, id: usize) -> BoxFuture<'_, Result<(), String>>;
+    fn search_title<'a>(
+        &'a mut self,
+        potential_title: &'a str,
+        offset: usize,
+    ) -> BoxFuture<'a, Result<ServiceSearchPage, String>>;
+    fn get_title(&mut self, id: usize) -> BoxFuture<'_, Result<String, String>>;
+    fn get_title_details(&mut self, id: usize) -> BoxFuture<'_, Result<ServiceTitle, String>>;
+    fn get_alternative_titles(
+        &mut self,
+        id: usize,
+    ) -> BoxFuture<'_, Result<Option<AlternativeTitles>, String>>;
+    fn get_episodes(
+        &mut self,
+        id: usize,
+        precise_score: bool,
+    ) -> BoxFuture<'_, Result<Vec<ServiceEpisodeDetails>, String>>;
+    fn get_episode_count(&mut self, id: usize) -> BoxFuture<'_, Result<Option<usize>, String>>;
+    fn get_user_entry_details(
+        &mut self,
+        id: usize,
+    ) -> BoxFuture<'_, Result<Option<ServiceEpisodeUser>, String>>;
+    fn get_user_entries_details<'a>(
+        &'a mut self,
+        ids: &'a [usize],
+        progress: &'a SyncProgress,
+    ) -> BoxFuture<'a, Result<RemoteEntries, String>>;
+    fn get_user_list(&mut self, offset: usize) -> BoxFuture<'_, Result<ServiceListPage, String>>;
+    fn update_user_entry<'a>(
+        &'a mut self,
+        id: usize,
+        entry: &'a ServiceEpisodeUser,
+    ) -> BoxFuture<'a, Result<(), String>>;
+    fn set_progress(
+        &mut self,
+        id: usize,
+        progress: usize,
+        episode_count: Option<usize>,
+    ) -> BoxFuture<'_, Result<usize, String>>;
+    fn get_service_type(&self) -> ServiceType;
+    fn capabilities(&self) -> Capabilities;
+    fn get_url(&self) -> Option<String>;
+    fn is_logged_in(&self) -> bool;
+    fn get_username(&mut self) -> BoxFuture<'_, Result<Option<String>, String>>;
+    fn logout(&mut self) -> BoxFuture<'_, Result<(), String>>;
+    fn get_season(
+        &mut self,
+        season: Season,
+        offset: usize,
+    ) -> BoxFuture<'_, Result<ServiceSearchPage, String>>;
+    fn get_airing_schedule<'a>(
+        &'a mut self,
+        ids: &'a [usize],
+    ) -> BoxFuture<'a, Result<Vec<ServiceAiring>, String>>;
+    fn get_relations(&mut self, id: usize) -> BoxFuture<'_, Result<Vec<ServiceRelation>, String>>;
+    fn needs_credentials(&self) -> bool;
+    fn set_credentials(&mut self, username: &str, password: &str);
+    fn set_redirect_port(&mut self, port: u16);
+    fn login_with_redirect<'a>(
+        &'a mut self,
+        redirect_url: &'a str,
+    ) -> BoxFuture<'a, Result<(), String>>;
+    fn take_failures(&mut self) -> Vec<ServiceFailure>;
+    fn invalidate_cache(&mut self, id: usize) -> BoxFuture<'_, Result<(), String>>;
+}
+
+impl<T: Service> DynService for T {
+    fn login(&mut self) -> BoxFuture<'_, Result<(), String>> {
+        Service::login(self).boxed()
+    }
+    fn auth(&mut self) -> BoxFuture<'_, ()> {
+        Service::auth(self).boxed()
+    }
+    fn init_show(&mut self, id: usize) -> BoxFuture<'_, Result<(), String>> {
+        Service::init_show(self, id).boxed()
+    }
+    fn search_title<'a>(
+        &'a mut self,
+        potential_title: &'a str,
+        offset: usize,
+    ) -> BoxFuture<'a, Result<ServiceSearchPage, String>> {
+        Service::search_title(self, potential_title, offset).boxed()
+    }
+    fn get_title(&mut self, id: usize) -> BoxFuture<'_, Result<String, String>> {
+        Service::get_title(self, id).boxed()
+    }
+    fn get_title_details(&mut self, id: usize) -> BoxFuture<'_, Result<ServiceTitle, String>> {
+        Service::get_title_details(self, id).boxed()
+    }
+    fn get_alternative_titles(
+        &mut self,
+        id: usize,
+    ) -> BoxFuture<'_, Result<Option<AlternativeTitles>, String>> {
+        Service::get_alternative_titles(self, id).boxed()
+    }
+    fn get_episodes(
+        &mut self,
+        id: usize,
+        precise_score: bool,
+    ) -> BoxFuture<'_, Result<Vec<ServiceEpisodeDetails>, String>> {
+        Service::get_episodes(self, id, precise_score).boxed()
+    }
+    fn get_episode_count(&mut self, id: usize) -> BoxFuture<'_, Result<Option<usize>, String>> {
+        Service::get_episode_count(self, id).boxed()
+    }
+    fn get_user_entry_details(
+        &mut self,
+        id: usize,
+    ) -> BoxFuture<'_, Result<Option<ServiceEpisodeUser>, String>> {
+        Service::get_user_entry_details(self, id).boxed()
+    }
+    fn get_user_entries_details<'a>(
+        &'a mut self,
+        ids: &'a [usize],
+        progress: &'a SyncProgress,
+    ) -> BoxFuture<'a, Result<RemoteEntries, String>> {
+        Service::get_user_entries_details(self, ids, progress).boxed()
+    }
+    fn get_user_list(&mut self, offset: usize) -> BoxFuture<'_, Result<ServiceListPage, String>> {
+        Service::get_user_list(self, offset).boxed()
+    }
+    fn update_user_entry<'a>(
+        &'a mut self,
+        id: usize,
+        entry: &'a ServiceEpisodeUser,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Service::update_user_entry(self, id, entry).boxed()
+    }
+    fn set_progress(
+        &mut self,
+        id: usize,
+        progress: usize,
+        episode_count: Option<usize>,
+    ) -> BoxFuture<'_, Result<usize, String>> {
+        Service::set_progress(self, id, progress, episode_count).boxed()
+    }
+    fn get_service_type(&self) -> ServiceType {
+        Service::get_service_type(self)
+    }
+    fn capabilities(&self) -> Capabilities {
+        Service::capabilities(self)
+    }
+    fn get_url(&self) -> Option<String> {
+        Service::get_url(self)
+    }
+    fn is_logged_in(&self) -> bool {
+        Service::is_logged_in(self)
+    }
+    fn get_username(&mut self) -> BoxFuture<'_, Result<Option<String>, String>> {
+        Service::get_username(self).boxed()
+    }
+    fn logout(&mut self) -> BoxFuture<'_, Result<(), String>> {
+        Service::logout(self).boxed()
+    }
+    fn get_season(
+        &mut self,
+        season: Season,
+        offset: usize,
+    ) -> BoxFuture<'_, Result<ServiceSearchPage, String>> {
+        Service::get_season(self, season, offset).boxed()
+    }
+    fn get_airing_schedule<'a>(
+        &'a mut self,
+        ids: &'a [usize],
+    ) -> BoxFuture<'a, Result<Vec<ServiceAiring>, String>> {
+        Service::get_airing_schedule(self, ids).boxed()
+    }
+    fn get_relations(&mut self, id: usize) -> BoxFuture<'_, Result<Vec<ServiceRelation>, String>> {
+        Service::get_relations(self, id).boxed()
+    }
+    fn needs_credentials(&self) -> bool {
+        Service::needs_credentials(self)
+    }
+    fn set_credentials(&mut self, username: &str, password: &str) {
+        Service::set_credentials(self, username, password);
+    }
+    fn set_redirect_port(&mut self, port: u16) {
+        Service::set_redirect_port(self, port);
+    }
+    fn login_with_redirect<'a>(
+        &'a mut self,
+        redirect_url: &'a str,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Service::login_with_redirect(self, redirect_url).boxed()
+    }
+    fn take_failures(&mut self) -> Vec<ServiceFailure> {
+        Service::take_failures(self)
+    }
+    fn invalidate_cache(&mut self, id: usize) -> BoxFuture<'_, Result<(), String>> {
+        Service::invalidate_cache(self, id).boxed()
+    }
+}
+
+impl Service for AnyService {
+    /// Use `new_service` instead, the type alone doesn't say which service to create
+    async fn new(_cache_dir: PathBuf) -> Result<Self, String> {
+        Err(String::from("Service has to be picked with new_service"))
+    }
+    async fn login(&mut self) -> Result<(), String> {
+        self.as_mut().login().await
+    }
+    async fn auth(&mut self) {
+        self.as_mut().auth().await;
+    }
+    async fn init_show(&mut self, id: usize) -> Result<(), String> {
+        self.as_mut().init_show(id).await
+    }
+    async fn search_title(
+        &mut self,
+        potential_title: &str,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        self.as_mut().search_title(potential_title, offset).await
+    }
+    async fn get_title(&mut self, id: usize) -> Result<String, String> {
+        self.as_mut().get_title(id).await
+    }
+    async fn get_title_details(&mut self, id: usize) -> Result<ServiceTitle, String> {
+        self.as_mut().get_title_details(id).await
+    }
+    async fn get_alternative_titles(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<AlternativeTitles>, String> {
+        self.as_mut().get_alternative_titles(id).await
+    }
+    async fn get_episodes(
+        &mut self,
+        id: usize,
+        precise_score: bool,
+    ) -> Result<Vec<ServiceEpisodeDetails>, String> {
+        self.as_mut().get_episodes(id, precise_score).await
+    }
+    async fn get_episode_count(&mut self, id: usize) -> Result<Option<usize>, String> {
+        self.as_mut().get_episode_count(id).await
+    }
+    async fn get_user_entry_details(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<ServiceEpisodeUser>, String> {
+        self.as_mut().get_user_entry_details(id).await
+    }
+    async fn get_user_entries_details(
+        &mut self,
+        ids: &[usize],
+        progress: &SyncProgress,
+    ) -> Result<RemoteEntries, String> {
+        self.as_mut().get_user_entries_details(ids, progress).await
+    }
+    async fn get_user_list(&mut self, offset: usize) -> Result<ServiceListPage, String> {
+        self.as_mut().get_user_list(offset).await
+    }
+    async fn update_user_entry(
+        &mut self,
+        id: usize,
+        entry: &ServiceEpisodeUser,
+    ) -> Result<(), String> {
+        self.as_mut().update_user_entry(id, entry).await
+    }
+    async fn set_progress(
+        &mut self,
+        id: usize,
+        progress: usize,
+        episode_count: Option<usize>,
+    ) -> Result<usize, String> {
+        self.as_mut()
+            .set_progress(id, progress, episode_count)
+            .await
+    }
+    fn get_service_type(&self) -> ServiceType {
+        self.as_ref().get_service_type()
+    }
+    fn capabilities(&self) -> Capabilities {
+        self.as_ref().capabilities()
+    }
+    fn get_url(&self) -> Option<String> {
+        self.as_ref().get_url()
+    }
+    fn is_logged_in(&self) -> bool {
+        self.as_ref().is_logged_in()
+    }
+    async fn get_username(&mut self) -> Result<Option<String>, String> {
+        self.as_mut().get_username().await
+    }
+    async fn logout(&mut self) -> Result<(), String> {
+        self.as_mut().logout().await
+    }
+    async fn get_season(
+        &mut self,
+        season: Season,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        self.as_mut().get_season(season, offset).await
+    }
+    async fn get_airing_schedule(&mut self, ids: &[usize]) -> Result<Vec<ServiceAiring>, String> {
+        self.as_mut().get_airing_schedule(ids).await
+    }
+    async fn get_relations(&mut self, id: usize) -> Result<Vec<ServiceRelation>, String> {
+        self.as_mut().get_relations(id).await
+    }
+    fn needs_credentials(&self) -> bool {
+        self.as_ref().needs_credentials()
+    }
+    fn set_credentials(&mut self, username: &str, password: &str) {
+        self.as_mut().set_credentials(username, password);
+    }
+    fn set_redirect_port(&mut self, port: u16) {
+        self.as_mut().set_redirect_port(port);
+    }
+    async fn login_with_redirect(&mut self, redirect_url: &str) -> Result<(), String> {
+        self.as_mut().login_with_redirect(redirect_url).await
+    }
+    fn take_failures(&mut self) -> Vec<ServiceFailure> {
+        self.as_mut().take_failures()
+    }
+    async fn invalidate_cache(&mut self, id: usize) -> Result<(), String> {
+        self.as_mut().invalidate_cache(id).await
+    }
+}
+
+/// Creates the service of the given type, changes get mirrored to each of `mirrors` in order
+/// and metadata is cached for as long as `cache_ttl` allows, login tokens are kept in `token_store`
+pub async fn new_service(
+    service_type: &ServiceType,
+    mirrors: &[ServiceType],
+    cache_dir: PathBuf,
+    cache_ttl: &CacheTtl,
+    mal_app: &MalApp,
+    token_store: &Arc<dyn CredentialStore>,
+) -> Result<AnyService, String> {
+    let service = open_service(
+        service_type,
+        mirrors,
+        cache_dir.clone(),
+        mal_app,
+        token_store,
+    )
+    .await?;
+    if service_type == &ServiceType::Local {
+        // everything is local already
+        return Ok(service);
+    }
+    Ok(Box::new(
+        Cached::open(service, &cache_dir, cache_ttl.clone()).await?,
+    ))
+}
+
+/// Logs out of the service, the tokens of its mirrors stay
+pub fn forget_login(
+    service_type: &ServiceType,
+    cache_dir: PathBuf,
+    token_store: &Arc<dyn CredentialStore>,
+) -> Result<(), String> {
+    if service_type == &ServiceType::Local {
+        return Ok(());
+    }
+    let credentials = Credentials::new(token_store.clone(), cache_dir, service_type.as_str());
+    credentials.take_plain()?;
+    credentials.delete()
+}
+
+async fn open_service(
+    service_type: &ServiceType,
+    mirrors: &[ServiceType],
+    cache_dir: PathBuf,
+    mal_app: &MalApp,
+    token_store: &Arc<dyn CredentialStore>,
+) -> Result<AnyService, String> {
+    let credentials = Credentials::new(
+        token_store.clone(),
+        cache_dir.clone(),
+        service_type.as_str(),
+    );
+    if service_type != &ServiceType::Local {
+        credentials.take_plain()?;
+    }
+    let service: AnyService = match service_type {
+        ServiceType::MAL => {
+            Box::new(MAL::<MALClient>::with_app(cache_dir.clone(), mal_app, credentials).await?)
+        }
+        ServiceType::AniList => Box::new(AniList::with_credentials(credentials).await?),
+        ServiceType::Kitsu => Box::new(Kitsu::with_credentials(credentials).await?),
+        ServiceType::Shikimori => Box::new(Shikimori::with_credentials(credentials).await?),
+        ServiceType::Local => Box::new(Local::new(cache_dir.clone()).await?),
+    };
+    let Some((mirror_type, other_mirrors)) = mirrors.split_first() else {
+        return Ok(service);
+    };
+    if mirror_type == &ServiceType::Local {
+        return Err(String::from("Local can't be used as a mirror"));
+    }
+    // the rest are mirrored from the first mirror, same as nested Mirrors
+    let mirror_dir = Mirror::<AnyService, AnyService>::mirror_dir(&cache_dir)?;
+    let mirror = Box::pin(open_service(
+        mirror_type,
+        other_mirrors,
+        mirror_dir,
+        mal_app,
+        token_store,
+    ))
+    .await?;
+    Ok(Box::new(Mirror::open(service, mirror, &cache_dir)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CredentialBackend, CredentialStorage};
+
+    #[tokio::test]
+    async fn test_local_service() {
+        let cache_dir = std::env::temp_dir().join(format!("lma_boxed_test_{}", std::process::id()));
+        std::fs::create_dir_all(&cache_dir).unwrap();
+        let token_store = CredentialStorage::default().open(&cache_dir, None).unwrap();
+        let mut service = new_service(
+            &ServiceType::Local,
+            &[],
+            cache_dir.clone(),
+            &CacheTtl::default(),
+            &MalApp::default(),
+            &token_store,
+        )
+        .await
+        .unwrap();
+        assert_eq!(Service::get_service_type(&service), ServiceType::Local);
+        assert!(Service::is_logged_in(&service));
+        assert_eq!(
+            Service::search_title(&mut service, "title", 0).await,
+            Ok(ServiceSearchPage::default())
+        );
+        assert!(new_service(
+            &ServiceType::Local,
+            &[ServiceType::Local],
+            cache_dir.clone(),
+            &CacheTtl::default(),
+            &MalApp::default(),
+            &token_store,
+        )
+        .await
+        .is_err());
+        _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[test]
+    fn test_forget_login() {
+        let cache_dir =
+            std::env::temp_dir().join(format!("lma_boxed_forget_{}", std::process::id()));
+        std::fs::create_dir_all(&cache_dir).unwrap();
+        let storage = CredentialStorage {
+            backend: CredentialBackend::EncryptedFile,
+            key_file: None,
+        };
+        let token_store = storage.open(&cache_dir, Some("passphrase")).unwrap();
+        Credentials::new(token_store.clone(), cache_dir.clone(), "MAL")
+            .save("token")
+            .unwrap();
+        // left by a version that kept MAL tokens in plain text
+        std::fs::write(cache_dir.join("tokens"), "old token").unwrap();
+        forget_login(&ServiceType::MAL, cache_dir.clone(), &token_store).unwrap();
+        assert!(!cache_dir.join("tokens").exists());
+        assert_eq!(token_store.load(&cache_dir, "MAL"), Ok(None));
+        _ = std::fs::remove_dir_all(cache_dir);
+    }
+}
@@ -0,0 +1,505 @@
+use crate::{
+    AlternativeTitles, Capabilities, RemoteEntries, Season, Service, ServiceAiring,
+    ServiceEpisodeDetails, ServiceEpisodeUser, ServiceFailure, ServiceListPage, ServiceRelation,
+    ServiceSearchPage, ServiceTitle, ServiceType, SyncProgress,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::SqlitePool;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
+
+/// How many hours cached metadata is used before asking the service again
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(default)]
+pub struct CacheTtl {
+    pub title: u64,
+    pub alternative_titles: u64,
+    pub episode_count: u64,
+    pub episodes: u64,
+    pub details: u64,
+}
+
+impl Default for CacheTtl {
+    fn default() -> Self {
+        // counts and episodes change while a show airs
+        Self {
+            title: 24 * 30,
+            alternative_titles: 24 * 30,
+            episode_count: 24,
+            episodes: 24,
+            details: 24 * 7,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Title,
+    AlternativeTitles,
+    EpisodeCount,
+    Episodes { precise_score: bool },
+    Details,
+}
+
+impl Field {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::AlternativeTitles => "alternative_titles",
+            Self::EpisodeCount => "episode_count",
+            Self::Episodes {
+                precise_score: false,
+            } => "episodes",
+            Self::Episodes {
+                precise_score: true,
+            } => "episodes_precise",
+            Self::Details => "details",
+        }
+    }
+
+    const fn ttl_hours(self, ttl: &CacheTtl) -> u64 {
+        match self {
+            Self::Title => ttl.title,
+            Self::AlternativeTitles => ttl.alternative_titles,
+            Self::EpisodeCount => ttl.episode_count,
+            Self::Episodes { .. } => ttl.episodes,
+            Self::Details => ttl.details,
+        }
+    }
+}
+
+/// Keeps show metadata from the service in metadata.db3 instead of asking for it every time,
+/// expired metadata is still used when the service can't be reached
+pub struct Cached<S: Service> {
+    inner: S,
+    store: Store,
+}
+
+struct Store {
+    db_connection: SqlitePool,
+    service: ServiceType,
+    ttl: CacheTtl,
+}
+
+impl<S: Service> Cached<S> {
+    pub async fn open(inner: S, cache_dir: &Path, ttl: CacheTtl) -> Result<Self, String> {
+        let options = SqliteConnectOptions::new()
+            .filename(cache_dir.join("metadata.db3"))
+            .create_if_missing(true);
+        let db_connection = SqlitePool::connect_with(options)
+            .await
+            .map_err(|err| format!("Can't open metadata cache: {err}"))?;
+        sqlx::query!(
+            "CREATE TABLE IF NOT EXISTS Metadata (service TEXT, service_id INTEGER, field TEXT, value TEXT, fetched_at INTEGER, PRIMARY KEY (service, service_id, field))"
+        )
+        .execute(&db_connection)
+        .await
+        .map_err(|err| format!("Can't create metadata cache: {err}"))?;
+        let service = inner.get_service_type();
+        Ok(Self {
+            inner,
+            store: Store {
+                db_connection,
+                service,
+                ttl,
+            },
+        })
+    }
+}
+
+impl Store {
+    async fn get_or_fetch<V: Serialize + DeserializeOwned + Send>(
+        &self,
+        id: usize,
+        field: Field,
+        fetch: impl Future<Output = Result<V, String>> + Send,
+    ) -> Result<V, String> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let cached = self.load::<V>(id, field).await;
+        let max_age = i64::try_from(field.ttl_hours(&self.ttl) * 3600).unwrap_or(i64::MAX);
+        match cached {
+            Some((value, fetched_at)) if now - fetched_at < max_age => Ok(value),
+            cached => match fetch.await {
+                Ok(value) => {
+                    // a cache that can't be written only means more requests
+                    _ = self.save(id, field, &value, now).await;
+                    Ok(value)
+                }
+                Err(err) => cached.map(|(value, _)| value).ok_or(err),
+            },
+        }
+    }
+
+    async fn load<V: DeserializeOwned>(&self, id: usize, field: Field) -> Option<(V, i64)> {
+        let id = u32::try_from(id).ok()?;
+        let service = self.service.as_str();
+        let field = field.as_str();
+        let row = sqlx::query!(
+            "SELECT value, fetched_at FROM Metadata
+            WHERE service = ?1 AND service_id = ?2 AND field = ?3",
+            service,
+            id,
+            field
+        )
+        .fetch_optional(&self.db_connection)
+        .await
+        .ok()??;
+        let value = serde_json::from_str(&row.value?).ok()?;
+        Some((value, row.fetched_at?))
+    }
+
+    async fn save<V: Serialize>(
+        &self,
+        id: usize,
+        field: Field,
+        value: &V,
+        fetched_at: i64,
+    ) -> Result<(), String> {
+        let id = u32::try_from(id).map_err(|e| e.to_string())?;
+        let service = self.service.as_str();
+        let field = field.as_str();
+        let value = serde_json::to_string(value).map_err(|e| e.to_string())?;
+        sqlx::query!(
+            "REPLACE INTO Metadata (service, service_id, field, value, fetched_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            service,
+            id,
+            field,
+            value,
+            fetched_at
+        )
+        .execute(&self.db_connection)
+        .await
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    async fn invalidate(&self, id: usize) -> Result<(), String> {
+        let id = u32::try_from(id).map_err(|e| e.to_string())?;
+        let service = self.service.as_str();
+        sqlx::query!(
+            "DELETE FROM Metadata WHERE service = ?1 AND service_id = ?2",
+            service,
+            id
+        )
+        .execute(&self.db_connection)
+        .await
+        .map_err(|e| format!("Can't clear metadata cache: {e}"))?;
+        Ok(())
+    }
+}
+
+impl<S: Service> Service for Cached<S> {
+    async fn new(cache_dir: PathBuf) -> Result<Self, String> {
+        let inner = S::new(cache_dir.clone()).await?;
+        Self::open(inner, &cache_dir, CacheTtl::default()).await
+    }
+    async fn login(&mut self) -> Result<(), String> {
+        self.inner.login().await
+    }
+    async fn auth(&mut self) {
+        self.inner.auth().await;
+    }
+    async fn init_show(&mut self, id: usize) -> Result<(), String> {
+        self.inner.init_show(id).await
+    }
+    async fn search_title(
+        &mut self,
+        potential_title: &str,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        self.inner.search_title(potential_title, offset).await
+    }
+    async fn get_title(&mut self, id: usize) -> Result<String, String> {
+        self.store
+            .get_or_fetch(id, Field::Title, self.inner.get_title(id))
+            .await
+    }
+    async fn get_title_details(&mut self, id: usize) -> Result<ServiceTitle, String> {
+        self.store
+            .get_or_fetch(id, Field::Details, self.inner.get_title_details(id))
+            .await
+    }
+    async fn get_alternative_titles(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<AlternativeTitles>, String> {
+        self.store
+            .get_or_fetch(
+                id,
+                Field::AlternativeTitles,
+                self.inner.get_alternative_titles(id),
+            )
+            .await
+    }
+    async fn get_episodes(
+        &mut self,
+        id: usize,
+        precise_score: bool,
+    ) -> Result<Vec<ServiceEpisodeDetails>, String> {
+        self.store
+            .get_or_fetch(
+                id,
+                Field::Episodes { precise_score },
+                self.inner.get_episodes(id, precise_score),
+            )
+            .await
+    }
+    async fn get_episode_count(&mut self, id: usize) -> Result<Option<usize>, String> {
+        self.store
+            .get_or_fetch(id, Field::EpisodeCount, self.inner.get_episode_count(id))
+            .await
+    }
+    async fn get_user_entry_details(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<ServiceEpisodeUser>, String> {
+        self.inner.get_user_entry_details(id).await
+    }
+    async fn get_user_entries_details(
+        &mut self,
+        ids: &[usize],
+        progress: &SyncProgress,
+    ) -> Result<RemoteEntries, String> {
+        self.inner.get_user_entries_details(ids, progress).await
+    }
+    async fn get_user_list(&mut self, offset: usize) -> Result<ServiceListPage, String> {
+        self.inner.get_user_list(offset).await
+    }
+    async fn update_user_entry(
+        &mut self,
+        id: usize,
+        entry: &ServiceEpisodeUser,
+    ) -> Result<(), String> {
+        self.inner.update_user_entry(id, entry).await
+    }
+    /// The episode count comes from the cache when it's not given
+    async fn set_progress(
+        &mut self,
+        id: usize,
+        progress: usize,
+        episode_count: Option<usize>,
+    ) -> Result<usize, String> {
+        let episode_count = match episode_count {
+            Some(episode_count) => Some(episode_count),
+            None => self
+                .get_episode_count(id)
+                .await
+                .ok()
+                .map(Option::unwrap_or_default),
+        };
+        self.inner.set_progress(id, progress, episode_count).await
+    }
+    fn get_service_type(&self) -> ServiceType {
+        self.inner.get_service_type()
+    }
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+    fn get_url(&self) -> Option<String> {
+        self.inner.get_url()
+    }
+    fn is_logged_in(&self) -> bool {
+        self.inner.is_logged_in()
+    }
+    async fn get_username(&mut self) -> Result<Option<String>, String> {
+        self.inner.get_username().await
+    }
+    async fn logout(&mut self) -> Result<(), String> {
+        self.inner.logout().await
+    }
+    // seasons and air dates change too often to be worth caching
+    async fn get_season(
+        &mut self,
+        season: Season,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        self.inner.get_season(season, offset).await
+    }
+    async fn get_airing_schedule(&mut self, ids: &[usize]) -> Result<Vec<ServiceAiring>, String> {
+        self.inner.get_airing_schedule(ids).await
+    }
+    // the list keeps relations of its shows itself
+    async fn get_relations(&mut self, id: usize) -> Result<Vec<ServiceRelation>, String> {
+        self.inner.get_relations(id).await
+    }
+    fn needs_credentials(&self) -> bool {
+        self.inner.needs_credentials()
+    }
+    fn set_credentials(&mut self, username: &str, password: &str) {
+        self.inner.set_credentials(username, password);
+    }
+    fn set_redirect_port(&mut self, port: u16) {
+        self.inner.set_redirect_port(port);
+    }
+    async fn login_with_redirect(&mut self, redirect_url: &str) -> Result<(), String> {
+        self.inner.login_with_redirect(redirect_url).await
+    }
+    fn take_failures(&mut self) -> Vec<ServiceFailure> {
+        self.inner.take_failures()
+    }
+    async fn invalidate_cache(&mut self, id: usize) -> Result<(), String> {
+        self.store.invalidate(id).await?;
+        self.inner.invalidate_cache(id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cached_metadata() {
+        let cache_dir =
+            std::env::temp_dir().join(format!("lma_cached_test_{}", std::process::id()));
+        std::fs::create_dir_all(&cache_dir).unwrap();
+        let mut cached = Cached::open(Fake::default(), &cache_dir, CacheTtl::default())
+            .await
+            .unwrap();
+        assert_eq!(cached.get_episode_count(1).await, Ok(Some(12)));
+        cached.inner.offline = true;
+        assert_eq!(cached.get_episode_count(1).await, Ok(Some(12)));
+        assert_eq!(cached.set_progress(1, 12, None).await, Ok(12));
+        assert_eq!(cached.inner.requests, 1, "Count and progress use the cache");
+
+        cached.inner.offline = false;
+        assert_eq!(cached.invalidate_cache(1).await, Ok(()));
+        assert_eq!(cached.get_episode_count(1).await, Ok(Some(12)));
+        assert_eq!(cached.inner.requests, 2);
+
+        // expired metadata is better than nothing while offline
+        cached.store.ttl.episode_count = 0;
+        cached.inner.offline = true;
+        assert_eq!(cached.get_episode_count(1).await, Ok(Some(12)));
+        assert_eq!(cached.inner.requests, 3);
+        assert!(cached.get_episode_count(2).await.is_err());
+        _ = std::fs::remove_dir_all(cache_dir);
+    }
+
+    #[derive(Default)]
+    struct Fake {
+        requests: usize,
+        offline: bool,
+    }
+
+    impl Service for Fake {
+        async fn new(_cache_dir: PathBuf) -> Result<Self, String> {
+            Ok(Self::default())
+        }
+        async fn login(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+        async fn auth(&mut self) {}
+        async fn init_show(&mut self, _id: usize) -> Result<(), String> {
+            Ok(())
+        }
+        async fn search_title(
+            &mut self,
+            _potential_title: &str,
+            _offset: usize,
+        ) -> Result<ServiceSearchPage, String> {
+            Ok(ServiceSearchPage::default())
+        }
+        async fn get_title(&mut self, _id: usize) -> Result<String, String> {
+            Ok(String::new())
+        }
+        async fn get_title_details(&mut self, id: usize) -> Result<ServiceTitle, String> {
+            Ok(ServiceTitle {
+                service_id: id,
+                ..Default::default()
+            })
+        }
+        async fn get_alternative_titles(
+            &mut self,
+            _id: usize,
+        ) -> Result<Option<AlternativeTitles>, String> {
+            Ok(None)
+        }
+        async fn get_episodes(
+            &mut self,
+            _id: usize,
+            _precise_score: bool,
+        ) -> Result<Vec<ServiceEpisodeDetails>, String> {
+            Ok(Vec::new())
+        }
+        async fn get_episode_count(&mut self, _id: usize) -> Result<Option<usize>, String> {
+            self.requests += 1;
+            if self.offline {
+                Err(String::from("Offline"))
+            } else {
+                Ok(Some(12))
+            }
+        }
+        async fn get_user_entry_details(
+            &mut self,
+            _id: usize,
+        ) -> Result<Option<ServiceEpisodeUser>, String> {
+            Ok(None)
+        }
+        async fn get_user_entries_details(
+            &mut self,
+            _ids: &[usize],
+            _progress: &SyncProgress,
+        ) -> Result<RemoteEntries, String> {
+            Ok(RemoteEntries::new())
+        }
+        async fn get_user_list(&mut self, _offset: usize) -> Result<ServiceListPage, String> {
+            Ok(ServiceListPage::default())
+        }
+        async fn update_user_entry(
+            &mut self,
+            _id: usize,
+            _entry: &ServiceEpisodeUser,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+        async fn set_progress(
+            &mut self,
+            id: usize,
+            progress: usize,
+            episode_count: Option<usize>,
+        ) -> Result<usize, String> {
+            let episode_count = match episode_count {
+                Some(episode_count) => Some(episode_count),
+                None => self.get_episode_count(id).await?,
+            };
+            Ok(progress.min(episode_count.unwrap_or(usize::MAX)))
+        }
+        fn get_service_type(&self) -> ServiceType {
+            ServiceType::MAL
+        }
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::ALL
+        }
+        fn get_url(&self) -> Option<String> {
+            None
+        }
+        fn is_logged_in(&self) -> bool {
+            true
+        }
+        async fn get_username(&mut self) -> Result<Option<String>, String> {
+            Ok(None)
+        }
+        async fn logout(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+        async fn get_season(
+            &mut self,
+            _season: Season,
+            _offset: usize,
+        ) -> Result<ServiceSearchPage, String> {
+            Ok(ServiceSearchPage::default())
+        }
+        async fn get_airing_schedule(
+            &mut self,
+            _ids: &[usize],
+        ) -> Result<Vec<ServiceAiring>, String> {
+            Ok(Vec::new())
+        }
+        async fn get_relations(&mut self, _id: usize) -> Result<Vec<ServiceRelation>, String> {
+            Ok(Vec::new())
+        }
+    }
+}
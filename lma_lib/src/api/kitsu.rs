@@ -0,0 +1,1104 @@
+use super::rate_limit::TokenBucket;
+use crate::{
+    AlternativeTitles, Capabilities, Credentials, EpisodeStatus, MediaType, RelationType,
+    RemoteEntries, Season, Service, ServiceAiring, ServiceEpisodeDetails, ServiceEpisodeUser,
+    ServiceListEntry, ServiceListPage, ServiceRelation, ServiceSearchPage, ServiceTitle,
+    ServiceType, SyncProgress,
+};
+use reqwest::{Method, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use time::OffsetDateTime;
+
+const API_URL: &str = "https://kitsu.io/api/edge";
+const TOKEN_URL: &str = "https://kitsu.io/api/oauth/token";
+const JSON_API: &str = "application/vnd.api+json";
+const PAGE_SIZE: usize = 20;
+const RATE_LIMIT_BURST: u32 = 5;
+const RATE_LIMIT_PER_SECOND: f64 = 2.0;
+
+pub struct Kitsu {
+    client: reqwest::Client,
+    api_url: String,
+    token_url: String,
+    tokens: Option<Tokens>,
+    token_store: Credentials,
+    credentials: Option<(String, String)>,
+    user_id: Option<String>,
+    username: Option<String>,
+    rate_limiter: TokenBucket,
+}
+
+impl Service for Kitsu {
+    async fn new(cache_dir: PathBuf) -> Result<Self, String> {
+        Self::with_credentials(Credentials::plain(cache_dir, ServiceType::Kitsu.as_str())).await
+    }
+    async fn login(&mut self) -> Result<(), String> {
+        let (username, password) = self
+            .credentials
+            .take()
+            .ok_or("Enter your Kitsu email and password")?;
+        self.request_tokens(&[
+            ("grant_type", "password"),
+            ("username", &username),
+            ("password", &password),
+        ])
+        .await
+    }
+    async fn auth(&mut self) {}
+    async fn init_show(&mut self, id: usize) -> Result<(), String> {
+        if self.get_library_entry(id).await?.is_none() {
+            self.create_library_entry(
+                id,
+                Map::from_iter([(String::from("status"), json!("planned"))]),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+    async fn search_title(
+        &mut self,
+        potential_title: &str,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        let document: Document<Vec<Resource<AnimeAttributes>>> = self
+            .get(
+                "/anime",
+                &[
+                    ("filter[text]", potential_title.to_owned()),
+                    ("page[offset]", offset.to_string()),
+                    ("page[limit]", PAGE_SIZE.to_string()),
+                ],
+            )
+            .await
+            .map_err(|err| format!("Kitsu search result: {err}"))?;
+        Ok(search_page(document, offset))
+    }
+    async fn get_title(&mut self, id: usize) -> Result<String, String> {
+        Ok(self
+            .get_anime(id)
+            .await
+            .map_err(|err| format!("Anime title: {err}"))?
+            .canonical_title)
+    }
+    async fn get_title_details(&mut self, id: usize) -> Result<ServiceTitle, String> {
+        Ok(self
+            .get_anime(id)
+            .await
+            .map_err(|err| format!("Anime details: {err}"))?
+            .into_service_title(id))
+    }
+    async fn get_alternative_titles(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<AlternativeTitles>, String> {
+        let anime = self
+            .get_anime(id)
+            .await
+            .map_err(|err| format!("Alternative anime titles: {err}"))?;
+        let mut synonyms = anime.abbreviated_titles;
+        let mut languages = HashMap::new();
+        for (key, title) in anime.titles {
+            let Some(title) = title else {
+                continue;
+            };
+            match key.as_str() {
+                "en" | "en_us" => _ = languages.insert(String::from("en"), title),
+                "ja_jp" => _ = languages.insert(String::from("ja"), title),
+                // romanized titles
+                _ => synonyms.push(title),
+            }
+        }
+        Ok(Some(AlternativeTitles {
+            synonyms,
+            languages,
+        }))
+    }
+    async fn get_episodes(
+        &mut self,
+        id: usize,
+        _precise_score: bool,
+    ) -> Result<Vec<ServiceEpisodeDetails>, String> {
+        let mut episodes = Vec::new();
+        let mut offset = Some(0);
+        while let Some(current_offset) = offset {
+            let document: Document<Vec<Resource<EpisodeAttributes>>> = self
+                .get(
+                    &format!("/anime/{id}/episodes"),
+                    &[
+                        ("page[offset]", current_offset.to_string()),
+                        ("page[limit]", PAGE_SIZE.to_string()),
+                        ("sort", String::from("number")),
+                    ],
+                )
+                .await
+                .map_err(|err| format!("Get episodes: {err}"))?;
+            offset = document.next_offset(current_offset);
+            episodes.extend(document.data.into_iter().map(|episode| {
+                let mut titles = episode.attributes.titles;
+                ServiceEpisodeDetails {
+                    number: episode.attributes.number,
+                    title: episode.attributes.canonical_title,
+                    title_japanese: titles.remove("ja_jp").flatten(),
+                    title_romanji: titles.remove("en_jp").flatten(),
+                    duration: episode.attributes.length,
+                    aired: episode.attributes.airdate,
+                    score: None,
+                    filler: None,
+                    recap: None,
+                }
+            }));
+        }
+        Ok(episodes)
+    }
+    async fn get_episode_count(&mut self, id: usize) -> Result<Option<usize>, String> {
+        Ok(self
+            .get_anime(id)
+            .await
+            .map_err(|err| format!("Anime episode count: {err}"))?
+            .episode_count)
+    }
+    async fn get_user_entry_details(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<ServiceEpisodeUser>, String> {
+        Ok(self
+            .get_library_entry(id)
+            .await?
+            .map(|entry| entry.attributes.into_service_user()))
+    }
+    async fn get_user_entries_details(
+        &mut self,
+        ids: &[usize],
+        progress: &SyncProgress,
+    ) -> Result<RemoteEntries, String> {
+        let user_id = self.user_id().await?;
+        let mut entries = RemoteEntries::new();
+        for chunk in ids.chunks(PAGE_SIZE) {
+            let anime_ids: Vec<_> = chunk.iter().map(ToString::to_string).collect();
+            let document: Document<Vec<Resource<LibraryAttributes>>> = self
+                .get(
+                    "/library-entries",
+                    &[
+                        ("filter[userId]", user_id.clone()),
+                        ("filter[animeId]", anime_ids.join(",")),
+                        ("include", String::from("anime")),
+                        ("fields[anime]", String::from("canonicalTitle")),
+                        ("page[limit]", PAGE_SIZE.to_string()),
+                    ],
+                )
+                .await
+                .map_err(|err| format!("User's list: {err}"))?;
+            for entry in document.data {
+                if let Some(anime_id) = entry.anime_id() {
+                    entries.insert(anime_id, entry.attributes.into_service_user());
+                }
+            }
+            chunk.iter().for_each(|id| progress.finish_one(*id));
+        }
+        Ok(entries)
+    }
+    async fn get_user_list(&mut self, offset: usize) -> Result<ServiceListPage, String> {
+        let user_id = self.user_id().await?;
+        let document: Document<Vec<Resource<LibraryAttributes>>> = self
+            .get(
+                "/library-entries",
+                &[
+                    ("filter[userId]", user_id),
+                    ("filter[kind]", String::from("anime")),
+                    ("include", String::from("anime")),
+                    ("fields[anime]", String::from("canonicalTitle")),
+                    ("page[offset]", offset.to_string()),
+                    ("page[limit]", PAGE_SIZE.to_string()),
+                ],
+            )
+            .await
+            .map_err(|err| format!("User's list: {err}"))?;
+        let next_offset = document.next_offset(offset);
+        let titles: HashMap<_, _> = document
+            .included
+            .into_iter()
+            .map(|anime| (anime.id, anime.attributes.canonical_title))
+            .collect();
+        let entries = document
+            .data
+            .into_iter()
+            .filter_map(|entry| {
+                let service_id = entry.anime_id()?;
+                let title = titles.get(&service_id.to_string())?.clone();
+                Some(ServiceListEntry {
+                    service_id,
+                    title,
+                    user: entry.attributes.into_service_user(),
+                })
+            })
+            .collect();
+        Ok(ServiceListPage {
+            entries,
+            next_offset,
+        })
+    }
+    async fn update_user_entry(
+        &mut self,
+        id: usize,
+        entry: &ServiceEpisodeUser,
+    ) -> Result<(), String> {
+        let mut attributes = Map::new();
+        if let Some(status) = entry.status.and_then(to_kitsu_status) {
+            attributes.insert(String::from("status"), json!(status));
+        }
+        if let Some(score) = entry.score {
+            // 0 clears the rating
+            let rating = (score != 0).then_some(u16::from(score) * 2);
+            attributes.insert(String::from("ratingTwenty"), json!(rating));
+        }
+        // empty dates clear them
+        if let Some(start_date) = &entry.start_date {
+            let start_date = (!start_date.is_empty()).then_some(start_date);
+            attributes.insert(String::from("startedAt"), json!(start_date));
+        }
+        if let Some(finish_date) = &entry.finish_date {
+            let finish_date = (!finish_date.is_empty()).then_some(finish_date);
+            attributes.insert(String::from("finishedAt"), json!(finish_date));
+        }
+        self.save_library_entry(id, attributes).await?;
+        Ok(())
+    }
+    async fn set_progress(
+        &mut self,
+        id: usize,
+        progress: usize,
+        episode_count: Option<usize>,
+    ) -> Result<usize, String> {
+        let current = self
+            .get_library_entry(id)
+            .await?
+            .map(|entry| entry.attributes.into_service_user())
+            .unwrap_or_default();
+        let episode_count = match episode_count {
+            Some(episode_count) => Some(episode_count),
+            None => self.get_episode_count(id).await?,
+        }
+        .filter(|count| *count != 0)
+        .unwrap_or(usize::MAX);
+        let today = OffsetDateTime::now_utc().date().to_string();
+        let mut attributes = Map::new();
+        attributes.insert(String::from("progress"), json!(progress));
+        let status = if progress == 0 {
+            "planned"
+        } else if progress >= episode_count {
+            if current.finish_date.is_none() {
+                attributes.insert(String::from("finishedAt"), json!(today));
+            }
+            "completed"
+        } else {
+            "current"
+        };
+        attributes.insert(String::from("status"), json!(status));
+        if current.start_date.is_none() && progress == 1 {
+            attributes.insert(String::from("startedAt"), json!(today));
+        }
+        let saved = self.save_library_entry(id, attributes).await?;
+        Ok(saved.progress.unwrap_or(progress))
+    }
+    fn get_service_type(&self) -> ServiceType {
+        ServiceType::Kitsu
+    }
+    fn capabilities(&self) -> Capabilities {
+        // Kitsu knows which shows are airing but not when
+        Capabilities {
+            schedule: false,
+            ..Capabilities::ALL
+        }
+    }
+    fn get_url(&self) -> Option<String> {
+        None
+    }
+    fn is_logged_in(&self) -> bool {
+        self.tokens.is_some()
+    }
+    async fn get_username(&mut self) -> Result<Option<String>, String> {
+        if !self.is_logged_in() {
+            return Ok(None);
+        }
+        self.user_id().await?;
+        Ok(self.username.clone())
+    }
+    async fn logout(&mut self) -> Result<(), String> {
+        self.token_store.delete()?;
+        self.tokens = None;
+        self.user_id = None;
+        self.username = None;
+        Ok(())
+    }
+    async fn get_season(
+        &mut self,
+        season: Season,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        let document: Document<Vec<Resource<AnimeAttributes>>> = self
+            .get(
+                "/anime",
+                &[
+                    ("filter[season]", season.name.as_str().to_lowercase()),
+                    ("filter[seasonYear]", season.year.to_string()),
+                    ("sort", String::from("-userCount")),
+                    ("page[offset]", offset.to_string()),
+                    ("page[limit]", PAGE_SIZE.to_string()),
+                ],
+            )
+            .await
+            .map_err(|err| format!("Season {season}: {err}"))?;
+        Ok(search_page(document, offset))
+    }
+    async fn get_airing_schedule(&mut self, _ids: &[usize]) -> Result<Vec<ServiceAiring>, String> {
+        Ok(Vec::new())
+    }
+    async fn get_relations(&mut self, id: usize) -> Result<Vec<ServiceRelation>, String> {
+        let document: Document<Vec<Resource<RelationshipAttributes>>> = self
+            .get(
+                &format!("/anime/{id}/media-relationships"),
+                &[
+                    ("include", String::from("destination")),
+                    ("page[limit]", PAGE_SIZE.to_string()),
+                ],
+            )
+            .await
+            .map_err(|err| format!("Related anime: {err}"))?;
+        // manga are included too and their ids overlap with anime ones
+        let mut anime: HashMap<_, _> = document
+            .included
+            .into_iter()
+            .filter(|resource| resource.kind.as_deref() == Some("anime"))
+            .map(|resource| (resource.id, resource.attributes))
+            .collect();
+        Ok(document
+            .data
+            .into_iter()
+            .filter_map(|relationship| {
+                let destination = relationship.relationships?.destination?.data?;
+                if destination.kind.as_deref() != Some("anime") {
+                    return None;
+                }
+                let attributes = anime.remove(&destination.id)?;
+                Some(ServiceRelation {
+                    relation: RelationType::parse(&relationship.attributes.role),
+                    show: attributes.into_service_title(destination.id.parse().ok()?),
+                })
+            })
+            .collect())
+    }
+    fn needs_credentials(&self) -> bool {
+        true
+    }
+    fn set_credentials(&mut self, username: &str, password: &str) {
+        self.credentials = Some((username.to_owned(), password.to_owned()));
+    }
+}
+
+impl Kitsu {
+    /// Keeps the tokens in `token_store` instead of the data directory
+    pub async fn with_credentials(token_store: Credentials) -> Result<Self, String> {
+        let tokens = token_store
+            .load()?
+            .and_then(|tokens| serde_json::from_str(&tokens).ok());
+        let mut kitsu = Self::build(API_URL, TOKEN_URL, tokens, token_store);
+        if kitsu.tokens.as_ref().is_some_and(Tokens::expired) {
+            // log in again if the refresh fails
+            if kitsu.refresh().await.is_err() {
+                kitsu.tokens = None;
+            }
+        }
+        Ok(kitsu)
+    }
+
+    fn build(
+        api_url: &str,
+        token_url: &str,
+        tokens: Option<Tokens>,
+        token_store: Credentials,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_url: api_url.to_owned(),
+            token_url: token_url.to_owned(),
+            tokens,
+            token_store,
+            credentials: None,
+            user_id: None,
+            username: None,
+            rate_limiter: TokenBucket::new(RATE_LIMIT_BURST, RATE_LIMIT_PER_SECOND),
+        }
+    }
+
+    async fn refresh(&mut self) -> Result<(), String> {
+        let refresh_token = self
+            .tokens
+            .as_ref()
+            .map(|tokens| tokens.refresh_token.clone())
+            .ok_or("Not logged in")?;
+        self.request_tokens(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &refresh_token),
+        ])
+        .await
+    }
+
+    async fn request_tokens(&mut self, form: &[(&str, &str)]) -> Result<(), String> {
+        let response = self
+            .client
+            .post(&self.token_url)
+            .form(form)
+            .send()
+            .await
+            .map_err(|err| format!("Kitsu login: {err}"))?;
+        if !response.status().is_success() {
+            return Err(format!("Kitsu login: {}", response.status()));
+        }
+        let tokens: Tokens = response
+            .json()
+            .await
+            .map_err(|err| format!("Kitsu login: {err}"))?;
+        let serialized = serde_json::to_string(&tokens).map_err(|err| err.to_string())?;
+        self.token_store.save(&serialized)?;
+        self.tokens = Some(tokens);
+        self.user_id = None;
+        self.username = None;
+        Ok(())
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .client
+            .request(method, format!("{}{path}", self.api_url))
+            .header(reqwest::header::ACCEPT, JSON_API)
+            .header(reqwest::header::CONTENT_TYPE, JSON_API);
+        match &self.tokens {
+            Some(tokens) => request.bearer_auth(&tokens.access_token),
+            None => request,
+        }
+    }
+
+    async fn send<R: DeserializeOwned>(&self, request: RequestBuilder) -> Result<R, String> {
+        self.rate_limiter.acquire().await;
+        let response = request.send().await.map_err(|err| err.to_string())?;
+        let status = response.status();
+        let body: Value = response.json().await.map_err(|err| err.to_string())?;
+        if !status.is_success() {
+            let error = body["errors"][0]["title"]
+                .as_str()
+                .map_or_else(|| status.to_string(), ToOwned::to_owned);
+            return Err(error);
+        }
+        serde_json::from_value(body).map_err(|err| err.to_string())
+    }
+
+    async fn get<R: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<R, String> {
+        self.send(self.request(Method::GET, path).query(query))
+            .await
+    }
+
+    async fn get_anime(&self, id: usize) -> Result<AnimeAttributes, String> {
+        let document: Document<Resource<AnimeAttributes>> =
+            self.get(&format!("/anime/{id}"), &[]).await?;
+        Ok(document.data.attributes)
+    }
+
+    async fn user_id(&mut self) -> Result<String, String> {
+        if let Some(user_id) = &self.user_id {
+            return Ok(user_id.clone());
+        }
+        let document: Document<Vec<Resource<Value>>> = self
+            .get("/users", &[("filter[self]", String::from("true"))])
+            .await
+            .map_err(|err| format!("Kitsu user: {err}"))?;
+        let user = document
+            .data
+            .into_iter()
+            .next()
+            .ok_or("Kitsu user: not logged in")?;
+        self.username = user.attributes["name"].as_str().map(String::from);
+        self.user_id = Some(user.id.clone());
+        Ok(user.id)
+    }
+
+    async fn get_library_entry(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<Resource<LibraryAttributes>>, String> {
+        let user_id = self.user_id().await?;
+        let document: Document<Vec<Resource<LibraryAttributes>>> = self
+            .get(
+                "/library-entries",
+                &[
+                    ("filter[userId]", user_id),
+                    ("filter[animeId]", id.to_string()),
+                ],
+            )
+            .await
+            .map_err(|err| format!("Anime details: {err}"))?;
+        Ok(document.data.into_iter().next())
+    }
+
+    async fn create_library_entry(
+        &mut self,
+        id: usize,
+        attributes: Map<String, Value>,
+    ) -> Result<LibraryAttributes, String> {
+        let user_id = self.user_id().await?;
+        let body = json!({ "data": {
+            "type": "libraryEntries",
+            "attributes": attributes,
+            "relationships": {
+                "anime": { "data": { "type": "anime", "id": id.to_string() } },
+                "user": { "data": { "type": "users", "id": user_id } },
+            },
+        } });
+        let document: Document<Resource<LibraryAttributes>> = self
+            .send(self.request(Method::POST, "/library-entries").json(&body))
+            .await
+            .map_err(|err| format!("Update user's list: {err}"))?;
+        Ok(document.data.attributes)
+    }
+
+    /// Creates the entry if the show isn't on the user's list yet
+    async fn save_library_entry(
+        &mut self,
+        id: usize,
+        attributes: Map<String, Value>,
+    ) -> Result<LibraryAttributes, String> {
+        let Some(entry) = self.get_library_entry(id).await? else {
+            return self.create_library_entry(id, attributes).await;
+        };
+        let body = json!({ "data": {
+            "id": entry.id,
+            "type": "libraryEntries",
+            "attributes": attributes,
+        } });
+        let document: Document<Resource<LibraryAttributes>> = self
+            .send(
+                self.request(Method::PATCH, &format!("/library-entries/{}", entry.id))
+                    .json(&body),
+            )
+            .await
+            .map_err(|err| format!("Update user's list: {err}"))?;
+        Ok(document.data.attributes)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Tokens {
+    access_token: String,
+    refresh_token: String,
+    created_at: i64,
+    expires_in: i64,
+}
+
+impl Tokens {
+    fn expired(&self) -> bool {
+        OffsetDateTime::now_utc().unix_timestamp() >= self.created_at + self.expires_in
+    }
+}
+
+/// JSON:API document
+#[derive(Deserialize)]
+struct Document<T> {
+    data: T,
+    #[serde(default)]
+    included: Vec<Resource<AnimeAttributes>>,
+    links: Option<Links>,
+}
+
+impl<T> Document<T> {
+    fn next_offset(&self, offset: usize) -> Option<usize> {
+        self.links
+            .as_ref()
+            .and_then(|links| links.next.as_ref())
+            .map(|_| offset + PAGE_SIZE)
+    }
+}
+
+#[derive(Deserialize)]
+struct Links {
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Resource<A> {
+    id: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    attributes: A,
+    relationships: Option<Relationships>,
+}
+
+impl<A> Resource<A> {
+    fn anime_id(&self) -> Option<usize> {
+        self.relationships
+            .as_ref()?
+            .anime
+            .as_ref()?
+            .data
+            .as_ref()?
+            .id
+            .parse()
+            .ok()
+    }
+}
+
+#[derive(Deserialize)]
+struct Relationships {
+    anime: Option<Relationship>,
+    destination: Option<Relationship>,
+}
+
+#[derive(Deserialize)]
+struct Relationship {
+    data: Option<ResourceIdentifier>,
+}
+
+#[derive(Deserialize)]
+struct ResourceIdentifier {
+    id: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AnimeAttributes {
+    canonical_title: String,
+    #[serde(default)]
+    titles: HashMap<String, Option<String>>,
+    #[serde(default)]
+    abbreviated_titles: Vec<String>,
+    episode_count: Option<usize>,
+    subtype: Option<String>,
+    start_date: Option<String>,
+    #[serde(default)]
+    nsfw: bool,
+}
+
+impl AnimeAttributes {
+    fn into_service_title(self, id: usize) -> ServiceTitle {
+        ServiceTitle {
+            service_id: id,
+            title: self.canonical_title,
+            media_type: self.subtype.as_deref().map(MediaType::parse),
+            year: self.start_date.and_then(|date| date.get(..4)?.parse().ok()),
+            episodes: self.episode_count,
+            nsfw: self.nsfw,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RelationshipAttributes {
+    role: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EpisodeAttributes {
+    canonical_title: Option<String>,
+    #[serde(default)]
+    titles: HashMap<String, Option<String>>,
+    number: Option<usize>,
+    airdate: Option<String>,
+    length: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LibraryAttributes {
+    status: Option<String>,
+    progress: Option<usize>,
+    reconsuming: Option<bool>,
+    reconsume_count: Option<usize>,
+    notes: Option<String>,
+    rating_twenty: Option<u8>,
+    updated_at: Option<String>,
+    started_at: Option<String>,
+    finished_at: Option<String>,
+}
+
+impl LibraryAttributes {
+    fn into_service_user(self) -> ServiceEpisodeUser {
+        ServiceEpisodeUser {
+            status: self.status.as_deref().map(EpisodeStatus::parse_kitsu),
+            progress: self.progress,
+            // ratings are 2-20 in steps of 1, scores are 1-10
+            score: self.rating_twenty.map(|rating| rating.div_ceil(2)),
+            is_rewatching: self.reconsuming,
+            rewatch_count: self.reconsume_count,
+            updated_at: self.updated_at,
+            start_date: self.started_at.map(to_date),
+            finish_date: self.finished_at.map(to_date),
+            comments: self.notes,
+        }
+    }
+}
+
+fn search_page(
+    document: Document<Vec<Resource<AnimeAttributes>>>,
+    offset: usize,
+) -> ServiceSearchPage {
+    let next_offset = document.next_offset(offset);
+    let titles = document
+        .data
+        .into_iter()
+        .filter_map(|anime| Some(anime.attributes.into_service_title(anime.id.parse().ok()?)))
+        .collect();
+    ServiceSearchPage {
+        titles,
+        next_offset,
+    }
+}
+
+/// Kitsu dates are full timestamps
+fn to_date(timestamp: String) -> String {
+    timestamp.chars().take(10).collect()
+}
+
+impl EpisodeStatus {
+    fn parse_kitsu(status: &str) -> Self {
+        match status {
+            "current" => Self::Watching,
+            "planned" => Self::PlanToWatch,
+            _ => Self::parse(status),
+        }
+    }
+}
+
+const fn to_kitsu_status(status: EpisodeStatus) -> Option<&'static str> {
+    match status {
+        EpisodeStatus::Watching => Some("current"),
+        EpisodeStatus::Completed => Some("completed"),
+        EpisodeStatus::OnHold => Some("on_hold"),
+        EpisodeStatus::Dropped => Some("dropped"),
+        EpisodeStatus::PlanToWatch => Some("planned"),
+        EpisodeStatus::None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SeasonName;
+    use mockito::{Matcher, Server, ServerGuard};
+
+    const USER: &str = include_str!("../../fixtures/kitsu/user.json");
+    const LIBRARY_ENTRIES: &str = include_str!("../../fixtures/kitsu/library_entries.json");
+    const LIBRARY_EMPTY: &str = include_str!("../../fixtures/kitsu/library_empty.json");
+
+    #[tokio::test]
+    async fn test_login() {
+        let mut server = Server::new_async().await;
+        let token = server
+            .mock("POST", "/oauth/token")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded(String::from("grant_type"), String::from("password")),
+                Matcher::UrlEncoded(String::from("username"), String::from("user@example.com")),
+            ]))
+            .with_body(include_str!("../../fixtures/kitsu/token.json"))
+            .create_async()
+            .await;
+        let token_store = Credentials::memory();
+        let mut client = Kitsu::build(
+            &server.url(),
+            &format!("{}/oauth/token", server.url()),
+            None,
+            token_store.clone(),
+        );
+        assert!(client.login().await.is_err(), "No credentials set");
+        client.set_credentials("user@example.com", "password");
+        assert_eq!(client.login().await, Ok(()));
+        token.assert_async().await;
+        assert!(client.is_logged_in());
+        assert!(token_store
+            .load()
+            .unwrap()
+            .is_some_and(|tokens| tokens.contains("refresh_token")));
+    }
+
+    #[tokio::test]
+    async fn test_logout() {
+        let mut server = Server::new_async().await;
+        mock_user(&mut server).await;
+        let mut client = generate_test_client(&server);
+        client.token_store.save("tokens").unwrap();
+        assert_eq!(
+            client.get_username().await,
+            Ok(Some(String::from("lma_user")))
+        );
+        client.logout().await.unwrap();
+        assert!(!client.is_logged_in());
+        assert_eq!(client.get_username().await, Ok(None));
+        assert_eq!(client.token_store.load(), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/anime")
+            .match_query(Matcher::UrlEncoded(
+                String::from("filter[text]"),
+                String::from("One Piece"),
+            ))
+            .with_body(include_str!("../../fixtures/kitsu/search.json"))
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server);
+        let result = client.search_title("One Piece", 0).await.unwrap();
+        assert_eq!(result.next_offset, Some(20));
+        let result = result.titles;
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].service_id, 12);
+        assert_eq!(result[0].year, Some(1999));
+        assert_eq!(result[1].title, "One Piece: Episode of Sabo");
+        assert_eq!(result[1].media_type, Some(MediaType::Special));
+    }
+
+    #[tokio::test]
+    async fn test_season() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/anime")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded(String::from("filter[season]"), String::from("fall")),
+                Matcher::UrlEncoded(String::from("filter[seasonYear]"), String::from("1999")),
+            ]))
+            .with_body(include_str!("../../fixtures/kitsu/search.json"))
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server);
+        let season = Season::new(SeasonName::Fall, 1999);
+        let result = client.get_season(season, 0).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(result.next_offset, Some(20));
+        assert_eq!(result.titles[0].title, "One Piece");
+        assert_eq!(client.get_airing_schedule(&[12]).await, Ok(Vec::new()));
+        assert!(!client.capabilities().schedule);
+    }
+
+    #[tokio::test]
+    async fn test_relations() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/anime/10517/media-relationships")
+            .match_query(Matcher::UrlEncoded(
+                String::from("include"),
+                String::from("destination"),
+            ))
+            .with_body(include_str!("../../fixtures/kitsu/relations.json"))
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server);
+        let result = client.get_relations(10517).await.unwrap();
+        assert_eq!(result.len(), 2, "The manga is left out");
+        assert_eq!(result[0].relation, RelationType::Prequel);
+        assert_eq!(result[0].show.service_id, 8134);
+        assert_eq!(result[1].relation, RelationType::Sequel);
+        assert_eq!(result[1].show.title, "Diamond no Ace: Act II");
+        assert_eq!(result[1].show.year, Some(2019));
+    }
+
+    #[tokio::test]
+    async fn test_get_alternative_titles() {
+        let mut server = Server::new_async().await;
+        mock_anime(&mut server).await;
+        let mut client = generate_test_client(&server);
+        let titles = client.get_alternative_titles(10517).await.unwrap().unwrap();
+        assert_eq!(
+            titles.languages.get("en").map(String::as_str),
+            Some("Ace of the Diamond: Second Season")
+        );
+        assert!(titles.synonyms.contains(&String::from("Daiya no A 2")));
+        assert!(titles
+            .synonyms
+            .contains(&String::from("Diamond no Ace: Second Season")));
+        assert_eq!(client.get_episode_count(10517).await, Ok(Some(51)));
+        assert_eq!(
+            client
+                .get_title_details(10517)
+                .await
+                .map(|details| details.episodes),
+            Ok(Some(51))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_episodes() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/anime/10517/episodes")
+            .match_query(Matcher::Any)
+            .with_body(include_str!("../../fixtures/kitsu/episodes.json"))
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server);
+        let episodes = client.get_episodes(10517, false).await.unwrap();
+        assert_eq!(episodes.len(), 1);
+        assert_eq!(episodes[0].number, Some(1));
+        assert_eq!(episodes[0].title.as_deref(), Some("The Three-Way Battle"));
+        assert_eq!(
+            episodes[0].title_romanji.as_deref(),
+            Some("Sanbyakuen no Tatakai")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_user_entry_details() {
+        let mut server = Server::new_async().await;
+        mock_user(&mut server).await;
+        mock_library(&mut server, "10517", LIBRARY_ENTRIES).await;
+        let mut client = generate_test_client(&server);
+        let entry = client.get_user_entry_details(10517).await.unwrap().unwrap();
+        assert_eq!(entry.status, Some(EpisodeStatus::OnHold));
+        assert_eq!(entry.progress, Some(12));
+        assert_eq!(entry.score, Some(7));
+        assert_eq!(entry.start_date.as_deref(), Some("2023-01-05"));
+        assert_eq!(entry.finish_date, None);
+    }
+
+    #[tokio::test]
+    async fn test_get_user_list() {
+        let mut server = Server::new_async().await;
+        mock_user(&mut server).await;
+        server
+            .mock("GET", "/library-entries")
+            .match_query(Matcher::UrlEncoded(
+                String::from("filter[kind]"),
+                String::from("anime"),
+            ))
+            .with_body(LIBRARY_ENTRIES)
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server);
+        let page = client.get_user_list(0).await.unwrap();
+        assert_eq!(page.next_offset, Some(PAGE_SIZE));
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.entries[0].service_id, 10517);
+        assert_eq!(page.entries[0].title, "Diamond no Ace: Second Season");
+    }
+
+    #[tokio::test]
+    async fn test_set_progress() {
+        let mut server = Server::new_async().await;
+        mock_user(&mut server).await;
+        mock_library(&mut server, "10517", LIBRARY_ENTRIES).await;
+        mock_anime(&mut server).await;
+        let update = server
+            .mock("PATCH", "/library-entries/55501")
+            .match_body(Matcher::Regex(String::from("\"status\":\"completed\"")))
+            .with_body(include_str!(
+                "../../fixtures/kitsu/library_entry_updated.json"
+            ))
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server);
+        assert_eq!(client.set_progress(10517, 51, None).await, Ok(51));
+        update.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_init_show_creates_entry() {
+        let mut server = Server::new_async().await;
+        mock_user(&mut server).await;
+        mock_library(&mut server, "12", LIBRARY_EMPTY).await;
+        let create = server
+            .mock("POST", "/library-entries")
+            .match_body(Matcher::Regex(String::from("\"status\":\"planned\"")))
+            .with_status(201)
+            .with_body(include_str!(
+                "../../fixtures/kitsu/library_entry_updated.json"
+            ))
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server);
+        assert_eq!(client.init_show(12).await, Ok(()));
+        create.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_error() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/anime/999999999")
+            .with_status(404)
+            .with_body(include_str!("../../fixtures/kitsu/error.json"))
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server);
+        assert_eq!(
+            client.get_title(999_999_999).await,
+            Err(String::from("Anime title: Record not found"))
+        );
+    }
+
+    #[test]
+    fn test_status_conversion() {
+        for status in [
+            EpisodeStatus::Watching,
+            EpisodeStatus::Completed,
+            EpisodeStatus::OnHold,
+            EpisodeStatus::Dropped,
+            EpisodeStatus::PlanToWatch,
+        ] {
+            let kitsu_status = to_kitsu_status(status).unwrap();
+            assert_eq!(EpisodeStatus::parse_kitsu(kitsu_status), status);
+        }
+    }
+
+    async fn mock_user(server: &mut ServerGuard) {
+        server
+            .mock("GET", "/users")
+            .match_query(Matcher::Any)
+            .with_body(USER)
+            .create_async()
+            .await;
+    }
+
+    async fn mock_anime(server: &mut ServerGuard) {
+        server
+            .mock("GET", "/anime/10517")
+            .with_body(include_str!("../../fixtures/kitsu/anime.json"))
+            .create_async()
+            .await;
+    }
+
+    async fn mock_library(server: &mut ServerGuard, anime_id: &str, body: &str) {
+        server
+            .mock("GET", "/library-entries")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded(String::from("filter[userId]"), String::from("123456")),
+                Matcher::UrlEncoded(String::from("filter[animeId]"), anime_id.to_owned()),
+            ]))
+            .with_body(body)
+            .create_async()
+            .await;
+    }
+
+    fn generate_test_client(server: &ServerGuard) -> Kitsu {
+        let tokens = Tokens {
+            access_token: String::from("token"),
+            refresh_token: String::from("refresh"),
+            created_at: OffsetDateTime::now_utc().unix_timestamp(),
+            expires_in: 2_591_963,
+        };
+        Kitsu::build(
+            &server.url(),
+            &format!("{}/oauth/token", server.url()),
+            Some(tokens),
+            Credentials::memory(),
+        )
+    }
+}
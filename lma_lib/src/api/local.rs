@@ -1,18 +1,27 @@
+use crate::mapping::{self, MappingEntry};
 use crate::{
-    AlternativeTitles, Service, ServiceEpisodeDetails, ServiceEpisodeUser, ServiceTitle,
-    ServiceType,
+    AlternativeTitles, Capabilities, MediaType, RemoteEntries, Season, SeasonName, Service,
+    ServiceAiring, ServiceEpisodeDetails, ServiceEpisodeUser, ServiceListPage, ServiceRelation,
+    ServiceSearchPage, ServiceTitle, ServiceType, SyncProgress,
 };
-use std::{fs, path::PathBuf};
+use std::collections::HashMap;
+use std::path::PathBuf;
 
-pub struct Local {}
+const SEARCH_PAGE_SIZE: usize = 20;
+
+/// Works without a service, titles and episode counts come from an offline database if there is one
+#[derive(Default)]
+pub struct Local {
+    /// Shows from the offline database by their MAL id
+    shows: HashMap<usize, MappingEntry>,
+}
 
 impl Service for Local {
     async fn new(cache_dir: PathBuf) -> Result<Self, String> {
-        let tokens_path = cache_dir.join("tokens");
-        if !tokens_path.exists() {
-            fs::write(tokens_path, String::new()).map_err(|err| err.to_string())?;
+        match mapping::find_offline_database(&cache_dir) {
+            Some(path) => Ok(Self::build(mapping::load_entries(&path)?)),
+            None => Ok(Self::default()),
         }
-        Ok(Self {})
     }
     async fn login(&mut self) -> Result<(), String> {
         Ok(())
@@ -21,20 +30,73 @@ impl Service for Local {
     async fn init_show(&mut self, _id: usize) -> Result<(), String> {
         Ok(())
     }
-    async fn search_title(&mut self, _potential_title: &str) -> Result<Vec<ServiceTitle>, String> {
-        Ok(Vec::new())
+    async fn search_title(
+        &mut self,
+        potential_title: &str,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        let query = normalize(potential_title);
+        let mut matches: Vec<_> = self
+            .shows
+            .iter()
+            .filter_map(|(id, show)| {
+                let score = std::iter::once(&show.title)
+                    .chain(&show.synonyms)
+                    .map(|title| match_score(&query, &normalize(title)))
+                    .max()
+                    .unwrap_or_default();
+                (score > 0).then_some((score, *id, show))
+            })
+            .collect();
+        matches.sort_by(|(a_score, _, a_show), (b_score, _, b_show)| {
+            b_score
+                .cmp(a_score)
+                .then_with(|| a_show.title.cmp(&b_show.title))
+        });
+        let next_offset =
+            (matches.len() > offset + SEARCH_PAGE_SIZE).then_some(offset + SEARCH_PAGE_SIZE);
+        let titles = matches
+            .into_iter()
+            .skip(offset)
+            .take(SEARCH_PAGE_SIZE)
+            .map(|(_, id, show)| service_title(id, show))
+            .collect();
+        Ok(ServiceSearchPage {
+            titles,
+            next_offset,
+        })
     }
-    async fn get_title(&mut self, _id: usize) -> Result<String, String> {
-        Ok(String::new())
+    async fn get_title(&mut self, id: usize) -> Result<String, String> {
+        Ok(self
+            .shows
+            .get(&id)
+            .map(|show| show.title.clone())
+            .unwrap_or_default())
+    }
+    async fn get_title_details(&mut self, id: usize) -> Result<ServiceTitle, String> {
+        // without the offline database every id is fine
+        if self.shows.is_empty() {
+            return Ok(ServiceTitle {
+                service_id: id,
+                ..Default::default()
+            });
+        }
+        self.shows
+            .get(&id)
+            .map(|show| service_title(id, show))
+            .ok_or_else(|| format!("No MAL id {id} in the offline database"))
     }
     async fn get_alternative_titles(
         &mut self,
-        _id: usize,
+        id: usize,
     ) -> Result<Option<AlternativeTitles>, String> {
-        Ok(None)
+        Ok(self.shows.get(&id).map(|show| AlternativeTitles {
+            synonyms: show.synonyms.clone(),
+            languages: HashMap::new(),
+        }))
     }
-    async fn get_episode_count(&mut self, _id: usize) -> Result<Option<usize>, String> {
-        Ok(None)
+    async fn get_episode_count(&mut self, id: usize) -> Result<Option<usize>, String> {
+        Ok(self.shows.get(&id).and_then(|show| show.episodes))
     }
     async fn get_user_entry_details(
         &mut self,
@@ -49,20 +111,161 @@ impl Service for Local {
     ) -> Result<Vec<ServiceEpisodeDetails>, String> {
         Ok(Vec::new())
     }
-    async fn set_progress(&mut self, _id: usize, progress: usize) -> Result<usize, String> {
+    async fn get_user_entries_details(
+        &mut self,
+        ids: &[usize],
+        progress: &SyncProgress,
+    ) -> Result<RemoteEntries, String> {
+        ids.iter().for_each(|id| progress.finish_one(*id));
+        Ok(RemoteEntries::new())
+    }
+    async fn get_user_list(&mut self, _offset: usize) -> Result<ServiceListPage, String> {
+        Ok(ServiceListPage::default())
+    }
+    async fn update_user_entry(
+        &mut self,
+        _id: usize,
+        _entry: &ServiceEpisodeUser,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+    async fn set_progress(
+        &mut self,
+        _id: usize,
+        progress: usize,
+        _episode_count: Option<usize>,
+    ) -> Result<usize, String> {
         Ok(progress)
     }
     fn get_service_type(&self) -> ServiceType {
         ServiceType::Local
     }
+    fn capabilities(&self) -> Capabilities {
+        // titles and episode counts need the offline database
+        Capabilities {
+            search: !self.shows.is_empty(),
+            ..Default::default()
+        }
+    }
     fn is_logged_in(&self) -> bool {
         true
     }
+    async fn get_username(&mut self) -> Result<Option<String>, String> {
+        Ok(None)
+    }
+    async fn logout(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+    async fn get_season(
+        &mut self,
+        season: Season,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        let mut shows: Vec<_> = self
+            .shows
+            .iter()
+            .filter(|(_, show)| {
+                show.anime_season.as_ref().is_some_and(|anime_season| {
+                    anime_season.year == Some(season.year)
+                        && anime_season.season.as_deref().and_then(SeasonName::parse)
+                            == Some(season.name)
+                })
+            })
+            .collect();
+        shows.sort_by(|(_, a), (_, b)| a.title.cmp(&b.title));
+        let next_offset =
+            (shows.len() > offset + SEARCH_PAGE_SIZE).then_some(offset + SEARCH_PAGE_SIZE);
+        let titles = shows
+            .into_iter()
+            .skip(offset)
+            .take(SEARCH_PAGE_SIZE)
+            .map(|(id, show)| service_title(*id, show))
+            .collect();
+        Ok(ServiceSearchPage {
+            titles,
+            next_offset,
+        })
+    }
+    async fn get_airing_schedule(&mut self, _ids: &[usize]) -> Result<Vec<ServiceAiring>, String> {
+        // the offline database has seasons but no air dates
+        Ok(Vec::new())
+    }
+    async fn get_relations(&mut self, _id: usize) -> Result<Vec<ServiceRelation>, String> {
+        // related anime in the offline database don't say how they are related
+        Ok(Vec::new())
+    }
     fn get_url(&self) -> Option<String> {
         Some("Using local service stub".to_owned())
     }
 }
 
+impl Local {
+    /// Shows without a MAL id are left out, their id would change between database releases
+    fn build(entries: Vec<MappingEntry>) -> Self {
+        let shows = entries
+            .into_iter()
+            .filter_map(|show| Some((show.service_id(&ServiceType::MAL)?, show)))
+            .collect();
+        Self { shows }
+    }
+}
+
+fn normalize(title: &str) -> String {
+    title
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                ' '
+            }
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Higher is closer, 0 when nothing matches
+fn match_score(query: &str, title: &str) -> usize {
+    if query.is_empty() || title.is_empty() {
+        0
+    } else if query == title {
+        100
+    } else if title.starts_with(query) {
+        80
+    } else if title.contains(query) {
+        60
+    } else {
+        let words: Vec<_> = query.split_whitespace().collect();
+        let found = words
+            .iter()
+            .filter(|word| {
+                title
+                    .split_whitespace()
+                    .any(|title_word| &title_word == *word)
+            })
+            .count();
+        // a single shared word like "no" isn't enough
+        if found * 2 < words.len() {
+            0
+        } else {
+            found * 50 / words.len()
+        }
+    }
+}
+
+fn service_title(id: usize, show: &MappingEntry) -> ServiceTitle {
+    ServiceTitle {
+        service_id: id,
+        title: show.title.clone(),
+        media_type: show.media_type.as_deref().map(MediaType::parse),
+        year: show.anime_season.as_ref().and_then(|season| season.year),
+        episodes: show.episodes,
+        nsfw: show.tags.iter().any(|tag| tag == "hentai"),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -72,7 +275,7 @@ mod tests {
         let result = Local::new(PathBuf::new()).await;
         assert!(result.is_ok());
 
-        let mut local_service = Local {};
+        let mut local_service = Local::default();
 
         let result = local_service.login().await;
         assert!(result.is_ok());
@@ -80,12 +283,15 @@ mod tests {
         let result = local_service.init_show(123).await;
         assert!(result.is_ok());
 
-        let result = local_service.search_title("some_title").await;
-        assert_eq!(result, Ok(Vec::new()));
+        let result = local_service.search_title("some_title", 0).await;
+        assert_eq!(result, Ok(ServiceSearchPage::default()));
 
         let result = local_service.get_title(456).await;
         assert_eq!(result, Ok(String::new()));
 
+        let result = local_service.get_title_details(456).await;
+        assert_eq!(result.map(|details| details.service_id), Ok(456));
+
         let result = local_service.get_alternative_titles(727).await;
         assert_eq!(result, Ok(None));
 
@@ -98,7 +304,12 @@ mod tests {
         let result = local_service.get_episodes(222, false).await;
         assert_eq!(result, Ok(Vec::new()));
 
-        let result = local_service.set_progress(333, 50).await;
+        let result = local_service
+            .update_user_entry(444, &ServiceEpisodeUser::default())
+            .await;
+        assert!(result.is_ok());
+
+        let result = local_service.set_progress(333, 50, None).await;
         assert!(result.is_ok());
 
         let service_type = local_service.get_service_type();
@@ -110,4 +321,88 @@ mod tests {
         let url = local_service.get_url();
         assert_eq!(url, Some("Using local service stub".to_owned()));
     }
+
+    #[tokio::test]
+    async fn test_season() {
+        let data = include_str!("../../fixtures/local/anime-offline-database.json");
+        let mut local_service = Local::build(mapping::parse_entries(data).unwrap());
+
+        let season = Season::new(SeasonName::Fall, 2023);
+        let result = local_service.get_season(season, 0).await.unwrap();
+        assert_eq!(
+            result
+                .titles
+                .iter()
+                .map(|title| title.title.as_str())
+                .collect::<Vec<_>>(),
+            ["Kusuriya no Hitorigoto", "Sousou no Frieren"]
+        );
+        assert_eq!(result.next_offset, None);
+        let result = local_service.get_season(season.previous(), 0).await;
+        assert_eq!(
+            result.map(|page| page.titles.len()),
+            Ok(1),
+            "Undefined seasons are left out"
+        );
+        let result = local_service.get_season(season.next(), 0).await;
+        assert_eq!(result, Ok(ServiceSearchPage::default()));
+        assert_eq!(
+            local_service.get_airing_schedule(&[52991]).await,
+            Ok(Vec::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_offline_database() {
+        let data = r#"{ "data": [
+            { "title": "Shingeki no Kyojin", "synonyms": ["Attack on Titan"], "episodes": 25,
+              "type": "TV", "animeSeason": { "season": "SPRING", "year": 2013 },
+              "sources": ["https://myanimelist.net/anime/16498"] },
+            { "title": "Shingeki no Kyojin Season 2", "synonyms": [], "episodes": 12,
+              "sources": ["https://myanimelist.net/anime/25777"] },
+            { "title": "No MAL id", "sources": ["https://anilist.co/anime/1"] }
+        ] }"#;
+        let mut local_service = Local::build(mapping::parse_entries(data).unwrap());
+
+        let result = local_service.search_title("attack on titan", 0).await;
+        assert_eq!(
+            result.map(|page| page.titles),
+            Ok(vec![ServiceTitle {
+                service_id: 16498,
+                title: "Shingeki no Kyojin".to_owned(),
+                media_type: Some(MediaType::TV),
+                year: Some(2013),
+                episodes: Some(25),
+                nsfw: false,
+            }])
+        );
+        let result = local_service
+            .search_title("shingeki kyojin", 0)
+            .await
+            .unwrap();
+        assert_eq!(result.titles.len(), 2, "Matched on words");
+        assert_eq!(result.next_offset, None);
+        assert!(local_service
+            .search_title("no mal id", 0)
+            .await
+            .unwrap()
+            .titles
+            .is_empty());
+
+        let result = local_service.get_title(25777).await;
+        assert_eq!(result, Ok("Shingeki no Kyojin Season 2".to_owned()));
+        let result = local_service.get_episode_count(16498).await;
+        assert_eq!(result, Ok(Some(25)));
+        let result = local_service.get_title_details(25777).await;
+        assert_eq!(result.map(|details| details.episodes), Ok(Some(12)));
+        assert!(local_service.get_title_details(1).await.is_err());
+        let result = local_service.get_alternative_titles(16498).await;
+        assert_eq!(
+            result,
+            Ok(Some(AlternativeTitles {
+                synonyms: vec!["Attack on Titan".to_owned()],
+                languages: HashMap::new()
+            }))
+        );
+    }
 }
@@ -1,105 +1,359 @@
+mod retry;
+
+use super::oauth;
+use super::rate_limit::TokenBucket;
 use crate::{
-    AlternativeTitles, EpisodeStatus, Service, ServiceEpisodeDetails, ServiceEpisodeUser,
-    ServiceTitle, ServiceType,
+    AlternativeTitles, Capabilities, Credentials, EpisodeStatus, MediaType, RelationType,
+    RemoteEntries, Season, Service, ServiceAiring, ServiceEpisodeDetails, ServiceEpisodeUser,
+    ServiceListEntry, ServiceListPage, ServiceRelation, ServiceSearchPage, ServiceTitle,
+    ServiceType, SyncProgress,
 };
+use futures::{stream, StreamExt};
 use lib_mal::prelude::fields::AnimeFields;
 use lib_mal::prelude::options::{Status, StatusUpdate};
-use lib_mal::prelude::ListStatus;
-use lib_mal::{ClientBuilder, MALClientTrait};
-use std::path::PathBuf;
-use time::OffsetDateTime;
+use lib_mal::prelude::{AnimeDetails, ListStatus};
+use lib_mal::MALClientTrait;
+use retry::{HttpError, RetryPolicy};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use time::{OffsetDateTime, Time, UtcOffset, Weekday};
+
+/// Client id of lma's own MAL API app
+const CLIENT_ID: &str = "8f7bd7e31dcf4f931949fc0b418c76d8";
+const CLIENT_ID_VAR: &str = "LMA_MAL_CLIENT_ID";
+const REDIRECT_URI_VAR: &str = "LMA_MAL_REDIRECT_URI";
+/// Biggest page MAL allows for user lists
+const USER_LIST_PAGE_SIZE: usize = 1000;
+const SEARCH_PAGE_SIZE: usize = 20;
+/// Everything a search result shows, so it takes one request per page
+const SEARCH_FIELDS: &str = "media_type,start_season,num_episodes,nsfw";
+/// Requests in flight during a sync, the rate limiter still has the final say
+const MAX_CONCURRENT_REQUESTS: usize = 4;
+const RATE_LIMIT_BURST: u32 = 5;
+const RATE_LIMIT_PER_SECOND: f64 = 2.0;
+/// Searches skip lib-mal, it can't ask for fields or an offset
+const API_URL: &str = "https://api.myanimelist.net/v2";
+/// Logins skip lib-mal too, so the tokens stay with lma
+const OAUTH_URL: &str = "https://myanimelist.net/v1/oauth2";
+/// Episodes of long shows past the first page, seasons and broadcast times come straight from Jikan
+const JIKAN_URL: &str = "https://api.jikan.moe/v4";
+const JIKAN_PAGE_SIZE: usize = 100;
+const JIKAN_SEASON_PAGE_SIZE: usize = 25;
+const JIKAN_RATE_LIMIT_BURST: u32 = 3;
+const JIKAN_RATE_LIMIT_PER_SECOND: f64 = 1.0;
+
+/// MAL API app to log in with, environment variables win over these
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Debug)]
+#[serde(default)]
+pub struct MalApp {
+    /// lma's own app when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Has to be one of the app's redirect URIs, localhost with the redirect port when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_uri: Option<String>,
+}
+
+impl MalApp {
+    fn client_id(&self) -> String {
+        env::var(CLIENT_ID_VAR)
+            .ok()
+            .or_else(|| self.client_id.clone())
+            .filter(|client_id| !client_id.trim().is_empty())
+            .unwrap_or_else(|| CLIENT_ID.to_owned())
+    }
+
+    fn redirect_uri(&self) -> Option<String> {
+        env::var(REDIRECT_URI_VAR)
+            .ok()
+            .or_else(|| self.redirect_uri.clone())
+            .filter(|redirect_uri| !redirect_uri.trim().is_empty())
+    }
+}
 
 pub struct MAL<T> {
     client: T,
+    cache_dir: PathBuf,
+    tokens: Option<Tokens>,
+    token_store: Credentials,
+    client_id: String,
+    /// PKCE code verifier of the login, MAL only supports the plain method
     challenge: String,
     state: String,
     url: Option<String>,
+    redirect_uri: Option<String>,
+    redirect_port: u16,
+    rate_limiter: TokenBucket,
+    retry: RetryPolicy,
+    http: reqwest::Client,
+    api_url: String,
+    oauth_url: String,
+    jikan_url: String,
+    jikan_rate_limiter: TokenBucket,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Tokens {
+    access_token: String,
+    refresh_token: String,
+    /// Unix time the access token stops working
+    expires_at: i64,
+}
+
+impl Tokens {
+    fn expired(&self) -> bool {
+        OffsetDateTime::now_utc().unix_timestamp() >= self.expires_at
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: String,
+    expires_in: i64,
+}
+
+#[derive(Deserialize)]
+struct MalSearch {
+    data: Vec<MalSearchNode>,
+    paging: MalPaging,
+}
+
+#[derive(Deserialize)]
+struct MalPaging {
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MalSearchNode {
+    node: MalSearchAnime,
+}
+
+#[derive(Deserialize)]
+struct MalSearchAnime {
+    id: usize,
+    title: String,
+    media_type: Option<String>,
+    start_season: Option<MalSeason>,
+    num_episodes: Option<usize>,
+    nsfw: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MalSeason {
+    year: u32,
+}
+
+#[derive(Deserialize)]
+struct JikanEpisodes {
+    pagination: JikanPagination,
+    data: Vec<JikanEpisode>,
+}
+
+#[derive(Deserialize)]
+struct JikanPagination {
+    has_next_page: bool,
+}
+
+#[derive(Deserialize)]
+struct JikanSeason {
+    pagination: JikanPagination,
+    data: Vec<JikanAnime>,
+}
+
+#[derive(Deserialize)]
+struct JikanAnimeData {
+    data: JikanAnime,
+}
+
+#[derive(Deserialize)]
+struct JikanAnime {
+    mal_id: usize,
+    title: String,
+    #[serde(rename = "type")]
+    media_type: Option<String>,
+    year: Option<u32>,
+    episodes: Option<usize>,
+    rating: Option<String>,
+    #[serde(default)]
+    airing: bool,
+    broadcast: Option<JikanBroadcast>,
+}
+
+#[derive(Deserialize)]
+struct JikanRelations {
+    data: Vec<JikanRelated>,
+}
+
+#[derive(Deserialize)]
+struct JikanRelated {
+    /// Like "Side Story"
+    relation: String,
+    entry: Vec<JikanEntry>,
+}
+
+#[derive(Deserialize)]
+struct JikanEntry {
+    mal_id: usize,
+    /// "anime" or "manga"
+    #[serde(rename = "type")]
+    kind: String,
+    name: String,
+}
+
+/// Weekly slot in Japan's time
+#[derive(Deserialize)]
+struct JikanBroadcast {
+    /// Like "Saturdays"
+    day: Option<String>,
+    /// Like "01:00"
+    time: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JikanEpisode {
+    mal_id: Option<usize>,
+    title: Option<String>,
+    title_japanese: Option<String>,
+    title_romanji: Option<String>,
+    aired: Option<String>,
+    score: Option<f32>,
+    filler: Option<bool>,
+    recap: Option<bool>,
 }
 
 impl<T: MALClientTrait + Send + Sync> Service for MAL<T> {
     async fn new(cache_dir: PathBuf) -> Result<Self, String> {
-        let token = "8f7bd7e31dcf4f931949fc0b418c76d8".to_string();
-        let client = ClientBuilder::new()
-            .secret(token)
-            .caching(true)
-            .cache_dir(Some(cache_dir))
-            .build_with_refresh()
-            .await
-            .map_err(|e| e.to_string())?;
-
-        Ok(Self {
-            client,
-            challenge: String::new(),
-            state: String::new(),
-            url: Some(String::new()),
-        })
+        let token_store = Credentials::plain(cache_dir.clone(), ServiceType::MAL.as_str());
+        Self::with_app(cache_dir, &MalApp::default(), token_store).await
     }
     async fn login(&mut self) -> Result<(), String> {
-        let redirect_uri = "localhost:2525";
-        self.client
-            .auth(redirect_uri, &self.challenge, &self.state)
-            .await
-            .map_err(|err| err.to_string())?;
-        self.url = None;
-        Ok(())
+        let port = self
+            .redirect_uri
+            .as_deref()
+            .and_then(listen_port)
+            .unwrap_or(self.redirect_port);
+        let listener = oauth::bind(port).await?;
+        let params = oauth::wait_for_redirect(listener, "code", &self.state).await?;
+        self.exchange_code(&params).await
+    }
+    async fn login_with_redirect(&mut self, redirect_url: &str) -> Result<(), String> {
+        let params = oauth::parse_redirect_url(redirect_url, "code", &self.state)?;
+        self.exchange_code(&params).await
     }
     async fn auth(&mut self) {
-        self.url = if self.client.need_auth() {
-            let url;
-            (url, self.challenge, self.state) = self.client.get_auth_parts();
-            Some(url)
-        } else {
-            None
+        if self.is_logged_in() {
+            self.url = None;
+            return;
         }
+        self.state = oauth::new_state();
+        // plain PKCE, the verifier is sent again with the code
+        self.challenge = oauth::new_state() + &oauth::new_state();
+        let mut params = vec![
+            ("response_type", "code"),
+            ("client_id", self.client_id.as_str()),
+            ("code_challenge", self.challenge.as_str()),
+            ("code_challenge_method", "plain"),
+            ("state", self.state.as_str()),
+        ];
+        if let Some(redirect_uri) = &self.redirect_uri {
+            params.push(("redirect_uri", redirect_uri));
+        }
+        self.url =
+            reqwest::Url::parse_with_params(&format!("{}/authorize", self.oauth_url), params)
+                .ok()
+                .map(String::from);
     }
     async fn init_show(&mut self, id: usize) -> Result<(), String> {
         if self.get_user_entry_details(id).await?.is_none() {
             // add to plan to watch
             let mut update = StatusUpdate::new();
             update.status(Status::PlanToWatch);
-            self.client
-                .update_user_anime_status(id, update)
-                .await
-                .map_err(|err| format!("Update user's list: {err}"))?;
+            self.update_status(id, update).await?;
         }
         Ok(())
     }
-    async fn search_title(&mut self, potential_title: &str) -> Result<Vec<ServiceTitle>, String> {
-        // what does it do when it returns 0 results?
-        // pad titles below 3 characters to avoid errors
-        let mut padded_title = String::from(potential_title);
-        while padded_title.len() < 3 {
-            padded_title += r"\&nbsp";
+    async fn search_title(
+        &mut self,
+        potential_title: &str,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        if potential_title.trim().chars().count() < 3 {
+            return Err(String::from(
+                "MAL needs at least 3 characters to search, type in the id instead",
+            ));
         }
-        Ok(self
-            .client
-            .get_anime_list(&padded_title, 20)
-            .await
-            .map_err(|err| format!("MAL search result: {err}"))?
-            .data
-            .iter()
-            .map(|entry| ServiceTitle {
-                service_id: entry.node.id,
-                title: entry.node.title.to_string(),
+        let page_size = SEARCH_PAGE_SIZE.to_string();
+        let offset_param = offset.to_string();
+        let query = [
+            ("q", potential_title),
+            ("limit", &page_size),
+            ("offset", &offset_param),
+            ("fields", SEARCH_FIELDS),
+            ("nsfw", "true"),
+        ];
+        let found: MalSearch = self
+            .retry
+            .run(|| async {
+                self.rate_limiter.acquire().await;
+                let response = self
+                    .http
+                    .get(format!("{}/anime", self.api_url))
+                    .header("X-MAL-CLIENT-ID", &self.client_id)
+                    .query(&query)
+                    .send()
+                    .await?;
+                retry::check_status(response)?
+                    .json()
+                    .await
+                    .map_err(HttpError::from)
             })
-            .collect())
+            .await
+            .map_err(|err| format!("MAL search result: {err}"))?;
+        let next_offset = found
+            .paging
+            .next
+            .is_some()
+            .then_some(offset + found.data.len());
+        Ok(ServiceSearchPage {
+            titles: found
+                .data
+                .into_iter()
+                .map(|entry| search_result(entry.node))
+                .collect(),
+            next_offset,
+        })
     }
     async fn get_title(&mut self, id: usize) -> Result<String, String> {
         Ok(self
-            .client
-            .get_anime_details(id, AnimeFields::Title)
+            .retry
+            .run(|| self.client.get_anime_details(id, AnimeFields::Title))
             .await
             .map_err(|err| format!("Anime title: {err}"))?
             .show
             .title)
     }
+    async fn get_title_details(&mut self, id: usize) -> Result<ServiceTitle, String> {
+        let details = self
+            .retry
+            .run(|| self.client.get_anime_details(id, AnimeFields::ALL))
+            .await
+            .map_err(|err| format!("Anime details: {err}"))?;
+        Ok(service_title(id, &details))
+    }
     async fn get_alternative_titles(
         &mut self,
         id: usize,
     ) -> Result<Option<AlternativeTitles>, String> {
         Ok(self
-            .client
-            .get_anime_details(id, AnimeFields::AlternativeTitles)
+            .retry
+            .run(|| {
+                self.client
+                    .get_anime_details(id, AnimeFields::AlternativeTitles)
+            })
             .await
             .map_err(|err| format!("Alternative anime titles: {err}"))?
             .alternative_titles
@@ -110,8 +364,8 @@ impl<T: MALClientTrait + Send + Sync> Service for MAL<T> {
     }
     async fn get_episode_count(&mut self, id: usize) -> Result<Option<usize>, String> {
         Ok(self
-            .client
-            .get_anime_details(id, AnimeFields::NumEpisodes)
+            .retry
+            .run(|| self.client.get_anime_details(id, AnimeFields::NumEpisodes))
             .await
             .map_err(|err| format!("Anime episode count: {err}"))?
             .num_episodes)
@@ -120,51 +374,108 @@ impl<T: MALClientTrait + Send + Sync> Service for MAL<T> {
         &mut self,
         id: usize,
     ) -> Result<Option<ServiceEpisodeUser>, String> {
-        Ok(self
-            .client
-            .get_anime_details(id, AnimeFields::MyListStatus)
+        self.fetch_user_entry(id).await
+    }
+    async fn get_user_entries_details(
+        &mut self,
+        ids: &[usize],
+        progress: &SyncProgress,
+    ) -> Result<RemoteEntries, String> {
+        let this = &*self;
+        let mut requests = stream::iter(ids.iter().copied())
+            .map(|id| async move { (id, this.fetch_user_entry(id).await) })
+            .buffer_unordered(MAX_CONCURRENT_REQUESTS);
+        let mut entries = RemoteEntries::new();
+        while let Some((id, entry)) = requests.next().await {
+            progress.finish_one(id);
+            if let Some(entry) = entry? {
+                entries.insert(id, entry);
+            }
+        }
+        Ok(entries)
+    }
+    async fn get_user_list(&mut self, offset: usize) -> Result<ServiceListPage, String> {
+        let list = self
+            .retry
+            .run(|| self.client.get_user_anime_list(offset, USER_LIST_PAGE_SIZE))
             .await
-            .map_err(|err| format!("Anime details: {err}"))?
-            .my_list_status
-            .map(|episode_status| ServiceEpisodeUser {
-                status: to_episode_status(episode_status.status),
-                progress: episode_status.num_episodes_watched,
-                score: episode_status.score,
-                is_rewatching: episode_status.is_rewatching,
-                rewatch_count: episode_status.num_times_rewatched,
-                updated_at: episode_status.updated_at,
-                start_date: episode_status.start_date,
-                finish_date: episode_status.finish_date,
-                comments: episode_status.comments,
-            }))
+            .map_err(|err| format!("User's list: {err}"))?;
+        let next_offset = list
+            .paging
+            .next
+            .is_some()
+            .then_some(offset + list.data.len());
+        let entries = list
+            .data
+            .into_iter()
+            .map(|entry| ServiceListEntry {
+                service_id: entry.node.id,
+                title: entry.node.title,
+                user: entry.list_status.map(to_service_user).unwrap_or_default(),
+            })
+            .collect();
+        Ok(ServiceListPage {
+            entries,
+            next_offset,
+        })
     }
     async fn get_episodes(
         &mut self,
         id: usize,
         precise_score: bool,
     ) -> Result<Vec<ServiceEpisodeDetails>, String> {
-        self.client
-            .get_anime_episodes(id, precise_score)
+        let mut episodes: Vec<_> = self
+            .retry
+            .run(|| self.client.get_anime_episodes(id, precise_score))
             .await
-            .map(|episodes| episodes.data)
-            .map(|vec| {
-                vec.into_iter()
-                    .map(|episode| ServiceEpisodeDetails {
-                        number: episode.mal_id,
-                        title: episode.title,
-                        title_japanese: episode.title_japanese,
-                        title_romanji: episode.title_romanji,
-                        duration: episode.duration,
-                        aired: episode.aired,
-                        score: episode.score,
-                        filler: episode.filler,
-                        recap: episode.recap,
-                    })
-                    .collect::<Vec<_>>()
+            .map_err(|err| format!("Get episodes: {err}"))?
+            .data
+            .into_iter()
+            .map(|episode| ServiceEpisodeDetails {
+                number: episode.mal_id,
+                title: episode.title,
+                title_japanese: episode.title_japanese,
+                title_romanji: episode.title_romanji,
+                duration: episode.duration,
+                aired: episode.aired,
+                score: episode.score,
+                filler: episode.filler,
+                recap: episode.recap,
             })
-            .map_err(|err| format!("Get episodes: {err}"))
+            .collect();
+        // the client only returns the first page
+        if episodes.len() >= JIKAN_PAGE_SIZE {
+            episodes.extend(self.fetch_remaining_episodes(id).await?);
+        }
+        Ok(episodes)
+    }
+    async fn update_user_entry(
+        &mut self,
+        id: usize,
+        entry: &ServiceEpisodeUser,
+    ) -> Result<(), String> {
+        let mut update = StatusUpdate::new();
+        if let Some(status) = entry.status.and_then(to_mal_status) {
+            update.status(status);
+        }
+        if let Some(score) = entry.score {
+            update.score(score);
+        }
+        if let Some(start_date) = &entry.start_date {
+            update.start_date(start_date);
+        }
+        if let Some(finish_date) = &entry.finish_date {
+            update.finish_date(finish_date);
+        }
+        self.update_status(id, update).await?;
+        Ok(())
     }
-    async fn set_progress(&mut self, id: usize, progress: usize) -> Result<usize, String> {
+    async fn set_progress(
+        &mut self,
+        id: usize,
+        progress: usize,
+        episode_count: Option<usize>,
+    ) -> Result<usize, String> {
         let mut update = StatusUpdate::new();
         update.num_watched_episodes(progress);
         if progress == 0 {
@@ -181,22 +492,12 @@ impl<T: MALClientTrait + Send + Sync> Service for MAL<T> {
             update.start_date(&format!("{local_date}"));
             self.update_status(id, update).await?;
         }
-        let episode_count = self
-            .client
-            .get_anime_details(id, AnimeFields::NumEpisodes)
-            .await
-            .map_err(|err| format!("Anime details: {err}"))?
-            .num_episodes
-            .map_or(
-                usize::MAX,
-                |count| {
-                    if count == 0 {
-                        usize::MAX
-                    } else {
-                        count
-                    }
-                },
-            );
+        let episode_count = match episode_count {
+            Some(episode_count) => Some(episode_count),
+            None => self.get_episode_count(id).await?,
+        }
+        .filter(|count| *count != 0)
+        .unwrap_or(usize::MAX);
         let actual_progress = updated_status.num_episodes_watched.unwrap_or(progress);
         if actual_progress >= episode_count {
             let mut update = StatusUpdate::new();
@@ -212,44 +513,449 @@ impl<T: MALClientTrait + Send + Sync> Service for MAL<T> {
     fn get_service_type(&self) -> ServiceType {
         ServiceType::MAL
     }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::ALL
+    }
     fn is_logged_in(&self) -> bool {
         !self.client.need_auth()
     }
+    async fn get_username(&mut self) -> Result<Option<String>, String> {
+        if !self.is_logged_in() {
+            return Ok(None);
+        }
+        self.rate_limiter.acquire().await;
+        let user = self
+            .client
+            .get_my_user_info()
+            .await
+            .map_err(|err| format!("MAL user: {err}"))?;
+        Ok(Some(user.name))
+    }
+    async fn logout(&mut self) -> Result<(), String> {
+        self.token_store.delete()?;
+        self.tokens = None;
+        // lib-mal can't forget its token, so it gets a new client without one
+        self.reset_client();
+        self.url = Some(String::new());
+        Ok(())
+    }
+    async fn get_season(
+        &mut self,
+        season: Season,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        let page = offset / JIKAN_SEASON_PAGE_SIZE + 1;
+        let response: JikanSeason = self
+            .jikan(
+                &format!(
+                    "/seasons/{}/{}",
+                    season.year,
+                    season.name.as_str().to_lowercase()
+                ),
+                &[("page", page)],
+            )
+            .await
+            .map_err(|err| format!("Season {season}: {err}"))?;
+        let next_offset = response
+            .pagination
+            .has_next_page
+            .then_some(page * JIKAN_SEASON_PAGE_SIZE);
+        let titles = response
+            .data
+            .into_iter()
+            .map(|anime| ServiceTitle {
+                service_id: anime.mal_id,
+                title: anime.title,
+                media_type: anime.media_type.as_deref().map(MediaType::parse),
+                year: anime.year,
+                episodes: anime.episodes,
+                nsfw: anime.rating.is_some_and(|rating| rating.starts_with("Rx")),
+            })
+            .collect();
+        Ok(ServiceSearchPage {
+            titles,
+            next_offset,
+        })
+    }
+    async fn get_airing_schedule(&mut self, ids: &[usize]) -> Result<Vec<ServiceAiring>, String> {
+        // MAL only knows the weekly slot, not which episode airs in it
+        let now = OffsetDateTime::now_utc();
+        let mut schedule = Vec::new();
+        for id in ids {
+            let anime = self
+                .jikan::<JikanAnimeData>(&format!("/anime/{id}"), &[])
+                .await
+                .map_err(|err| format!("Airing schedule: {err}"))?
+                .data;
+            let airs_at = anime
+                .broadcast
+                .filter(|_| anime.airing)
+                .and_then(|broadcast| next_broadcast(&broadcast.day?, &broadcast.time?, now));
+            if let Some(airs_at) = airs_at {
+                schedule.push(ServiceAiring {
+                    service_id: *id,
+                    episode: None,
+                    airs_at,
+                });
+            }
+        }
+        Ok(schedule)
+    }
+    async fn get_relations(&mut self, id: usize) -> Result<Vec<ServiceRelation>, String> {
+        let response: JikanRelations = self
+            .jikan(&format!("/anime/{id}/relations"), &[])
+            .await
+            .map_err(|err| format!("Related anime: {err}"))?;
+        // Jikan only has the titles, manga are related too
+        Ok(response
+            .data
+            .into_iter()
+            .flat_map(|related| {
+                let relation = RelationType::parse(&related.relation);
+                related
+                    .entry
+                    .into_iter()
+                    .filter(|entry| entry.kind == "anime")
+                    .map(move |entry| ServiceRelation {
+                        relation,
+                        show: ServiceTitle {
+                            service_id: entry.mal_id,
+                            title: entry.name,
+                            ..Default::default()
+                        },
+                    })
+            })
+            .collect())
+    }
+    fn set_redirect_port(&mut self, port: u16) {
+        self.redirect_port = port;
+    }
     fn get_url(&self) -> Option<String> {
         self.url.clone()
     }
 }
 
+fn service_title(id: usize, details: &AnimeDetails) -> ServiceTitle {
+    ServiceTitle {
+        service_id: id,
+        title: details.show.title.clone(),
+        media_type: details.media_type.as_deref().map(MediaType::parse),
+        year: details.start_season.as_ref().map(|season| season.year),
+        episodes: details.num_episodes.filter(|episodes| *episodes > 0),
+        nsfw: details.nsfw.as_deref() == Some("black"),
+    }
+}
+
+fn search_result(anime: MalSearchAnime) -> ServiceTitle {
+    ServiceTitle {
+        service_id: anime.id,
+        title: anime.title,
+        media_type: anime.media_type.as_deref().map(MediaType::parse),
+        year: anime.start_season.map(|season| season.year),
+        episodes: anime.num_episodes.filter(|episodes| *episodes > 0),
+        nsfw: anime.nsfw.as_deref() == Some("black"),
+    }
+}
+
 impl<T: MALClientTrait + Send + Sync> MAL<T> {
+    /// Keeps the tokens in `token_store` instead of the data directory
+    pub async fn with_app(
+        cache_dir: PathBuf,
+        app: &MalApp,
+        token_store: Credentials,
+    ) -> Result<Self, String> {
+        let saved = token_store.load()?;
+        let tokens = saved
+            .as_deref()
+            .and_then(|tokens| serde_json::from_str(tokens).ok());
+        if saved.is_some() && tokens.is_none() {
+            // saved by lib-mal in earlier versions, those need a new login
+            token_store.delete()?;
+        }
+        let mut mal = Self::build(cache_dir, app, tokens, token_store);
+        if mal.tokens.as_ref().is_some_and(Tokens::expired) {
+            // log in again if the refresh fails
+            if mal.refresh().await.is_err() {
+                mal.tokens = None;
+                mal.reset_client();
+            }
+        }
+        Ok(mal)
+    }
+
+    fn build(
+        cache_dir: PathBuf,
+        app: &MalApp,
+        tokens: Option<Tokens>,
+        token_store: Credentials,
+    ) -> Self {
+        let client_id = app.client_id();
+        Self {
+            client: Self::new_client(&client_id, &cache_dir, tokens.as_ref()),
+            cache_dir,
+            tokens,
+            token_store,
+            client_id,
+            challenge: String::new(),
+            state: String::new(),
+            url: Some(String::new()),
+            redirect_uri: app.redirect_uri(),
+            redirect_port: oauth::DEFAULT_REDIRECT_PORT,
+            rate_limiter: TokenBucket::new(RATE_LIMIT_BURST, RATE_LIMIT_PER_SECOND),
+            retry: RetryPolicy::default(),
+            http: reqwest::Client::new(),
+            api_url: API_URL.to_owned(),
+            oauth_url: OAUTH_URL.to_owned(),
+            jikan_url: JIKAN_URL.to_owned(),
+            jikan_rate_limiter: TokenBucket::new(
+                JIKAN_RATE_LIMIT_BURST,
+                JIKAN_RATE_LIMIT_PER_SECOND,
+            ),
+        }
+    }
+
+    /// lib-mal client with the access token, it doesn't save or refresh it itself
+    fn new_client(client_id: &str, cache_dir: &Path, tokens: Option<&Tokens>) -> T {
+        T::new(
+            client_id.to_owned(),
+            cache_dir.to_owned(),
+            tokens.map_or_else(String::new, |tokens| tokens.access_token.clone()),
+            reqwest::Client::new(),
+            false,
+            tokens.is_none(),
+        )
+    }
+
+    fn reset_client(&mut self) {
+        self.client = Self::new_client(&self.client_id, &self.cache_dir, self.tokens.as_ref());
+    }
+
+    async fn refresh(&mut self) -> Result<(), String> {
+        let refresh_token = self
+            .tokens
+            .as_ref()
+            .map(|tokens| tokens.refresh_token.clone())
+            .ok_or("Not logged in")?;
+        self.request_tokens(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &refresh_token),
+        ])
+        .await
+    }
+
+    async fn exchange_code(&mut self, params: &HashMap<String, String>) -> Result<(), String> {
+        let code = params.get("code").ok_or("MAL didn't return a code")?;
+        let challenge = self.challenge.clone();
+        let redirect_uri = self.redirect_uri.clone();
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("code_verifier", challenge.as_str()),
+        ];
+        if let Some(redirect_uri) = &redirect_uri {
+            form.push(("redirect_uri", redirect_uri));
+        }
+        self.request_tokens(&form).await?;
+        self.url = None;
+        Ok(())
+    }
+
+    async fn request_tokens(&mut self, form: &[(&str, &str)]) -> Result<(), String> {
+        let client_id = self.client_id.clone();
+        let mut form = form.to_vec();
+        form.push(("client_id", &client_id));
+        let response = self
+            .http
+            .post(format!("{}/token", self.oauth_url))
+            .form(&form)
+            .send()
+            .await
+            .map_err(|err| format!("MAL login: {err}"))?;
+        if !response.status().is_success() {
+            return Err(format!("MAL login: {}", response.status()));
+        }
+        let response: TokenResponse = response
+            .json()
+            .await
+            .map_err(|err| format!("MAL login: {err}"))?;
+        let tokens = Tokens {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+            expires_at: OffsetDateTime::now_utc().unix_timestamp() + response.expires_in,
+        };
+        let serialized = serde_json::to_string(&tokens).map_err(|err| err.to_string())?;
+        self.token_store.save(&serialized)?;
+        self.tokens = Some(tokens);
+        self.reset_client();
+        Ok(())
+    }
+
+    async fn fetch_user_entry(&self, id: usize) -> Result<Option<ServiceEpisodeUser>, String> {
+        Ok(self
+            .retry
+            .run(|| async {
+                // retries wait for the limiter too
+                self.rate_limiter.acquire().await;
+                self.client
+                    .get_anime_details(id, AnimeFields::MyListStatus)
+                    .await
+            })
+            .await
+            .map_err(|err| format!("Anime details: {err}"))?
+            .my_list_status
+            .map(to_service_user))
+    }
+
+    /// Episodes from the second page on, without the duration that only the first page has
+    async fn fetch_remaining_episodes(
+        &self,
+        id: usize,
+    ) -> Result<Vec<ServiceEpisodeDetails>, String> {
+        let mut episodes = Vec::new();
+        let mut page = Some(2);
+        while let Some(current_page) = page {
+            let response: JikanEpisodes = self
+                .jikan(&format!("/anime/{id}/episodes"), &[("page", current_page)])
+                .await
+                .map_err(|err| format!("Get episodes, page {current_page}: {err}"))?;
+            page = response
+                .pagination
+                .has_next_page
+                .then_some(current_page + 1);
+            episodes.extend(
+                response
+                    .data
+                    .into_iter()
+                    .map(|episode| ServiceEpisodeDetails {
+                        number: episode.mal_id,
+                        title: episode.title,
+                        title_japanese: episode.title_japanese,
+                        title_romanji: episode.title_romanji,
+                        duration: None,
+                        aired: episode.aired,
+                        score: episode.score,
+                        filler: episode.filler,
+                        recap: episode.recap,
+                    }),
+            );
+        }
+        Ok(episodes)
+    }
+
+    /// GET from Jikan within its rate limit
+    async fn jikan<R: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, usize)],
+    ) -> Result<R, String> {
+        self.retry
+            .run(|| async {
+                self.jikan_rate_limiter.acquire().await;
+                let response = self
+                    .http
+                    .get(format!("{}{path}", self.jikan_url))
+                    .query(query)
+                    .send()
+                    .await?;
+                retry::check_status(response)?
+                    .json()
+                    .await
+                    .map_err(HttpError::from)
+            })
+            .await
+    }
+
     async fn update_status(
         &mut self,
         id: usize,
         update: StatusUpdate,
     ) -> Result<ListStatus, String> {
-        self.client
-            .update_user_anime_status(id, update)
+        self.retry
+            .run(|| self.client.update_user_anime_status(id, update.clone()))
             .await
             .map_err(|err| format!("Update user's list: {err}"))
     }
 }
 
-fn to_episode_status(status: Option<String>) -> Option<EpisodeStatus> {
-    status.map(|status_str| match status_str.as_str() {
-        "watching" => EpisodeStatus::Watching,
-        "completed" => EpisodeStatus::Completed,
-        "on_hold" => EpisodeStatus::OnHold,
-        "dropped" => EpisodeStatus::Dropped,
-        "plan_to_watch" => EpisodeStatus::PlanToWatch,
-        _ => EpisodeStatus::None,
+/// Next time the weekly slot comes up after `now`, `day` is like "Saturdays" and `time` like "01:00" in Japan
+fn next_broadcast(day: &str, time: &str, now: OffsetDateTime) -> Option<OffsetDateTime> {
+    let day = day.to_lowercase();
+    let weekday = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ]
+    .into_iter()
+    .find(|weekday| day.starts_with(&weekday.to_string().to_lowercase()))?;
+    let (hour, minute) = time.split_once(':')?;
+    let time = Time::from_hms(hour.trim().parse().ok()?, minute.trim().parse().ok()?, 0).ok()?;
+    let japan = UtcOffset::from_hms(9, 0, 0).ok()?;
+    let today = now.to_offset(japan);
+    let days_ahead =
+        (7 + weekday.number_days_from_monday() - today.weekday().number_days_from_monday()) % 7;
+    let airs_at = (today.date() + time::Duration::days(days_ahead.into()))
+        .with_time(time)
+        .assume_offset(japan);
+    Some(if airs_at <= now {
+        airs_at + time::Duration::weeks(1)
+    } else {
+        airs_at
     })
 }
 
+/// Port of the redirect URI, lma listens on it for the redirect
+fn listen_port(redirect_uri: &str) -> Option<u16> {
+    let address = redirect_uri
+        .split_once("://")
+        .map_or(redirect_uri, |(_, address)| address);
+    let host = address.split('/').next().unwrap_or_default();
+    host.rsplit_once(':')?.1.parse().ok()
+}
+
+fn to_service_user(list_status: ListStatus) -> ServiceEpisodeUser {
+    ServiceEpisodeUser {
+        status: to_episode_status(list_status.status),
+        progress: list_status.num_episodes_watched,
+        score: list_status.score,
+        is_rewatching: list_status.is_rewatching,
+        rewatch_count: list_status.num_times_rewatched,
+        updated_at: list_status.updated_at,
+        start_date: list_status.start_date,
+        finish_date: list_status.finish_date,
+        comments: list_status.comments,
+    }
+}
+
+fn to_episode_status(status: Option<String>) -> Option<EpisodeStatus> {
+    status.map(|status_str| EpisodeStatus::parse(&status_str))
+}
+
+const fn to_mal_status(status: EpisodeStatus) -> Option<Status> {
+    match status {
+        EpisodeStatus::Watching => Some(Status::Watching),
+        EpisodeStatus::Completed => Some(Status::Completed),
+        EpisodeStatus::OnHold => Some(Status::OnHold),
+        EpisodeStatus::Dropped => Some(Status::Dropped),
+        EpisodeStatus::PlanToWatch => Some(Status::PlanToWatch),
+        EpisodeStatus::None => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use lib_mal::MockMALClient;
+    use mockito::{Matcher, Server};
     use reqwest::Client;
+    use serde_json::json;
 
     use super::*;
+    use crate::SeasonName;
+    use std::time::Duration;
+    use time::macros::datetime;
 
     #[tokio::test]
     async fn test_init_show() {
@@ -262,18 +968,41 @@ mod tests {
 
     #[tokio::test]
     async fn test_search() {
+        let mut server = Server::new_async().await;
+        let search = server
+            .mock("GET", "/anime")
+            .match_header("X-MAL-CLIENT-ID", "client_secret")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("q".into(), "evangelion".into()),
+                Matcher::UrlEncoded("offset".into(), "20".into()),
+                Matcher::UrlEncoded("fields".into(), SEARCH_FIELDS.into()),
+            ]))
+            .with_body(include_str!("../../fixtures/mal/search.json"))
+            .expect(1)
+            .create_async()
+            .await;
         let mut client = create_logged_in_client().await;
-        let search_result = client.search_title("doesn't matter").await;
+        client.api_url = server.url();
+        let search_result = client.search_title("evangelion", 20).await;
         assert!(search_result.is_ok());
+        search.assert_async().await;
 
-        let search_vec = search_result.unwrap();
+        let search_page = search_result.unwrap();
+        assert_eq!(search_page.next_offset, Some(23));
+        let search_vec = search_page.titles;
         assert_eq!(search_vec.len(), 3);
+        assert_eq!(search_vec[0].media_type, Some(MediaType::TV));
+        assert_eq!(search_vec[1].year, Some(1997));
 
         let last_opt = search_vec.get(2);
         assert!(last_opt.is_some());
 
         let last = last_opt.unwrap();
         assert_eq!(last.service_id, 459);
+        assert_eq!(last.episodes, None, "0 means unknown");
+
+        let search_result = client.search_title("ab", 0).await;
+        assert!(search_result.is_err(), "Too short for MAL");
     }
 
     #[tokio::test]
@@ -285,6 +1014,15 @@ mod tests {
         assert_eq!(title, "One Piece");
     }
 
+    #[tokio::test]
+    async fn test_get_title_details() {
+        let mut client = create_logged_in_client().await;
+        let details = client.get_title_details(30230).await.unwrap();
+        assert_eq!(details.title, "Diamond no Ace: Second Season");
+        assert_eq!(details.episodes, Some(51));
+        assert!(client.get_title_details(1).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_get_episode_count() {
         let mut client = create_logged_in_client().await;
@@ -324,6 +1062,74 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_update_user_entry() {
+        let mut client = create_logged_in_client().await;
+        let entry = ServiceEpisodeUser {
+            status: Some(EpisodeStatus::Completed),
+            score: Some(8),
+            finish_date: Some(String::from("2023-01-01")),
+            ..Default::default()
+        };
+        let result = client.update_user_entry(30230, &entry).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_get_user_entries_details() {
+        let mut client = create_logged_in_client().await;
+        let progress = SyncProgress::default();
+        progress.start(2);
+        let entries = client
+            .get_user_entries_details(&[21, 30230], &progress)
+            .await
+            .expect("Entries");
+        assert_eq!(progress.done(), 2);
+        assert!(!entries.contains_key(&21), "Not on the user's list");
+        assert_eq!(
+            entries.get(&30230).and_then(|entry| entry.status),
+            Some(EpisodeStatus::PlanToWatch)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_user_list() {
+        let mut client = create_logged_in_client().await;
+        let mut entries = Vec::new();
+        let mut offset = Some(0);
+        while let Some(current) = offset {
+            let page = client.get_user_list(current).await.expect("List page");
+            offset = page.next_offset;
+            entries.extend(page.entries);
+        }
+        assert!(!entries.is_empty());
+        assert!(entries
+            .iter()
+            .all(|entry| entry.service_id != 0 && !entry.title.is_empty()));
+    }
+
+    #[test]
+    fn test_mal_app() {
+        assert_eq!(MalApp::default().client_id(), CLIENT_ID);
+        let app = MalApp {
+            client_id: Some(String::from("own_client")),
+            redirect_uri: Some(String::from("http://127.0.0.1:8080/callback")),
+        };
+        assert_eq!(app.client_id(), "own_client");
+        assert_eq!(listen_port(&app.redirect_uri().unwrap()), Some(8080));
+        assert_eq!(listen_port("localhost:2525"), Some(2525));
+        assert_eq!(listen_port("https://example.com/callback"), None);
+    }
+
+    #[test]
+    fn test_status_conversion() {
+        assert_eq!(
+            to_episode_status(Some(String::from("on_hold"))),
+            Some(EpisodeStatus::OnHold)
+        );
+        assert!(to_mal_status(EpisodeStatus::None).is_none());
+    }
+
     #[tokio::test]
     async fn test_get_anime_episodes() {
         let mut client = create_logged_in_client().await;
@@ -334,12 +1140,151 @@ mod tests {
         assert!(episodes.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_fetch_remaining_episodes() {
+        let mut server = Server::new_async().await;
+        let page = |number: usize, has_next_page: bool| {
+            json!({
+                "pagination": { "last_visible_page": 3, "has_next_page": has_next_page },
+                "data": [{
+                    "mal_id": number * 100 + 1,
+                    "title": format!("Episode {}", number * 100 + 1),
+                    "title_japanese": null,
+                    "title_romanji": null,
+                    "aired": "2001-01-01T00:00:00+00:00",
+                    "score": 4.5,
+                    "filler": number == 2,
+                    "recap": false
+                }]
+            })
+            .to_string()
+        };
+        let second = server
+            .mock("GET", "/anime/21/episodes")
+            .match_query(Matcher::UrlEncoded(String::from("page"), String::from("2")))
+            .with_body(page(1, true))
+            .create_async()
+            .await;
+        let third = server
+            .mock("GET", "/anime/21/episodes")
+            .match_query(Matcher::UrlEncoded(String::from("page"), String::from("3")))
+            .with_body(page(2, false))
+            .create_async()
+            .await;
+        let mut client = create_logged_in_client().await;
+        client.jikan_url = server.url();
+        let episodes = client.fetch_remaining_episodes(21).await.expect("Episodes");
+        second.assert_async().await;
+        third.assert_async().await;
+        assert_eq!(
+            episodes
+                .iter()
+                .map(|episode| episode.number)
+                .collect::<Vec<_>>(),
+            [Some(101), Some(201)]
+        );
+        assert_eq!(episodes[1].filler, Some(true));
+        assert_eq!(episodes[0].title.as_deref(), Some("Episode 101"));
+    }
+
+    #[tokio::test]
+    async fn test_season() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/seasons/2023/fall")
+            .match_query(Matcher::UrlEncoded(String::from("page"), String::from("2")))
+            .with_body(include_str!("../../fixtures/jikan/season.json"))
+            .create_async()
+            .await;
+        let mut client = create_logged_in_client().await;
+        client.jikan_url = server.url();
+        let season = Season::new(SeasonName::Fall, 2023);
+        let result = client.get_season(season, 25).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(result.next_offset, Some(50));
+        assert_eq!(result.titles.len(), 2);
+        assert_eq!(result.titles[0].service_id, 52991);
+        assert_eq!(result.titles[0].media_type, Some(MediaType::TV));
+        assert!(!result.titles[1].nsfw);
+    }
+
+    #[tokio::test]
+    async fn test_airing_schedule() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/anime/52991")
+            .with_body(include_str!("../../fixtures/jikan/anime.json"))
+            .create_async()
+            .await;
+        let mut client = create_logged_in_client().await;
+        client.jikan_url = server.url();
+        let result = client.get_airing_schedule(&[52991]).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].episode, None);
+        let airs_at = result[0].airs_at;
+        assert_eq!(airs_at.weekday(), Weekday::Friday);
+        assert_eq!((airs_at.hour(), airs_at.minute()), (23, 0));
+        assert!(airs_at > OffsetDateTime::now_utc());
+    }
+
+    #[tokio::test]
+    async fn test_relations() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/anime/37521/relations")
+            .with_body(include_str!("../../fixtures/jikan/relations.json"))
+            .create_async()
+            .await;
+        let mut client = create_logged_in_client().await;
+        client.jikan_url = server.url();
+        let result = client.get_relations(37521).await.unwrap();
+        assert_eq!(result.len(), 2, "The manga is left out");
+        assert_eq!(result[0].relation, RelationType::Sequel);
+        assert_eq!(result[0].show.service_id, 49387);
+        assert_eq!(result[0].show.title, "Vinland Saga Season 2");
+        assert_eq!(result[1].relation, RelationType::SideStory);
+    }
+
+    #[test]
+    fn test_next_broadcast() {
+        // a Friday, 12:00 in Japan
+        let now = datetime!(2023-10-27 03:00 UTC);
+        assert_eq!(
+            next_broadcast("Fridays", "23:00", now),
+            Some(datetime!(2023-10-27 23:00 +9))
+        );
+        assert_eq!(
+            next_broadcast("Fridays", "01:00", now),
+            Some(datetime!(2023-11-03 01:00 +9)),
+            "Already aired this week"
+        );
+        assert_eq!(
+            next_broadcast("Sundays", "00:55", now),
+            Some(datetime!(2023-10-29 00:55 +9))
+        );
+        assert_eq!(next_broadcast("Unknown", "23:00", now), None);
+        assert_eq!(next_broadcast("Fridays", "late", now), None);
+    }
+
+    #[tokio::test]
+    async fn test_fatal_error_not_retried() {
+        let mut client = create_logged_in_client().await;
+        client.retry.base_delay = Duration::from_secs(3600);
+        let result = tokio::time::timeout(Duration::from_secs(1), client.get_title(1)).await;
+        assert!(
+            matches!(result, Ok(Err(_))),
+            "Unknown id fails without waiting"
+        );
+    }
+
     #[tokio::test]
     async fn test_get_url_not_logged_in() {
         let mut client = generate_test_client();
         client.auth().await;
         let url = client.get_url().unwrap();
-        assert!(url.contains("https://example.com/&client_id=client_secret"));
+        assert!(url.starts_with("https://myanimelist.net/v1/oauth2/authorize?"));
+        assert!(url.contains("client_id=client_secret"));
+        assert!(url.contains(&format!("state={}", client.state)));
     }
 
     #[tokio::test]
@@ -350,9 +1295,106 @@ mod tests {
         assert!(url.is_none());
     }
 
+    #[tokio::test]
+    async fn test_login_with_redirect() {
+        let mut server = Server::new_async().await;
+        let mut client = generate_test_client();
+        client.oauth_url = server.url();
+        client.auth().await;
+        let token = server
+            .mock("POST", "/token")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded(String::from("code"), String::from("abc")),
+                Matcher::UrlEncoded(String::from("code_verifier"), client.challenge.clone()),
+                Matcher::UrlEncoded(String::from("client_id"), String::from("client_secret")),
+            ]))
+            .with_body(
+                json!({
+                    "token_type": "Bearer",
+                    "access_token": "token",
+                    "refresh_token": "refresh",
+                    "expires_in": 2_678_400
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        assert!(client
+            .get_url()
+            .is_some_and(|url| url.contains(&format!("code_challenge={}", client.challenge))));
+        assert!(client
+            .login_with_redirect("http://localhost:2525/?code=abc&state=other")
+            .await
+            .is_err());
+        client
+            .login_with_redirect(&format!(
+                "http://localhost:2525/?code=abc&state={}",
+                client.state
+            ))
+            .await
+            .unwrap();
+        token.assert_async().await;
+        assert!(client.is_logged_in());
+        assert!(client.get_url().is_none());
+        let saved = client.token_store.load().unwrap().unwrap();
+        assert!(saved.contains("refresh"));
+    }
+
+    #[tokio::test]
+    async fn test_saved_tokens() {
+        let token_store = Credentials::memory();
+        token_store.save("saved by lib-mal").unwrap();
+        let app = MalApp::default();
+        let client = MAL::<MockMALClient>::with_app(PathBuf::new(), &app, token_store.clone())
+            .await
+            .unwrap();
+        assert!(!client.is_logged_in());
+        assert_eq!(token_store.load(), Ok(None));
+        token_store
+            .save(
+                &json!({
+                    "access_token": "access",
+                    "refresh_token": "refresh",
+                    "expires_at": i64::MAX
+                })
+                .to_string(),
+            )
+            .unwrap();
+        let client = MAL::<MockMALClient>::with_app(PathBuf::new(), &app, token_store)
+            .await
+            .unwrap();
+        assert!(client.is_logged_in());
+    }
+
+    #[tokio::test]
+    async fn test_logout() {
+        let mut client = create_logged_in_client().await;
+        assert_eq!(
+            client.get_username().await,
+            Ok(Some(String::from("mal_user")))
+        );
+        client.cache_dir =
+            std::env::temp_dir().join(format!("lma_mal_logout_{}", std::process::id()));
+        std::fs::create_dir_all(&client.cache_dir).unwrap();
+        client.token_store = Credentials::plain(client.cache_dir.clone(), "MAL");
+        std::fs::write(client.cache_dir.join("tokens"), "tokens").unwrap();
+        client.logout().await.unwrap();
+        assert!(!client.is_logged_in());
+        assert!(!client.cache_dir.join("tokens").exists());
+        assert_eq!(client.get_username().await, Ok(None));
+        client.auth().await;
+        assert!(client.get_url().is_some());
+        _ = std::fs::remove_dir_all(&client.cache_dir);
+    }
+
     async fn create_logged_in_client() -> MAL<MockMALClient> {
         let mut client = generate_test_client();
-        _ = client.login().await;
+        client.tokens = Some(Tokens {
+            access_token: String::from("access"),
+            refresh_token: String::from("refresh"),
+            expires_at: i64::MAX,
+        });
+        client.reset_client();
         client
     }
 
@@ -366,9 +1408,25 @@ mod tests {
                 false,
                 true,
             ),
+            cache_dir: PathBuf::new(),
+            tokens: None,
+            token_store: Credentials::memory(),
+            client_id: String::from("client_secret"),
             challenge: String::new(),
             state: String::new(),
             url: Some(String::new()),
+            redirect_uri: None,
+            redirect_port: oauth::DEFAULT_REDIRECT_PORT,
+            rate_limiter: TokenBucket::new(RATE_LIMIT_BURST, RATE_LIMIT_PER_SECOND),
+            retry: RetryPolicy::default(),
+            http: Client::new(),
+            api_url: API_URL.to_owned(),
+            oauth_url: OAUTH_URL.to_owned(),
+            jikan_url: JIKAN_URL.to_owned(),
+            jikan_rate_limiter: TokenBucket::new(
+                JIKAN_RATE_LIMIT_BURST,
+                JIKAN_RATE_LIMIT_PER_SECOND,
+            ),
         }
     }
 }
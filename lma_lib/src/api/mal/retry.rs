@@ -0,0 +1,367 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorKind {
+    /// Rate limits, server errors and dropped connections
+    Retryable {
+        retry_after: Option<Duration>,
+    },
+    Fatal,
+}
+
+/// Statuses worth another attempt with the reason phrase they are printed with
+const RETRYABLE_STATUSES: [(u16, &str); 5] = [
+    (429, "too many requests"),
+    (500, "internal server error"),
+    (502, "bad gateway"),
+    (503, "service unavailable"),
+    (504, "gateway timeout"),
+];
+
+/// Dropped connections as reqwest and hyper describe them
+const RETRYABLE_PHRASES: [&str; 5] = [
+    "operation timed out",
+    "connection reset",
+    "connection refused",
+    "connection closed before message completed",
+    "error sending request",
+];
+
+/// Errors that can tell if another attempt could help
+pub trait Classify: Display {
+    /// Errors that only carry a message are classified by it
+    fn kind(&self) -> ErrorKind {
+        classify(&self.to_string())
+    }
+}
+
+impl Classify for &str {}
+
+impl Classify for lib_mal::MALError {}
+
+impl Classify for reqwest::Error {
+    fn kind(&self) -> ErrorKind {
+        match self.status() {
+            Some(status) => classify_status(status.as_u16()),
+            None if self.is_timeout() || self.is_connect() => {
+                ErrorKind::Retryable { retry_after: None }
+            }
+            None => ErrorKind::Fatal,
+        }
+    }
+}
+
+/// reqwest error with the Retry-After header of the response, which the error itself drops
+#[derive(Debug)]
+pub struct HttpError {
+    error: reqwest::Error,
+    retry_after: Option<Duration>,
+}
+
+impl From<reqwest::Error> for HttpError {
+    fn from(error: reqwest::Error) -> Self {
+        Self {
+            error,
+            retry_after: None,
+        }
+    }
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl Classify for HttpError {
+    fn kind(&self) -> ErrorKind {
+        match self.error.kind() {
+            ErrorKind::Retryable { .. } => ErrorKind::Retryable {
+                retry_after: self.retry_after,
+            },
+            ErrorKind::Fatal => ErrorKind::Fatal,
+        }
+    }
+}
+
+/// Like `error_for_status`, but keeps how long the service asked to wait
+pub fn check_status(response: reqwest::Response) -> Result<reqwest::Response, HttpError> {
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs);
+    response
+        .error_for_status()
+        .map_err(|error| HttpError { error, retry_after })
+}
+
+pub fn classify_status(status: u16) -> ErrorKind {
+    if RETRYABLE_STATUSES.iter().any(|(code, _)| *code == status) {
+        ErrorKind::Retryable { retry_after: None }
+    } else {
+        ErrorKind::Fatal
+    }
+}
+
+/// Looks for a status with its reason phrase, like "503 Service Unavailable", or a dropped connection
+pub fn classify(error: &str) -> ErrorKind {
+    let error = error.to_lowercase();
+    let retryable = RETRYABLE_STATUSES
+        .iter()
+        .any(|(code, reason)| error.contains(&format!("{code} {reason}")))
+        || RETRYABLE_PHRASES
+            .iter()
+            .any(|phrase| error.contains(phrase));
+    if retryable {
+        ErrorKind::Retryable {
+            retry_after: parse_retry_after(&error),
+        }
+    } else {
+        ErrorKind::Fatal
+    }
+}
+
+/// Seconds from a Retry-After header that made it into the message
+fn parse_retry_after(error: &str) -> Option<Duration> {
+    let start = ["retry-after", "retry after"]
+        .into_iter()
+        .find_map(|header| error.find(header).map(|index| index + header.len()))?;
+    let seconds: String = error[start..]
+        .trim_start_matches(|c: char| c == ':' || c.is_whitespace())
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    seconds.parse().ok().map(Duration::from_secs)
+}
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Longer Retry-After than this fails right away instead of freezing the app
+    pub max_retry_after: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+            max_retry_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before the next attempt, None if the error shouldn't be retried
+    pub fn delay(&self, attempt: u32, kind: ErrorKind) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        match kind {
+            ErrorKind::Fatal => None,
+            ErrorKind::Retryable {
+                retry_after: Some(retry_after),
+            } => (retry_after <= self.max_retry_after).then_some(retry_after),
+            ErrorKind::Retryable { retry_after: None } => Some(
+                self.base_delay
+                    .saturating_mul(2_u32.saturating_pow(attempt - 1))
+                    .min(self.max_delay),
+            ),
+        }
+    }
+
+    pub async fn run<T, E, F, Fut>(&self, mut request: F) -> Result<T, String>
+    where
+        E: Classify,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match request().await {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    let Some(delay) = self.delay(attempt, err.kind()) else {
+                        return Err(err.to_string());
+                    };
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Server;
+    use std::cell::Cell;
+
+    fn instant_policy() -> RetryPolicy {
+        RetryPolicy {
+            base_delay: Duration::ZERO,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn classify_errors() {
+        assert_eq!(
+            classify("HTTP 503 Service Unavailable"),
+            ErrorKind::Retryable { retry_after: None }
+        );
+        assert_eq!(
+            classify("429 Too Many Requests, Retry-After: 12"),
+            ErrorKind::Retryable {
+                retry_after: Some(Duration::from_secs(12))
+            }
+        );
+        assert_eq!(classify("invalid_token"), ErrorKind::Fatal);
+        assert_eq!(classify("404 not_found"), ErrorKind::Fatal);
+        assert_eq!(
+            classify("error sending request for url (https://api.myanimelist.net/v2/anime/1)"),
+            ErrorKind::Retryable { retry_after: None }
+        );
+    }
+
+    #[test]
+    fn numbers_are_not_statuses() {
+        for error in [
+            "Anime 5029 not found",
+            "Anime 500 not found",
+            "https://myanimelist.net/anime/42900 is not a valid id",
+            "Episode 429 has no title",
+            "Can't parse timeout field",
+            "Connection to the keyring failed: locked",
+        ] {
+            assert_eq!(classify(error), ErrorKind::Fatal, "{error}");
+        }
+        assert_eq!(classify_status(404), ErrorKind::Fatal);
+        assert_eq!(
+            classify_status(502),
+            ErrorKind::Retryable { retry_after: None }
+        );
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = RetryPolicy::default();
+        let delays: Vec<_> = (1..=4)
+            .map(|attempt| policy.delay(attempt, classify_status(502)))
+            .collect();
+        assert_eq!(
+            delays,
+            [
+                Some(Duration::from_millis(500)),
+                Some(Duration::from_secs(1)),
+                Some(Duration::from_secs(2)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn backoff_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.delay(9, classify("operation timed out")),
+            Some(policy.max_delay)
+        );
+    }
+
+    #[test]
+    fn retry_after_honoured() {
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            policy.delay(1, classify("429 Too Many Requests, retry after 3")),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            policy.delay(1, classify("429 Too Many Requests, retry after 3600")),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_after_header() {
+        let mut server = Server::new_async().await;
+        let limited = server
+            .mock("GET", "/limited")
+            .with_status(429)
+            .with_header("Retry-After", "7")
+            .expect(2)
+            .create_async()
+            .await;
+        let http = reqwest::Client::new();
+        let get = || async {
+            let response = http.get(format!("{}/limited", server.url())).send().await?;
+            check_status(response).map(drop)
+        };
+        let error = get().await.unwrap_err();
+        assert_eq!(
+            RetryPolicy::default().delay(1, error.kind()),
+            Some(Duration::from_secs(7))
+        );
+
+        // too long to wait, so the request isn't sent again
+        let policy = RetryPolicy {
+            max_retry_after: Duration::from_secs(5),
+            ..Default::default()
+        };
+        assert!(policy.run(get).await.is_err());
+        limited.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn retries_until_success() {
+        let attempts = Cell::new(0);
+        let result = instant_policy()
+            .run(|| async {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < 3 {
+                    Err("500 Internal Server Error")
+                } else {
+                    Ok(attempts.get())
+                }
+            })
+            .await;
+        assert_eq!(result, Ok(3));
+    }
+
+    #[tokio::test]
+    async fn fatal_not_retried() {
+        let attempts = Cell::new(0);
+        let result: Result<(), _> = instant_policy()
+            .run(|| async {
+                attempts.set(attempts.get() + 1);
+                Err("invalid_token")
+            })
+            .await;
+        assert_eq!(result, Err(String::from("invalid_token")));
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let attempts = Cell::new(0);
+        let result: Result<(), _> = instant_policy()
+            .run(|| async {
+                attempts.set(attempts.get() + 1);
+                Err("operation timed out")
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), instant_policy().max_attempts);
+    }
+}
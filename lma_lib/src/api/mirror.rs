@@ -0,0 +1,557 @@
+use crate::{
+    AlternativeTitles, Capabilities, RemoteEntries, Season, Service, ServiceAiring,
+    ServiceEpisodeDetails, ServiceEpisodeUser, ServiceFailure, ServiceListPage, ServiceRelation,
+    ServiceSearchPage, ServiceTitle, ServiceType, SyncProgress,
+};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Uses the primary service for everything and copies changes to the user's list
+/// over to the mirror, nest it to mirror to more than one service
+pub struct Mirror<P: Service, S: Service> {
+    primary: P,
+    mirror: S,
+    /// Primary service id to the id of the same show on the mirror
+    ids: HashMap<usize, usize>,
+    ids_path: PathBuf,
+    failures: Vec<ServiceFailure>,
+}
+
+impl<P: Service, S: Service> Service for Mirror<P, S> {
+    async fn new(cache_dir: PathBuf) -> Result<Self, String> {
+        let primary = P::new(cache_dir.clone()).await?;
+        let mirror = S::new(Self::mirror_dir(&cache_dir)?).await?;
+        Ok(Self::open(primary, mirror, &cache_dir))
+    }
+    /// Logs in to one service at a time, primary first
+    async fn login(&mut self) -> Result<(), String> {
+        if self.primary.is_logged_in() {
+            self.mirror.login().await
+        } else {
+            self.primary.login().await
+        }
+    }
+    async fn auth(&mut self) {
+        self.primary.auth().await;
+        self.mirror.auth().await;
+    }
+    async fn init_show(&mut self, id: usize) -> Result<(), String> {
+        self.primary.init_show(id).await?;
+        if let Some(mirror_id) = self.mirror_id_or_fail(id).await {
+            let result = self.mirror.init_show(mirror_id).await;
+            self.record(result);
+        }
+        Ok(())
+    }
+    async fn search_title(
+        &mut self,
+        potential_title: &str,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        self.primary.search_title(potential_title, offset).await
+    }
+    async fn get_title(&mut self, id: usize) -> Result<String, String> {
+        self.primary.get_title(id).await
+    }
+    async fn get_title_details(&mut self, id: usize) -> Result<ServiceTitle, String> {
+        self.primary.get_title_details(id).await
+    }
+    async fn get_alternative_titles(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<AlternativeTitles>, String> {
+        self.primary.get_alternative_titles(id).await
+    }
+    async fn get_episodes(
+        &mut self,
+        id: usize,
+        precise_score: bool,
+    ) -> Result<Vec<ServiceEpisodeDetails>, String> {
+        self.primary.get_episodes(id, precise_score).await
+    }
+    async fn get_episode_count(&mut self, id: usize) -> Result<Option<usize>, String> {
+        self.primary.get_episode_count(id).await
+    }
+    async fn get_user_entry_details(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<ServiceEpisodeUser>, String> {
+        self.primary.get_user_entry_details(id).await
+    }
+    async fn get_user_entries_details(
+        &mut self,
+        ids: &[usize],
+        progress: &SyncProgress,
+    ) -> Result<RemoteEntries, String> {
+        self.primary.get_user_entries_details(ids, progress).await
+    }
+    async fn get_user_list(&mut self, offset: usize) -> Result<ServiceListPage, String> {
+        self.primary.get_user_list(offset).await
+    }
+    async fn update_user_entry(
+        &mut self,
+        id: usize,
+        entry: &ServiceEpisodeUser,
+    ) -> Result<(), String> {
+        self.primary.update_user_entry(id, entry).await?;
+        if let Some(mirror_id) = self.mirror_id_or_fail(id).await {
+            let result = self.mirror.update_user_entry(mirror_id, entry).await;
+            self.record(result);
+        }
+        Ok(())
+    }
+    async fn set_progress(
+        &mut self,
+        id: usize,
+        progress: usize,
+        episode_count: Option<usize>,
+    ) -> Result<usize, String> {
+        let actual_progress = self
+            .primary
+            .set_progress(id, progress, episode_count)
+            .await?;
+        if let Some(mirror_id) = self.mirror_id_or_fail(id).await {
+            // counts can differ between services so the mirror looks up its own
+            let result = self
+                .mirror
+                .set_progress(mirror_id, actual_progress, None)
+                .await;
+            self.record(result);
+        }
+        Ok(actual_progress)
+    }
+    fn get_service_type(&self) -> ServiceType {
+        self.primary.get_service_type()
+    }
+    fn capabilities(&self) -> Capabilities {
+        self.primary.capabilities()
+    }
+    fn get_url(&self) -> Option<String> {
+        if self.primary.is_logged_in() {
+            self.mirror.get_url()
+        } else {
+            self.primary.get_url()
+        }
+    }
+    fn is_logged_in(&self) -> bool {
+        self.primary.is_logged_in() && self.mirror.is_logged_in()
+    }
+    /// The account on the primary service
+    async fn get_username(&mut self) -> Result<Option<String>, String> {
+        self.primary.get_username().await
+    }
+    /// Logs out of both, the next login starts with the primary service again
+    async fn logout(&mut self) -> Result<(), String> {
+        self.primary.logout().await?;
+        self.mirror.logout().await
+    }
+    async fn get_season(
+        &mut self,
+        season: Season,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        self.primary.get_season(season, offset).await
+    }
+    async fn get_airing_schedule(&mut self, ids: &[usize]) -> Result<Vec<ServiceAiring>, String> {
+        self.primary.get_airing_schedule(ids).await
+    }
+    async fn get_relations(&mut self, id: usize) -> Result<Vec<ServiceRelation>, String> {
+        self.primary.get_relations(id).await
+    }
+    fn needs_credentials(&self) -> bool {
+        if self.primary.is_logged_in() {
+            self.mirror.needs_credentials()
+        } else {
+            self.primary.needs_credentials()
+        }
+    }
+    fn set_credentials(&mut self, username: &str, password: &str) {
+        if self.primary.is_logged_in() {
+            self.mirror.set_credentials(username, password);
+        } else {
+            self.primary.set_credentials(username, password);
+        }
+    }
+    fn set_redirect_port(&mut self, port: u16) {
+        self.primary.set_redirect_port(port);
+        self.mirror.set_redirect_port(port);
+    }
+    async fn login_with_redirect(&mut self, redirect_url: &str) -> Result<(), String> {
+        if self.primary.is_logged_in() {
+            self.mirror.login_with_redirect(redirect_url).await
+        } else {
+            self.primary.login_with_redirect(redirect_url).await
+        }
+    }
+    async fn invalidate_cache(&mut self, id: usize) -> Result<(), String> {
+        self.primary.invalidate_cache(id).await
+    }
+    fn take_failures(&mut self) -> Vec<ServiceFailure> {
+        let mut failures = std::mem::take(&mut self.failures);
+        failures.extend(self.primary.take_failures());
+        failures.extend(self.mirror.take_failures());
+        failures
+    }
+}
+
+impl<P: Service, S: Service> Mirror<P, S> {
+    /// Loads the known ids from the cache directory of the primary service
+    pub fn open(primary: P, mirror: S, cache_dir: &Path) -> Self {
+        let ids_path = cache_dir.join("mirror_ids.json");
+        let ids = fs::read_to_string(&ids_path)
+            .ok()
+            .and_then(|ids| serde_json::from_str(&ids).ok())
+            .unwrap_or_default();
+        Self::build(primary, mirror, ids, ids_path)
+    }
+    /// Each service keeps its tokens in its own directory
+    pub fn mirror_dir(cache_dir: &Path) -> Result<PathBuf, String> {
+        let mirror_dir = cache_dir.join("mirror");
+        fs::create_dir_all(&mirror_dir)
+            .map_err(|err| format!("Can't create mirror directory: {err}"))?;
+        Ok(mirror_dir)
+    }
+    pub fn build(primary: P, mirror: S, ids: HashMap<usize, usize>, ids_path: PathBuf) -> Self {
+        Self {
+            primary,
+            mirror,
+            ids,
+            ids_path,
+            failures: Vec::new(),
+        }
+    }
+
+    /// Links a show to a different one than the title search found
+    pub fn set_mirror_id(&mut self, id: usize, mirror_id: usize) -> Result<(), String> {
+        self.ids.insert(id, mirror_id);
+        let serialized = serde_json::to_string(&self.ids).map_err(|err| err.to_string())?;
+        fs::write(&self.ids_path, serialized).map_err(|err| format!("Can't save mirror ids: {err}"))
+    }
+
+    /// Id of the show on the mirror, searched for by title the first time
+    async fn mirror_id(&mut self, id: usize) -> Result<usize, String> {
+        if let Some(mirror_id) = self.ids.get(&id) {
+            return Ok(*mirror_id);
+        }
+        if !self.mirror.is_logged_in() {
+            return Err(String::from("Not logged in"));
+        }
+        let title = self.primary.get_title(id).await?;
+        let mut titles: HashSet<_> = HashSet::from([title.to_lowercase()]);
+        if let Some(alternative_titles) = self.primary.get_alternative_titles(id).await? {
+            titles.extend(
+                alternative_titles
+                    .synonyms
+                    .into_iter()
+                    .chain(alternative_titles.languages.into_values())
+                    .map(|title| title.to_lowercase()),
+            );
+        }
+        let mirror_id = self
+            .mirror
+            .search_title(&title, 0)
+            .await?
+            .titles
+            .into_iter()
+            .find(|found| titles.contains(&found.title.to_lowercase()))
+            .map(|found| found.service_id)
+            .ok_or_else(|| format!("Can't find {title}"))?;
+        self.set_mirror_id(id, mirror_id)?;
+        Ok(mirror_id)
+    }
+
+    async fn mirror_id_or_fail(&mut self, id: usize) -> Option<usize> {
+        let mirror_id = self.mirror_id(id).await;
+        self.record(mirror_id)
+    }
+
+    fn record<R>(&mut self, result: Result<R, String>) -> Option<R> {
+        result
+            .map_err(|error| {
+                self.failures.push(ServiceFailure {
+                    service: self.mirror.get_service_type(),
+                    error,
+                });
+            })
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn test_set_progress_mirrored() {
+        let (mut mirror, calls) = generate_mirror(true);
+        assert_eq!(mirror.set_progress(1, 3, None).await, Ok(3));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                (ServiceType::MAL, String::from("set_progress 1 3")),
+                (ServiceType::AniList, String::from("set_progress 101 3")),
+            ]
+        );
+        assert!(mirror.take_failures().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mirror_id_by_alternative_title() {
+        let (mut mirror, calls) = generate_mirror(true);
+        assert_eq!(mirror.init_show(2).await, Ok(()));
+        assert!(calls
+            .lock()
+            .unwrap()
+            .contains(&(ServiceType::AniList, String::from("init_show 102"))));
+        assert_eq!(mirror.ids.get(&2), Some(&102));
+    }
+
+    #[tokio::test]
+    async fn test_partial_failure() {
+        let (mut mirror, _) = generate_mirror(true);
+        // no show with a matching title on the mirror
+        assert_eq!(mirror.set_progress(3, 1, None).await, Ok(1));
+        let failures = mirror.take_failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].service, ServiceType::AniList);
+        assert!(mirror.take_failures().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mirror_not_logged_in() {
+        let (mut mirror, calls) = generate_mirror(false);
+        assert!(!mirror.is_logged_in());
+        assert_eq!(mirror.get_url().as_deref(), Some("AniList"));
+        assert_eq!(mirror.set_progress(1, 3, None).await, Ok(3));
+        assert_eq!(calls.lock().unwrap().len(), 1);
+        assert_eq!(mirror.take_failures()[0].error, "Not logged in");
+    }
+
+    #[tokio::test]
+    async fn test_logout() {
+        let (mut mirror, _) = generate_mirror(true);
+        assert_eq!(
+            mirror.get_username().await,
+            Ok(Some(String::from("MAL user")))
+        );
+        assert_eq!(mirror.logout().await, Ok(()));
+        assert!(!mirror.primary.is_logged_in());
+        assert!(!mirror.mirror.is_logged_in());
+        assert_eq!(mirror.get_username().await, Ok(None));
+        assert_eq!(mirror.get_url().as_deref(), Some("MAL"));
+    }
+
+    type Calls = Arc<Mutex<Vec<(ServiceType, String)>>>;
+
+    fn generate_mirror(logged_in: bool) -> (Mirror<Fake, Fake>, Calls) {
+        let calls = Calls::default();
+        let primary = Fake {
+            service_type: ServiceType::MAL,
+            logged_in: true,
+            shows: vec![
+                (1, String::from("One Piece"), None),
+                (
+                    2,
+                    String::from("Daiya no A"),
+                    Some(String::from("Ace of Diamond")),
+                ),
+                (3, String::from("Primary only"), None),
+            ],
+            calls: Arc::clone(&calls),
+        };
+        let mirror = Fake {
+            service_type: ServiceType::AniList,
+            logged_in,
+            shows: vec![
+                (101, String::from("ONE PIECE"), None),
+                (102, String::from("Ace of Diamond"), None),
+            ],
+            calls: Arc::clone(&calls),
+        };
+        let ids_path = std::env::temp_dir().join(format!(
+            "lma_mirror_test_{}_{}",
+            std::process::id(),
+            logged_in
+        ));
+        (
+            Mirror::build(primary, mirror, HashMap::new(), ids_path),
+            calls,
+        )
+    }
+
+    /// Service with a fixed list of shows that records changes made to it
+    struct Fake {
+        service_type: ServiceType,
+        logged_in: bool,
+        /// Id, title and english title
+        shows: Vec<(usize, String, Option<String>)>,
+        calls: Calls,
+    }
+
+    impl Fake {
+        fn record(&self, call: String) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push((self.service_type.clone(), call));
+        }
+
+        fn show(&self, id: usize) -> Result<&(usize, String, Option<String>), String> {
+            self.shows
+                .iter()
+                .find(|show| show.0 == id)
+                .ok_or_else(|| String::from("Not found"))
+        }
+    }
+
+    impl Service for Fake {
+        async fn new(_cache_dir: PathBuf) -> Result<Self, String> {
+            Err(String::from("Built in tests"))
+        }
+        async fn login(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+        async fn auth(&mut self) {}
+        async fn init_show(&mut self, id: usize) -> Result<(), String> {
+            self.record(format!("init_show {id}"));
+            Ok(())
+        }
+        async fn search_title(
+            &mut self,
+            potential_title: &str,
+            _offset: usize,
+        ) -> Result<ServiceSearchPage, String> {
+            let words: Vec<_> = potential_title
+                .to_lowercase()
+                .split(' ')
+                .map(String::from)
+                .collect();
+            let titles = self
+                .shows
+                .iter()
+                .filter(|(_, title, _)| {
+                    words
+                        .iter()
+                        .any(|word| title.to_lowercase().contains(word.as_str()))
+                })
+                .map(|(service_id, title, _)| ServiceTitle {
+                    service_id: *service_id,
+                    title: title.clone(),
+                    ..Default::default()
+                })
+                .collect();
+            Ok(ServiceSearchPage {
+                titles,
+                next_offset: None,
+            })
+        }
+        async fn get_title(&mut self, id: usize) -> Result<String, String> {
+            Ok(self.show(id)?.1.clone())
+        }
+        async fn get_title_details(&mut self, id: usize) -> Result<ServiceTitle, String> {
+            Ok(ServiceTitle {
+                service_id: id,
+                title: self.show(id)?.1.clone(),
+                ..Default::default()
+            })
+        }
+        async fn get_alternative_titles(
+            &mut self,
+            id: usize,
+        ) -> Result<Option<AlternativeTitles>, String> {
+            let languages = self
+                .show(id)?
+                .2
+                .clone()
+                .map(|title| (String::from("en"), title))
+                .into_iter()
+                .collect();
+            Ok(Some(AlternativeTitles {
+                synonyms: Vec::new(),
+                languages,
+            }))
+        }
+        async fn get_episodes(
+            &mut self,
+            _id: usize,
+            _precise_score: bool,
+        ) -> Result<Vec<ServiceEpisodeDetails>, String> {
+            Ok(Vec::new())
+        }
+        async fn get_episode_count(&mut self, _id: usize) -> Result<Option<usize>, String> {
+            Ok(None)
+        }
+        async fn get_user_entry_details(
+            &mut self,
+            _id: usize,
+        ) -> Result<Option<ServiceEpisodeUser>, String> {
+            Ok(None)
+        }
+        async fn get_user_entries_details(
+            &mut self,
+            _ids: &[usize],
+            _progress: &SyncProgress,
+        ) -> Result<RemoteEntries, String> {
+            Ok(RemoteEntries::new())
+        }
+        async fn get_user_list(&mut self, _offset: usize) -> Result<ServiceListPage, String> {
+            Ok(ServiceListPage::default())
+        }
+        async fn update_user_entry(
+            &mut self,
+            id: usize,
+            _entry: &ServiceEpisodeUser,
+        ) -> Result<(), String> {
+            self.record(format!("update_user_entry {id}"));
+            Ok(())
+        }
+        async fn set_progress(
+            &mut self,
+            id: usize,
+            progress: usize,
+            _episode_count: Option<usize>,
+        ) -> Result<usize, String> {
+            self.record(format!("set_progress {id} {progress}"));
+            Ok(progress)
+        }
+        fn get_service_type(&self) -> ServiceType {
+            self.service_type.clone()
+        }
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::ALL
+        }
+        fn get_url(&self) -> Option<String> {
+            (!self.logged_in).then(|| format!("{:?}", self.service_type))
+        }
+        fn is_logged_in(&self) -> bool {
+            self.logged_in
+        }
+        async fn get_username(&mut self) -> Result<Option<String>, String> {
+            Ok(self
+                .logged_in
+                .then(|| format!("{} user", self.service_type.as_str())))
+        }
+        async fn logout(&mut self) -> Result<(), String> {
+            self.logged_in = false;
+            Ok(())
+        }
+        async fn get_season(
+            &mut self,
+            _season: Season,
+            _offset: usize,
+        ) -> Result<ServiceSearchPage, String> {
+            Ok(ServiceSearchPage::default())
+        }
+        async fn get_airing_schedule(
+            &mut self,
+            _ids: &[usize],
+        ) -> Result<Vec<ServiceAiring>, String> {
+            Ok(Vec::new())
+        }
+        async fn get_relations(&mut self, _id: usize) -> Result<Vec<ServiceRelation>, String> {
+            Ok(Vec::new())
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+pub const DEFAULT_REDIRECT_PORT: u16 = 2525;
+/// Connections that send nothing, like the ones browsers open ahead of time, are dropped after it
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+const SUCCESS_PAGE: &str =
+    "<html><body>Logged in, you can close this tab and go back to lma</body></html>";
+const WRONG_STATE_PAGE: &str =
+    "<html><body>This login wasn't started by lma, try logging in again</body></html>";
+/// Browsers don't send the fragment, so it gets moved into the query and sent again
+const FRAGMENT_PAGE: &str = "<html><body><script>\
+    if (location.hash) { location.replace('/?' + location.hash.substring(1)); }\
+    </script>Missing login data, try logging in again</body></html>";
+
+pub async fn bind(port: u16) -> Result<TcpListener, String> {
+    TcpListener::bind(("127.0.0.1", port))
+        .await
+        .map_err(|err| format!("Can't listen for the login redirect on port {port}: {err}"))
+}
+
+pub fn redirect_uri(port: u16) -> String {
+    format!("http://localhost:{port}")
+}
+
+/// Random value for the `state` parameter, the service sends it back with the redirect
+pub fn new_state() -> String {
+    let mut bytes = [0; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn check_state(params: &HashMap<String, String>, state: &str) -> Result<(), String> {
+    if params.get("state").map(String::as_str) == Some(state) {
+        Ok(())
+    } else {
+        Err(String::from(
+            "The address doesn't belong to this login, log in again and copy the new one",
+        ))
+    }
+}
+
+/// Query and fragment parameters of the address the browser was redirected to,
+/// for when the redirect can't reach lma. `state` has to match the one sent with the login
+pub fn parse_redirect_url(
+    url: &str,
+    key: &str,
+    state: &str,
+) -> Result<HashMap<String, String>, String> {
+    let url = url.trim();
+    let (url, fragment) = url.split_once('#').unwrap_or((url, ""));
+    let query = url
+        .split_once('?')
+        .map(|(_, query)| query)
+        .unwrap_or_default();
+    let mut params = parse_query(query);
+    params.extend(parse_query(fragment));
+    if params.contains_key(key) {
+        check_state(&params, state)?;
+        Ok(params)
+    } else {
+        Err(format!(
+            "The address has no {key} in it, copy the whole address from the browser"
+        ))
+    }
+}
+
+/// Waits for the service to redirect the browser back, returns query parameters
+/// of the first request containing `key` and the `state` sent with the login
+pub async fn wait_for_redirect(
+    listener: TcpListener,
+    key: &str,
+    state: &str,
+) -> Result<HashMap<String, String>, String> {
+    loop {
+        let (mut stream, _) = listener
+            .accept()
+            .await
+            .map_err(|err| format!("Login redirect: {err}"))?;
+        // a broken or silent connection only loses itself, the real redirect can still come
+        let Ok(Ok(params)) = tokio::time::timeout(READ_TIMEOUT, read_query(&mut stream)).await
+        else {
+            continue;
+        };
+        if !params.contains_key(key) {
+            _ = respond(&mut stream, FRAGMENT_PAGE).await;
+        } else if check_state(&params, state).is_err() {
+            // anything running locally can send a request, keep waiting for the real one
+            _ = respond(&mut stream, WRONG_STATE_PAGE).await;
+        } else {
+            // the code is here even if the browser is gone already
+            _ = respond(&mut stream, SUCCESS_PAGE).await;
+            return Ok(params);
+        }
+    }
+}
+
+async fn read_query(stream: &mut TcpStream) -> Result<HashMap<String, String>, String> {
+    let mut buffer = [0; 4096];
+    let read = stream
+        .read(&mut buffer)
+        .await
+        .map_err(|err| format!("Login redirect: {err}"))?;
+    let request = String::from_utf8_lossy(&buffer[..read]);
+    // GET /?key=value HTTP/1.1
+    let path = request.split_whitespace().nth(1).unwrap_or_default();
+    Ok(parse_query(
+        path.split_once('?')
+            .map(|(_, query)| query)
+            .unwrap_or_default(),
+    ))
+}
+
+async fn respond(stream: &mut TcpStream, body: &str) -> Result<(), String> {
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream
+        .write_all(response.as_bytes())
+        .await
+        .map_err(|err| format!("Login redirect: {err}"))
+}
+
+pub fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (percent_decode(key), percent_decode(value)))
+        .collect()
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let byte = input
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                if let Some(byte) = byte {
+                    decoded.push(byte);
+                    i += 3;
+                    continue;
+                }
+                decoded.push(b'%');
+            }
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_parsing() {
+        let params = parse_query("access_token=abc.def-1&token_type=Bearer&state=a%20b+c");
+        assert_eq!(
+            params.get("access_token").map(String::as_str),
+            Some("abc.def-1")
+        );
+        assert_eq!(params.get("state").map(String::as_str), Some("a b c"));
+        assert!(parse_query("").is_empty());
+    }
+
+    #[test]
+    fn pasted_redirect() {
+        let params = parse_redirect_url(
+            " http://localhost:2525/#access_token=token&token_type=Bearer&state=s1 ",
+            "access_token",
+            "s1",
+        )
+        .unwrap();
+        assert_eq!(
+            params.get("access_token").map(String::as_str),
+            Some("token")
+        );
+        let params =
+            parse_redirect_url("http://localhost:2525/?code=abc&state=s1", "code", "s1").unwrap();
+        assert_eq!(params.get("code").map(String::as_str), Some("abc"));
+        assert!(parse_redirect_url("http://localhost:2525/", "code", "s1").is_err());
+    }
+
+    #[test]
+    fn pasted_redirect_from_another_login() {
+        assert!(parse_redirect_url("http://localhost:2525/?code=abc", "code", "s1").is_err());
+        assert!(
+            parse_redirect_url("http://localhost:2525/?code=abc&state=s2", "code", "s1").is_err()
+        );
+        assert_ne!(new_state(), new_state());
+    }
+
+    #[tokio::test]
+    async fn redirect_with_fragment() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let waiting =
+            tokio::spawn(async move { wait_for_redirect(listener, "access_token", "s1").await });
+
+        // first request has the token in the fragment which the browser doesn't send
+        let first = send_request(port, "/").await;
+        assert!(first.contains("location.hash"));
+        let second = send_request(port, "/?access_token=token&expires_in=10&state=s1").await;
+        assert!(second.contains("Logged in"));
+
+        let params = waiting.await.unwrap().unwrap();
+        assert_eq!(
+            params.get("access_token").map(String::as_str),
+            Some("token")
+        );
+    }
+
+    #[tokio::test]
+    async fn redirect_with_wrong_state() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let waiting = tokio::spawn(async move { wait_for_redirect(listener, "code", "s1").await });
+
+        let planted = send_request(port, "/?code=planted").await;
+        assert!(planted.contains("wasn't started by lma"));
+        let planted = send_request(port, "/?code=planted&state=s2").await;
+        assert!(planted.contains("wasn't started by lma"));
+        send_request(port, "/?code=real&state=s1").await;
+
+        let params = waiting.await.unwrap().unwrap();
+        assert_eq!(params.get("code").map(String::as_str), Some("real"));
+    }
+
+    #[tokio::test]
+    async fn redirect_after_bad_connections() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let waiting = tokio::spawn(async move { wait_for_redirect(listener, "code", "s1").await });
+
+        // closed before the response, then one that never sends anything
+        let mut closed = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        closed
+            .write_all(b"GET /?code=x HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        drop(closed);
+        let _silent = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let response = send_request(port, "/?code=real&state=s1").await;
+        assert!(response.contains("Logged in"));
+
+        let params = waiting.await.unwrap().unwrap();
+        assert_eq!(params.get("code").map(String::as_str), Some("real"));
+    }
+
+    async fn send_request(port: u16, path: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream
+            .write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Token bucket, requests wait for a free token instead of failing
+pub struct TokenBucket {
+    capacity: f64,
+    refill_per_second: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Starts full so the first `capacity` requests go out right away
+    pub fn new(capacity: u32, refill_per_second: f64) -> Self {
+        Self {
+            capacity: f64::from(capacity),
+            refill_per_second,
+            state: Mutex::new(BucketState {
+                tokens: f64::from(capacity),
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub async fn acquire(&self) {
+        while let Err(wait) = self.try_acquire(Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Takes a token or returns how long to wait for the next one
+    fn try_acquire(&self, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let elapsed = now.saturating_duration_since(state.last_refill);
+        state.tokens =
+            (state.tokens + elapsed.as_secs_f64() * self.refill_per_second).min(self.capacity);
+        state.last_refill = state.last_refill.max(now);
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - state.tokens) / self.refill_per_second,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burst_up_to_capacity() {
+        let bucket = TokenBucket::new(3, 1.0);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert!(bucket.try_acquire(now).is_ok());
+        }
+        let wait = bucket.try_acquire(now).expect_err("Bucket should be empty");
+        assert!(wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let bucket = TokenBucket::new(1, 2.0);
+        let now = Instant::now();
+        assert!(bucket.try_acquire(now).is_ok());
+        assert!(bucket.try_acquire(now).is_err());
+        assert!(bucket.try_acquire(now + Duration::from_millis(500)).is_ok());
+    }
+
+    #[test]
+    fn never_above_capacity() {
+        let bucket = TokenBucket::new(2, 10.0);
+        let later = Instant::now() + Duration::from_secs(60);
+        assert!(bucket.try_acquire(later).is_ok());
+        assert!(bucket.try_acquire(later).is_ok());
+        assert!(bucket.try_acquire(later).is_err());
+    }
+
+    #[tokio::test]
+    async fn acquire_waits_for_token() {
+        let bucket = TokenBucket::new(1, 20.0);
+        let start = Instant::now();
+        bucket.acquire().await;
+        bucket.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
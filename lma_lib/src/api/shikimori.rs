@@ -0,0 +1,1106 @@
+use super::oauth;
+use super::rate_limit::TokenBucket;
+use crate::{
+    AlternativeTitles, Capabilities, Credentials, EpisodeStatus, MediaType, RelationType,
+    RemoteEntries, Season, Service, ServiceAiring, ServiceEpisodeDetails, ServiceEpisodeUser,
+    ServiceListEntry, ServiceListPage, ServiceRelation, ServiceSearchPage, ServiceTitle,
+    ServiceType, SyncProgress,
+};
+use reqwest::{Method, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+const BASE_URL: &str = "https://shikimori.one";
+const CLIENT_ID_VAR: &str = "LMA_SHIKIMORI_CLIENT_ID";
+const CLIENT_SECRET_VAR: &str = "LMA_SHIKIMORI_CLIENT_SECRET";
+/// Shikimori rejects requests without the name of the application
+const USER_AGENT: &str = "lma";
+const SEARCH_LIMIT: usize = 20;
+const PAGE_SIZE: usize = 500;
+/// Shikimori allows 5 requests per second and 90 per minute
+const RATE_LIMIT_BURST: u32 = 5;
+const RATE_LIMIT_PER_SECOND: f64 = 1.5;
+
+pub struct Shikimori {
+    client: reqwest::Client,
+    base_url: String,
+    tokens: Option<Tokens>,
+    token_store: Credentials,
+    user_id: Option<usize>,
+    username: Option<String>,
+    client_id: Option<String>,
+    url: Option<String>,
+    /// Sent with the login and expected back with the code
+    state: String,
+    redirect_port: u16,
+    rate_limiter: TokenBucket,
+}
+
+impl Service for Shikimori {
+    async fn new(cache_dir: PathBuf) -> Result<Self, String> {
+        Self::with_credentials(Credentials::plain(
+            cache_dir,
+            ServiceType::Shikimori.as_str(),
+        ))
+        .await
+    }
+    async fn login(&mut self) -> Result<(), String> {
+        // no redirect is coming without an application
+        self.client_id()?;
+        let listener = oauth::bind(self.redirect_port).await?;
+        let params = oauth::wait_for_redirect(listener, "code", &self.state).await?;
+        self.exchange_code(&params).await
+    }
+    async fn login_with_redirect(&mut self, redirect_url: &str) -> Result<(), String> {
+        let params = oauth::parse_redirect_url(redirect_url, "code", &self.state)?;
+        self.exchange_code(&params).await
+    }
+    async fn auth(&mut self) {
+        self.state = oauth::new_state();
+        self.url = match (&self.tokens, self.client_id()) {
+            (None, Ok(client_id)) => Some(format!(
+                "{}/oauth/authorize?client_id={client_id}&redirect_uri={}&response_type=code&scope=user_rates&state={}",
+                self.base_url,
+                oauth::redirect_uri(self.redirect_port),
+                self.state
+            )),
+            _ => None,
+        }
+    }
+    async fn init_show(&mut self, id: usize) -> Result<(), String> {
+        if self.get_user_rate(id).await?.is_none() {
+            self.create_user_rate(
+                id,
+                Map::from_iter([(String::from("status"), json!("planned"))]),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+    async fn search_title(
+        &mut self,
+        potential_title: &str,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        let animes: Vec<AnimeShort> = self
+            .get(
+                "/api/animes",
+                &[
+                    ("search", potential_title.to_owned()),
+                    ("limit", SEARCH_LIMIT.to_string()),
+                    ("page", (offset / SEARCH_LIMIT + 1).to_string()),
+                ],
+            )
+            .await
+            .map_err(|err| format!("Shikimori search result: {err}"))?;
+        Ok(search_page(animes, offset))
+    }
+    async fn get_title(&mut self, id: usize) -> Result<String, String> {
+        Ok(self
+            .get_anime(id)
+            .await
+            .map_err(|err| format!("Anime title: {err}"))?
+            .name)
+    }
+    async fn get_title_details(&mut self, id: usize) -> Result<ServiceTitle, String> {
+        let anime = self
+            .get_anime(id)
+            .await
+            .map_err(|err| format!("Anime details: {err}"))?;
+        Ok(ServiceTitle {
+            service_id: id,
+            title: anime.name,
+            media_type: anime.kind.as_deref().map(MediaType::parse),
+            year: aired_year(anime.aired_on),
+            episodes: Some(anime.episodes).filter(|episodes| *episodes > 0),
+            nsfw: false,
+        })
+    }
+    async fn get_alternative_titles(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<AlternativeTitles>, String> {
+        let anime = self
+            .get_anime(id)
+            .await
+            .map_err(|err| format!("Alternative anime titles: {err}"))?;
+        let mut synonyms = anime.synonyms;
+        let mut languages = HashMap::new();
+        if let Some(russian) = anime.russian.filter(|title| !title.is_empty()) {
+            languages.insert(String::from("ru"), russian);
+        }
+        // first title of a language is the main one, the rest are synonyms
+        for (language, titles) in [("ja", anime.japanese), ("en", anime.english)] {
+            let mut titles = titles.into_iter().flatten();
+            if let Some(title) = titles.next() {
+                languages.insert(language.to_owned(), title);
+            }
+            synonyms.extend(titles);
+        }
+        Ok(Some(AlternativeTitles {
+            synonyms,
+            languages,
+        }))
+    }
+    async fn get_episodes(
+        &mut self,
+        _id: usize,
+        _precise_score: bool,
+    ) -> Result<Vec<ServiceEpisodeDetails>, String> {
+        // Shikimori has no per episode data
+        Ok(Vec::new())
+    }
+    async fn get_episode_count(&mut self, id: usize) -> Result<Option<usize>, String> {
+        let anime = self
+            .get_anime(id)
+            .await
+            .map_err(|err| format!("Anime episode count: {err}"))?;
+        // 0 until the total is known
+        Ok(Some(anime.episodes).filter(|count| *count != 0))
+    }
+    async fn get_user_entry_details(
+        &mut self,
+        id: usize,
+    ) -> Result<Option<ServiceEpisodeUser>, String> {
+        Ok(self
+            .get_user_rate(id)
+            .await?
+            .map(UserRate::into_service_user))
+    }
+    async fn get_user_entries_details(
+        &mut self,
+        ids: &[usize],
+        progress: &SyncProgress,
+    ) -> Result<RemoteEntries, String> {
+        // the whole list takes fewer requests than asking for every show
+        let mut entries = RemoteEntries::new();
+        let mut offset = Some(0);
+        while let Some(current_offset) = offset {
+            let page = self.get_user_list(current_offset).await?;
+            offset = page.next_offset;
+            for entry in page.entries {
+                if ids.contains(&entry.service_id) {
+                    progress.finish_one(entry.service_id);
+                    entries.insert(entry.service_id, entry.user);
+                }
+            }
+        }
+        ids.iter()
+            .filter(|id| !entries.contains_key(id))
+            .for_each(|id| progress.finish_one(*id));
+        Ok(entries)
+    }
+    async fn get_user_list(&mut self, offset: usize) -> Result<ServiceListPage, String> {
+        let user_id = self.user_id().await?;
+        let rates: Vec<AnimeRate> = self
+            .get(
+                &format!("/api/users/{user_id}/anime_rates"),
+                &[
+                    ("page", (offset / PAGE_SIZE + 1).to_string()),
+                    ("limit", PAGE_SIZE.to_string()),
+                ],
+            )
+            .await
+            .map_err(|err| format!("User's list: {err}"))?;
+        // a full page might have more after it
+        let next_offset = (rates.len() >= PAGE_SIZE).then_some(offset + PAGE_SIZE);
+        let entries = rates
+            .into_iter()
+            .map(|rate| ServiceListEntry {
+                service_id: rate.anime.id,
+                title: rate.anime.name,
+                user: rate.rate.into_service_user(),
+            })
+            .collect();
+        Ok(ServiceListPage {
+            entries,
+            next_offset,
+        })
+    }
+    async fn update_user_entry(
+        &mut self,
+        id: usize,
+        entry: &ServiceEpisodeUser,
+    ) -> Result<(), String> {
+        let mut user_rate = Map::new();
+        if let Some(status) = entry.status.and_then(to_shikimori_status) {
+            user_rate.insert(String::from("status"), json!(status));
+        }
+        if let Some(score) = entry.score {
+            user_rate.insert(String::from("score"), json!(score));
+        }
+        if let Some(comments) = &entry.comments {
+            user_rate.insert(String::from("text"), json!(comments));
+        }
+        // Shikimori doesn't store start and finish dates
+        if !user_rate.is_empty() {
+            self.save_user_rate(id, user_rate).await?;
+        }
+        Ok(())
+    }
+    async fn set_progress(
+        &mut self,
+        id: usize,
+        progress: usize,
+        episode_count: Option<usize>,
+    ) -> Result<usize, String> {
+        let episode_count = match episode_count {
+            Some(episode_count) => Some(episode_count),
+            None => self.get_episode_count(id).await?,
+        }
+        .filter(|count| *count != 0)
+        .unwrap_or(usize::MAX);
+        let status = if progress == 0 {
+            "planned"
+        } else if progress >= episode_count {
+            "completed"
+        } else {
+            "watching"
+        };
+        let user_rate = Map::from_iter([
+            (String::from("episodes"), json!(progress)),
+            (String::from("status"), json!(status)),
+        ]);
+        let saved = self.save_user_rate(id, user_rate).await?;
+        Ok(saved.episodes.unwrap_or(progress))
+    }
+    fn get_service_type(&self) -> ServiceType {
+        ServiceType::Shikimori
+    }
+    fn capabilities(&self) -> Capabilities {
+        // user rates have no start or finish dates
+        Capabilities {
+            episode_metadata: false,
+            dates: false,
+            ..Capabilities::ALL
+        }
+    }
+    fn get_url(&self) -> Option<String> {
+        self.url.clone()
+    }
+    fn is_logged_in(&self) -> bool {
+        self.tokens.is_some()
+    }
+    async fn get_username(&mut self) -> Result<Option<String>, String> {
+        if !self.is_logged_in() {
+            return Ok(None);
+        }
+        self.user_id().await?;
+        Ok(self.username.clone())
+    }
+    async fn logout(&mut self) -> Result<(), String> {
+        self.token_store.delete()?;
+        self.tokens = None;
+        self.user_id = None;
+        self.username = None;
+        self.url = Some(String::new());
+        Ok(())
+    }
+    async fn get_season(
+        &mut self,
+        season: Season,
+        offset: usize,
+    ) -> Result<ServiceSearchPage, String> {
+        let animes: Vec<AnimeShort> = self
+            .get(
+                "/api/animes",
+                &[
+                    (
+                        "season",
+                        format!("{}_{}", season.name.as_str().to_lowercase(), season.year),
+                    ),
+                    ("order", String::from("popularity")),
+                    ("limit", SEARCH_LIMIT.to_string()),
+                    ("page", (offset / SEARCH_LIMIT + 1).to_string()),
+                ],
+            )
+            .await
+            .map_err(|err| format!("Season {season}: {err}"))?;
+        Ok(search_page(animes, offset))
+    }
+    async fn get_airing_schedule(&mut self, ids: &[usize]) -> Result<Vec<ServiceAiring>, String> {
+        // only the full details have the next episode, one request per show
+        let mut schedule = Vec::new();
+        for id in ids {
+            let anime = self
+                .get_anime(*id)
+                .await
+                .map_err(|err| format!("Airing schedule: {err}"))?;
+            let airs_at = anime
+                .next_episode_at
+                .and_then(|date| OffsetDateTime::parse(&date, &Rfc3339).ok());
+            if let Some(airs_at) = airs_at {
+                schedule.push(ServiceAiring {
+                    service_id: *id,
+                    episode: Some(anime.episodes_aired + 1),
+                    airs_at,
+                });
+            }
+        }
+        Ok(schedule)
+    }
+    async fn get_relations(&mut self, id: usize) -> Result<Vec<ServiceRelation>, String> {
+        let related: Vec<Related> = self
+            .get(&format!("/api/animes/{id}/related"), &[])
+            .await
+            .map_err(|err| format!("Related anime: {err}"))?;
+        Ok(related
+            .into_iter()
+            // manga have `anime` set to null
+            .filter_map(|related| {
+                Some(ServiceRelation {
+                    relation: RelationType::parse(&related.relation),
+                    show: related.anime?.into_service_title(),
+                })
+            })
+            .collect())
+    }
+    fn set_redirect_port(&mut self, port: u16) {
+        self.redirect_port = port;
+    }
+}
+
+impl Shikimori {
+    /// Keeps the tokens in `token_store` instead of the data directory
+    pub async fn with_credentials(token_store: Credentials) -> Result<Self, String> {
+        let tokens = token_store
+            .load()?
+            .and_then(|tokens| serde_json::from_str(&tokens).ok());
+        let mut shikimori = Self::build(BASE_URL, tokens, token_store);
+        if shikimori.tokens.as_ref().is_some_and(Tokens::expired) {
+            // log in again if the refresh fails
+            if shikimori.refresh().await.is_err() {
+                shikimori.tokens = None;
+            }
+        }
+        Ok(shikimori)
+    }
+
+    fn build(base_url: &str, tokens: Option<Tokens>, token_store: Credentials) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.to_owned(),
+            tokens,
+            token_store,
+            user_id: None,
+            username: None,
+            client_id: env::var(CLIENT_ID_VAR)
+                .ok()
+                .filter(|client_id| !client_id.trim().is_empty()),
+            url: Some(String::new()),
+            state: String::new(),
+            redirect_port: oauth::DEFAULT_REDIRECT_PORT,
+            rate_limiter: TokenBucket::new(RATE_LIMIT_BURST, RATE_LIMIT_PER_SECOND),
+        }
+    }
+
+    fn client_id(&self) -> Result<&str, String> {
+        self.client_id
+            .as_deref()
+            .ok_or_else(|| format!("Set {CLIENT_ID_VAR} to the id of your Shikimori application"))
+    }
+
+    async fn refresh(&mut self) -> Result<(), String> {
+        let refresh_token = self
+            .tokens
+            .as_ref()
+            .map(|tokens| tokens.refresh_token.clone())
+            .ok_or("Not logged in")?;
+        self.request_tokens(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &refresh_token),
+        ])
+        .await
+    }
+
+    async fn exchange_code(&mut self, params: &HashMap<String, String>) -> Result<(), String> {
+        let code = params.get("code").ok_or("Shikimori didn't return a code")?;
+        let redirect_uri = oauth::redirect_uri(self.redirect_port);
+        self.request_tokens(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &redirect_uri),
+        ])
+        .await?;
+        self.url = None;
+        Ok(())
+    }
+
+    async fn request_tokens(&mut self, form: &[(&str, &str)]) -> Result<(), String> {
+        let client_id = self.client_id()?.to_owned();
+        let client_secret = env::var(CLIENT_SECRET_VAR).unwrap_or_default();
+        let mut form = form.to_vec();
+        form.extend([
+            ("client_id", client_id.as_str()),
+            ("client_secret", client_secret.as_str()),
+        ]);
+        let response = self
+            .client
+            .post(format!("{}/oauth/token", self.base_url))
+            .header(reqwest::header::USER_AGENT, USER_AGENT)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|err| format!("Shikimori login: {err}"))?;
+        if !response.status().is_success() {
+            return Err(format!("Shikimori login: {}", response.status()));
+        }
+        let tokens: Tokens = response
+            .json()
+            .await
+            .map_err(|err| format!("Shikimori login: {err}"))?;
+        let serialized = serde_json::to_string(&tokens).map_err(|err| err.to_string())?;
+        self.token_store.save(&serialized)?;
+        self.tokens = Some(tokens);
+        self.user_id = None;
+        self.username = None;
+        Ok(())
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .client
+            .request(method, format!("{}{path}", self.base_url))
+            .header(reqwest::header::USER_AGENT, USER_AGENT);
+        match &self.tokens {
+            Some(tokens) => request.bearer_auth(&tokens.access_token),
+            None => request,
+        }
+    }
+
+    async fn send<R: DeserializeOwned>(&self, request: RequestBuilder) -> Result<R, String> {
+        self.rate_limiter.acquire().await;
+        let response = request.send().await.map_err(|err| err.to_string())?;
+        let status = response.status();
+        if !status.is_success() {
+            let body: Value = response.json().await.unwrap_or_default();
+            // errors come either as a list or as a single message
+            let error = body["errors"][0]
+                .as_str()
+                .or_else(|| body["message"].as_str())
+                .map_or_else(|| status.to_string(), ToOwned::to_owned);
+            return Err(error);
+        }
+        response.json().await.map_err(|err| err.to_string())
+    }
+
+    async fn get<R: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<R, String> {
+        self.send(self.request(Method::GET, path).query(query))
+            .await
+    }
+
+    async fn get_anime(&self, id: usize) -> Result<Anime, String> {
+        self.get(&format!("/api/animes/{id}"), &[]).await
+    }
+
+    async fn user_id(&mut self) -> Result<usize, String> {
+        if let Some(user_id) = self.user_id {
+            return Ok(user_id);
+        }
+        let user: Option<User> = self
+            .get("/api/users/whoami", &[])
+            .await
+            .map_err(|err| format!("Shikimori user: {err}"))?;
+        let user = user.ok_or("Shikimori user: not logged in")?;
+        self.user_id = Some(user.id);
+        self.username = Some(user.nickname);
+        Ok(user.id)
+    }
+
+    async fn get_user_rate(&mut self, id: usize) -> Result<Option<UserRate>, String> {
+        let user_id = self.user_id().await?;
+        let rates: Vec<UserRate> = self
+            .get(
+                "/api/v2/user_rates",
+                &[
+                    ("user_id", user_id.to_string()),
+                    ("target_id", id.to_string()),
+                    ("target_type", String::from("Anime")),
+                ],
+            )
+            .await
+            .map_err(|err| format!("Anime details: {err}"))?;
+        Ok(rates.into_iter().next())
+    }
+
+    async fn create_user_rate(
+        &mut self,
+        id: usize,
+        mut user_rate: Map<String, Value>,
+    ) -> Result<UserRate, String> {
+        let user_id = self.user_id().await?;
+        user_rate.insert(String::from("user_id"), json!(user_id));
+        user_rate.insert(String::from("target_id"), json!(id));
+        user_rate.insert(String::from("target_type"), json!("Anime"));
+        self.send(
+            self.request(Method::POST, "/api/v2/user_rates")
+                .json(&json!({ "user_rate": user_rate })),
+        )
+        .await
+        .map_err(|err| format!("Update user's list: {err}"))
+    }
+
+    /// Creates the rate if the show isn't on the user's list yet
+    async fn save_user_rate(
+        &mut self,
+        id: usize,
+        user_rate: Map<String, Value>,
+    ) -> Result<UserRate, String> {
+        let Some(rate_id) = self.get_user_rate(id).await?.and_then(|rate| rate.id) else {
+            return self.create_user_rate(id, user_rate).await;
+        };
+        self.send(
+            self.request(Method::PATCH, &format!("/api/v2/user_rates/{rate_id}"))
+                .json(&json!({ "user_rate": user_rate })),
+        )
+        .await
+        .map_err(|err| format!("Update user's list: {err}"))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Tokens {
+    access_token: String,
+    refresh_token: String,
+    created_at: i64,
+    expires_in: i64,
+}
+
+impl Tokens {
+    fn expired(&self) -> bool {
+        OffsetDateTime::now_utc().unix_timestamp() >= self.created_at + self.expires_in
+    }
+}
+
+#[derive(Deserialize)]
+struct User {
+    id: usize,
+    nickname: String,
+}
+
+#[derive(Deserialize)]
+struct AnimeShort {
+    id: usize,
+    name: String,
+    kind: Option<String>,
+    episodes: Option<usize>,
+    aired_on: Option<String>,
+}
+
+impl AnimeShort {
+    fn into_service_title(self) -> ServiceTitle {
+        ServiceTitle {
+            service_id: self.id,
+            title: self.name,
+            media_type: self.kind.as_deref().map(MediaType::parse),
+            year: aired_year(self.aired_on),
+            episodes: self.episodes.filter(|episodes| *episodes > 0),
+            nsfw: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Related {
+    /// In English, like "Side story"
+    relation: String,
+    anime: Option<AnimeShort>,
+}
+
+#[derive(Deserialize)]
+struct Anime {
+    name: String,
+    russian: Option<String>,
+    #[serde(default)]
+    english: Vec<Option<String>>,
+    #[serde(default)]
+    japanese: Vec<Option<String>>,
+    #[serde(default)]
+    synonyms: Vec<String>,
+    #[serde(default)]
+    episodes: usize,
+    kind: Option<String>,
+    aired_on: Option<String>,
+    #[serde(default)]
+    episodes_aired: usize,
+    next_episode_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AnimeRate {
+    #[serde(flatten)]
+    rate: UserRate,
+    anime: AnimeShort,
+}
+
+#[derive(Deserialize)]
+struct UserRate {
+    id: Option<usize>,
+    status: Option<String>,
+    episodes: Option<usize>,
+    score: Option<u8>,
+    rewatches: Option<usize>,
+    text: Option<String>,
+    updated_at: Option<String>,
+}
+
+impl UserRate {
+    fn into_service_user(self) -> ServiceEpisodeUser {
+        ServiceEpisodeUser {
+            status: self.status.as_deref().map(to_episode_status),
+            progress: self.episodes,
+            // 0 means no score
+            score: self.score.filter(|score| *score != 0),
+            is_rewatching: self.status.as_ref().map(|status| status == "rewatching"),
+            rewatch_count: self.rewatches,
+            updated_at: self.updated_at,
+            start_date: None,
+            finish_date: None,
+            comments: self.text.filter(|text| !text.is_empty()),
+        }
+    }
+}
+
+fn search_page(animes: Vec<AnimeShort>, offset: usize) -> ServiceSearchPage {
+    // there's no total, a full page means there might be more
+    let next_offset = (animes.len() == SEARCH_LIMIT).then_some(offset + SEARCH_LIMIT);
+    let titles = animes
+        .into_iter()
+        .map(AnimeShort::into_service_title)
+        .collect();
+    ServiceSearchPage {
+        titles,
+        next_offset,
+    }
+}
+
+/// Dates look like 2015-04-06
+fn aired_year(aired_on: Option<String>) -> Option<u32> {
+    aired_on.and_then(|date| date.get(..4)?.parse().ok())
+}
+
+fn to_episode_status(status: &str) -> EpisodeStatus {
+    match status {
+        "watching" | "rewatching" => EpisodeStatus::Watching,
+        "planned" => EpisodeStatus::PlanToWatch,
+        _ => EpisodeStatus::parse(status),
+    }
+}
+
+const fn to_shikimori_status(status: EpisodeStatus) -> Option<&'static str> {
+    match status {
+        EpisodeStatus::Watching => Some("watching"),
+        EpisodeStatus::Completed => Some("completed"),
+        EpisodeStatus::OnHold => Some("on_hold"),
+        EpisodeStatus::Dropped => Some("dropped"),
+        EpisodeStatus::PlanToWatch => Some("planned"),
+        EpisodeStatus::None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SeasonName;
+    use mockito::{Matcher, Server, ServerGuard};
+    use time::macros::datetime;
+
+    #[tokio::test]
+    async fn test_search() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/api/animes")
+            .match_query(Matcher::UrlEncoded(
+                String::from("search"),
+                String::from("One Piece"),
+            ))
+            .match_header("user-agent", USER_AGENT)
+            .with_body(
+                json!([
+                    { "id": 21, "name": "One Piece", "russian": "Ван-Пис", "kind": "tv", "episodes": 0, "aired_on": "1999-10-20" },
+                    { "id": 459, "name": "One Piece Movie 1", "russian": "Ван-Пис: Фильм первый", "kind": "movie", "episodes": 1 },
+                ])
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server, false);
+        let result = client.search_title("One Piece", 0).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(result.next_offset, None);
+        let result = result.titles;
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[1].service_id, 459);
+        assert_eq!(result[1].episodes, Some(1));
+        assert_eq!(result[0].title, "One Piece");
+        assert_eq!(result[0].year, Some(1999));
+        assert_eq!(result[0].episodes, None, "Unknown episode count");
+    }
+
+    #[tokio::test]
+    async fn test_season() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/api/animes")
+            .match_query(Matcher::UrlEncoded(
+                String::from("season"),
+                String::from("fall_2023"),
+            ))
+            .with_body(include_str!("../../fixtures/shikimori/season.json"))
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server, false);
+        let season = Season::new(SeasonName::Fall, 2023);
+        let result = client.get_season(season, 0).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(result.next_offset, None);
+        assert_eq!(result.titles.len(), 2);
+        assert_eq!(result.titles[1].service_id, 54492);
+        assert_eq!(result.titles[1].year, Some(2023));
+    }
+
+    #[tokio::test]
+    async fn test_airing_schedule() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/api/animes/52991")
+            .with_body(include_str!("../../fixtures/shikimori/anime_ongoing.json"))
+            .create_async()
+            .await;
+        mock_anime(&mut server).await;
+        let mut client = generate_test_client(&server, false);
+        let result = client.get_airing_schedule(&[52991, 30230]).await;
+        assert_eq!(
+            result,
+            Ok(vec![ServiceAiring {
+                service_id: 52991,
+                episode: Some(8),
+                airs_at: datetime!(2023-10-27 14:00 UTC),
+            }]),
+            "Finished shows are left out"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_relations() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/api/animes/20507/related")
+            .with_body(include_str!("../../fixtures/shikimori/related.json"))
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server, false);
+        let result = client.get_relations(20507).await.unwrap();
+        assert_eq!(result.len(), 2, "The manga is left out");
+        assert_eq!(result[0].relation, RelationType::Sequel);
+        assert_eq!(result[0].show.title, "Noragami Aragoto");
+        assert_eq!(result[1].relation, RelationType::SideStory);
+        assert_eq!(result[1].show.episodes, None, "Unknown episode count");
+    }
+
+    #[tokio::test]
+    async fn test_get_alternative_titles() {
+        let mut server = Server::new_async().await;
+        mock_anime(&mut server).await;
+        let mut client = generate_test_client(&server, false);
+        let titles = client.get_alternative_titles(30230).await.unwrap().unwrap();
+        assert_eq!(
+            titles.languages.get("ru").map(String::as_str),
+            Some("Бейсбольный Эйс: Второй сезон")
+        );
+        assert_eq!(
+            titles.languages.get("ja").map(String::as_str),
+            Some("ダイヤのA -SECOND SEASON-")
+        );
+        assert_eq!(
+            titles.languages.get("en").map(String::as_str),
+            Some("Ace of the Diamond: Second Season")
+        );
+        assert!(titles.synonyms.contains(&String::from("Daiya no A 2")));
+        assert!(titles
+            .synonyms
+            .contains(&String::from("Ace of Diamond: Second Season")));
+        assert_eq!(client.get_episode_count(30230).await, Ok(Some(51)));
+        let details = client.get_title_details(30230).await.unwrap();
+        assert_eq!(details.media_type, Some(MediaType::TV));
+        assert_eq!(details.year, Some(2015));
+    }
+
+    #[tokio::test]
+    async fn test_get_user_entry_details() {
+        let mut server = Server::new_async().await;
+        mock_whoami(&mut server).await;
+        mock_user_rate(
+            &mut server,
+            "30230",
+            json!([{
+                "id": 77,
+                "status": "on_hold",
+                "episodes": 12,
+                "score": 7,
+                "rewatches": 0,
+                "text": "",
+                "updated_at": "2017-11-11T22:51:22.000+03:00",
+            }]),
+        )
+        .await;
+        let mut client = generate_test_client(&server, true);
+        let entry = client.get_user_entry_details(30230).await.unwrap().unwrap();
+        assert_eq!(entry.status, Some(EpisodeStatus::OnHold));
+        assert_eq!(entry.progress, Some(12));
+        assert_eq!(entry.score, Some(7));
+        assert_eq!(entry.comments, None);
+        assert!(entry
+            .updated_at
+            .as_deref()
+            .and_then(crate::sync::parse_timestamp)
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_user_list() {
+        let mut server = Server::new_async().await;
+        mock_whoami(&mut server).await;
+        server
+            .mock("GET", "/api/users/1000/anime_rates")
+            .match_query(Matcher::UrlEncoded(String::from("page"), String::from("1")))
+            .with_body(
+                json!([{
+                    "id": 77,
+                    "status": "rewatching",
+                    "episodes": 3,
+                    "score": 0,
+                    "anime": { "id": 21, "name": "One Piece" },
+                }])
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server, true);
+        let page = client.get_user_list(0).await.unwrap();
+        assert_eq!(page.next_offset, None);
+        assert_eq!(page.entries[0].service_id, 21);
+        assert_eq!(page.entries[0].user.status, Some(EpisodeStatus::Watching));
+        assert_eq!(page.entries[0].user.is_rewatching, Some(true));
+        assert_eq!(page.entries[0].user.score, None);
+
+        let progress = SyncProgress::default();
+        let entries = client
+            .get_user_entries_details(&[21, 30230], &progress)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(progress.done(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_set_progress() {
+        let mut server = Server::new_async().await;
+        mock_whoami(&mut server).await;
+        mock_anime(&mut server).await;
+        mock_user_rate(&mut server, "30230", json!([{ "id": 77, "episodes": 50 }])).await;
+        let update = server
+            .mock("PATCH", "/api/v2/user_rates/77")
+            .match_body(Matcher::PartialJson(
+                json!({ "user_rate": { "status": "completed", "episodes": 51 } }),
+            ))
+            .with_body(json!({ "id": 77, "status": "completed", "episodes": 51 }).to_string())
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server, true);
+        assert_eq!(client.set_progress(30230, 51, None).await, Ok(51));
+        update.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_init_show_creates_rate() {
+        let mut server = Server::new_async().await;
+        mock_whoami(&mut server).await;
+        mock_user_rate(&mut server, "21", json!([])).await;
+        let create = server
+            .mock("POST", "/api/v2/user_rates")
+            .match_body(Matcher::PartialJson(json!({ "user_rate": {
+                "user_id": 1000,
+                "target_id": 21,
+                "target_type": "Anime",
+                "status": "planned",
+            } })))
+            .with_status(201)
+            .with_body(json!({ "id": 78, "status": "planned" }).to_string())
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server, true);
+        assert_eq!(client.init_show(21).await, Ok(()));
+        create.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_error() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/api/animes/1")
+            .with_status(404)
+            .with_body(json!({ "message": "Resource not found", "code": 404 }).to_string())
+            .create_async()
+            .await;
+        let mut client = generate_test_client(&server, false);
+        assert_eq!(
+            client.get_title(1).await,
+            Err(String::from("Anime title: Resource not found"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_with_redirect() {
+        let mut server = Server::new_async().await;
+        let token = server
+            .mock("POST", "/oauth/token")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded(String::from("code"), String::from("abc")),
+                Matcher::UrlEncoded(
+                    String::from("redirect_uri"),
+                    String::from("http://localhost:8080"),
+                ),
+            ]))
+            .with_body(
+                json!({
+                    "access_token": "token",
+                    "refresh_token": "refresh",
+                    "created_at": 0,
+                    "expires_in": 86400
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let token_store = Credentials::memory();
+        let mut client = Shikimori::build(&server.url(), None, token_store.clone());
+        client.client_id = Some(String::from("1"));
+        client.set_redirect_port(8080);
+        client.auth().await;
+        assert!(client
+            .login_with_redirect("http://localhost:8080/?code=abc&state=other")
+            .await
+            .is_err());
+        client
+            .login_with_redirect(&format!(
+                "http://localhost:8080/?code=abc&state={}",
+                client.state
+            ))
+            .await
+            .unwrap();
+        token.assert_async().await;
+        assert!(client.is_logged_in());
+        assert!(token_store.load().unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_url() {
+        let mut client = generate_test_client(&Server::new_async().await, false);
+        client.auth().await;
+        assert!(client.get_url().is_some());
+        assert!(!client.is_logged_in());
+
+        client.client_id = None;
+        client.auth().await;
+        assert_eq!(client.get_url(), None);
+        assert!(client.login().await.unwrap_err().contains(CLIENT_ID_VAR));
+
+        let mut client = generate_test_client(&Server::new_async().await, true);
+        client.auth().await;
+        assert!(client.get_url().is_none());
+        assert!(client.is_logged_in());
+    }
+
+    #[tokio::test]
+    async fn test_logout() {
+        let mut server = Server::new_async().await;
+        mock_whoami(&mut server).await;
+        let mut client = generate_test_client(&server, true);
+        client.token_store.save("tokens").unwrap();
+        assert_eq!(client.get_username().await, Ok(Some(String::from("test"))));
+        client.logout().await.unwrap();
+        assert!(!client.is_logged_in());
+        assert_eq!(client.get_username().await, Ok(None));
+        assert_eq!(client.token_store.load(), Ok(None));
+        client.auth().await;
+        assert!(client.get_url().is_some());
+    }
+
+    #[test]
+    fn test_status_conversion() {
+        for status in [
+            EpisodeStatus::Watching,
+            EpisodeStatus::Completed,
+            EpisodeStatus::OnHold,
+            EpisodeStatus::Dropped,
+            EpisodeStatus::PlanToWatch,
+        ] {
+            let shikimori_status = to_shikimori_status(status).unwrap();
+            assert_eq!(to_episode_status(shikimori_status), status);
+        }
+    }
+
+    async fn mock_whoami(server: &mut ServerGuard) {
+        server
+            .mock("GET", "/api/users/whoami")
+            .with_body(json!({ "id": 1000, "nickname": "test" }).to_string())
+            .create_async()
+            .await;
+    }
+
+    async fn mock_anime(server: &mut ServerGuard) {
+        server
+            .mock("GET", "/api/animes/30230")
+            .with_body(
+                json!({
+                    "id": 30230,
+                    "name": "Diamond no Ace: Second Season",
+                    "russian": "Бейсбольный Эйс: Второй сезон",
+                    "english": ["Ace of the Diamond: Second Season", "Ace of Diamond: Second Season"],
+                    "japanese": ["ダイヤのA -SECOND SEASON-"],
+                    "synonyms": ["Daiya no A 2"],
+                    "episodes": 51,
+                    "kind": "tv",
+                    "aired_on": "2015-04-06",
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+    }
+
+    async fn mock_user_rate(server: &mut ServerGuard, anime_id: &str, body: Value) {
+        server
+            .mock("GET", "/api/v2/user_rates")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded(String::from("user_id"), String::from("1000")),
+                Matcher::UrlEncoded(String::from("target_id"), anime_id.to_owned()),
+            ]))
+            .with_body(body.to_string())
+            .create_async()
+            .await;
+    }
+
+    fn generate_test_client(server: &ServerGuard, logged_in: bool) -> Shikimori {
+        let tokens = logged_in.then(|| Tokens {
+            access_token: String::from("token"),
+            refresh_token: String::from("refresh"),
+            created_at: OffsetDateTime::now_utc().unix_timestamp(),
+            expires_in: 86400,
+        });
+        let mut client = Shikimori::build(&server.url(), tokens, Credentials::memory());
+        client.client_id = Some(String::from("1"));
+        client
+    }
+}
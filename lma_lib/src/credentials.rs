@@ -0,0 +1,376 @@
+#[cfg(feature = "secret-service")]
+mod secret_service;
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Where services keep their login tokens
+pub trait CredentialStore: Send + Sync {
+    /// `dir` is the data directory of the service and `account` its name
+    fn load(&self, dir: &Path, account: &str) -> Result<Option<String>, String>;
+    fn save(&self, dir: &Path, account: &str, secret: &str) -> Result<(), String>;
+    /// Does nothing when there's nothing saved
+    fn delete(&self, dir: &Path, account: &str) -> Result<(), String>;
+    /// Whether this is the `tokens` file that earlier versions always used
+    fn is_plain(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Debug)]
+pub enum CredentialBackend {
+    /// `tokens` file in the data directory, readable by anyone who can read the directory
+    #[default]
+    PlainFile,
+    /// `credentials.enc` in the data directory, locked with a passphrase or a key file
+    EncryptedFile,
+    /// Keyring of the desktop, like GNOME Keyring or KWallet
+    SecretService,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Debug)]
+#[serde(default)]
+pub struct CredentialStorage {
+    pub backend: CredentialBackend,
+    /// Used by `EncryptedFile` instead of a passphrase
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<PathBuf>,
+}
+
+impl CredentialStorage {
+    /// Whether `open` has to be given a passphrase
+    pub fn needs_passphrase(&self) -> bool {
+        self.backend == CredentialBackend::EncryptedFile && self.key_file.is_none()
+    }
+
+    pub fn open(
+        &self,
+        data_dir: &Path,
+        passphrase: Option<&str>,
+    ) -> Result<Arc<dyn CredentialStore>, String> {
+        match self.backend {
+            CredentialBackend::PlainFile => Ok(Arc::new(PlainFile)),
+            CredentialBackend::EncryptedFile => {
+                let secret = match &self.key_file {
+                    Some(key_file) => fs::read(key_file)
+                        .map_err(|err| format!("Can't read the key file: {err}"))?,
+                    None => passphrase
+                        .filter(|passphrase| !passphrase.is_empty())
+                        .ok_or("Enter the passphrase of the credentials file")?
+                        .as_bytes()
+                        .to_vec(),
+                };
+                Ok(Arc::new(EncryptedFile::open(
+                    data_dir.join("credentials.enc"),
+                    &secret,
+                )?))
+            }
+            #[cfg(feature = "secret-service")]
+            CredentialBackend::SecretService => {
+                Ok(Arc::new(secret_service::SecretService::connect()?))
+            }
+            #[cfg(not(feature = "secret-service"))]
+            CredentialBackend::SecretService => Err(String::from(
+                "lma was built without the secret-service feature",
+            )),
+        }
+    }
+}
+
+/// Tokens of one service in a store
+#[derive(Clone)]
+pub struct Credentials {
+    store: Arc<dyn CredentialStore>,
+    dir: PathBuf,
+    account: String,
+}
+
+impl Credentials {
+    pub fn new(store: Arc<dyn CredentialStore>, dir: PathBuf, service: &str) -> Self {
+        Self {
+            store,
+            dir,
+            account: service.to_owned(),
+        }
+    }
+
+    /// `tokens` file in `dir`, where lma always kept them
+    pub fn plain(dir: PathBuf, service: &str) -> Self {
+        Self::new(Arc::new(PlainFile), dir, service)
+    }
+
+    #[cfg(test)]
+    pub fn memory() -> Self {
+        Self::new(Arc::new(Memory::default()), PathBuf::new(), "test")
+    }
+
+    pub fn load(&self) -> Result<Option<String>, String> {
+        self.store.load(&self.dir, &self.account)
+    }
+
+    pub fn save(&self, secret: &str) -> Result<(), String> {
+        self.store.save(&self.dir, &self.account, secret)
+    }
+
+    pub fn delete(&self) -> Result<(), String> {
+        self.store.delete(&self.dir, &self.account)
+    }
+
+    /// Moves tokens left in the `tokens` file by earlier versions into the store, unless it
+    /// has its own already. The file is removed either way so no token stays in plain text
+    pub fn take_plain(&self) -> Result<(), String> {
+        if self.store.is_plain() {
+            return Ok(());
+        }
+        if let Some(tokens) = PlainFile.load(&self.dir, &self.account)? {
+            if self.load()?.is_none() {
+                self.save(&tokens)?;
+            }
+            PlainFile.delete(&self.dir, &self.account)?;
+        }
+        Ok(())
+    }
+}
+
+struct PlainFile;
+
+impl CredentialStore for PlainFile {
+    fn load(&self, dir: &Path, _account: &str) -> Result<Option<String>, String> {
+        let path = dir.join("tokens");
+        if !path.exists() {
+            return Ok(None);
+        }
+        let tokens = fs::read_to_string(path).map_err(|err| format!("Can't read tokens: {err}"))?;
+        Ok(Some(tokens).filter(|tokens| !tokens.is_empty()))
+    }
+
+    fn save(&self, dir: &Path, _account: &str, secret: &str) -> Result<(), String> {
+        fs::write(dir.join("tokens"), secret).map_err(|err| format!("Save token: {err}"))
+    }
+
+    fn delete(&self, dir: &Path, _account: &str) -> Result<(), String> {
+        let path = dir.join("tokens");
+        if path.exists() {
+            fs::remove_file(path).map_err(|err| format!("Can't remove tokens: {err}"))?;
+        }
+        Ok(())
+    }
+
+    fn is_plain(&self) -> bool {
+        true
+    }
+}
+
+const ENCRYPTED_MAGIC: &[u8] = b"LMA1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Tokens of every service in one file, encrypted with ChaCha20-Poly1305 under a key
+/// derived with Argon2
+struct EncryptedFile {
+    path: PathBuf,
+    salt: [u8; SALT_LEN],
+    cipher: ChaCha20Poly1305,
+    tokens: Mutex<HashMap<String, String>>,
+}
+
+impl EncryptedFile {
+    /// Creates the file on the first save, errors when `secret` doesn't unlock an existing one
+    fn open(path: PathBuf, secret: &[u8]) -> Result<Self, String> {
+        if !path.exists() {
+            let mut salt = [0; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            return Ok(Self {
+                cipher: cipher(secret, &salt)?,
+                path,
+                salt,
+                tokens: Mutex::default(),
+            });
+        }
+        let contents =
+            fs::read(&path).map_err(|err| format!("Can't read the credentials file: {err}"))?;
+        let header_len = ENCRYPTED_MAGIC.len() + SALT_LEN + NONCE_LEN;
+        if contents.len() < header_len || !contents.starts_with(ENCRYPTED_MAGIC) {
+            return Err(String::from("The credentials file is damaged"));
+        }
+        let (salt, rest) = contents[ENCRYPTED_MAGIC.len()..].split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let salt: [u8; SALT_LEN] = salt.try_into().map_err(|_| "Bad salt")?;
+        let cipher = cipher(secret, &salt)?;
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Wrong passphrase or key file for the credentials file")?;
+        let tokens = serde_json::from_slice(&plaintext).map_err(|err| err.to_string())?;
+        Ok(Self {
+            path,
+            salt,
+            cipher,
+            tokens: Mutex::new(tokens),
+        })
+    }
+
+    fn write(&self, tokens: &HashMap<String, String>) -> Result<(), String> {
+        let plaintext = serde_json::to_vec(tokens).map_err(|err| err.to_string())?;
+        // never reuse a nonce with the same key
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|err| format!("Can't encrypt tokens: {err}"))?;
+        let contents = [ENCRYPTED_MAGIC, &self.salt, &nonce, &ciphertext].concat();
+        fs::write(&self.path, contents).map_err(|err| format!("Save token: {err}"))
+    }
+}
+
+impl CredentialStore for EncryptedFile {
+    fn load(&self, _dir: &Path, account: &str) -> Result<Option<String>, String> {
+        let tokens = self.tokens.lock().map_err(|err| err.to_string())?;
+        Ok(tokens.get(account).cloned())
+    }
+
+    fn save(&self, _dir: &Path, account: &str, secret: &str) -> Result<(), String> {
+        let mut tokens = self.tokens.lock().map_err(|err| err.to_string())?;
+        tokens.insert(account.to_owned(), secret.to_owned());
+        self.write(&tokens)
+    }
+
+    fn delete(&self, _dir: &Path, account: &str) -> Result<(), String> {
+        let mut tokens = self.tokens.lock().map_err(|err| err.to_string())?;
+        if tokens.remove(account).is_some() {
+            self.write(&tokens)?;
+        }
+        Ok(())
+    }
+}
+
+fn cipher(secret: &[u8], salt: &[u8]) -> Result<ChaCha20Poly1305, String> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(secret, salt, &mut key)
+        .map_err(|err| format!("Can't derive the key: {err}"))?;
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct Memory {
+    tokens: Mutex<HashMap<String, String>>,
+}
+
+#[cfg(test)]
+impl CredentialStore for Memory {
+    fn load(&self, _dir: &Path, account: &str) -> Result<Option<String>, String> {
+        Ok(self.tokens.lock().unwrap().get(account).cloned())
+    }
+
+    fn save(&self, _dir: &Path, account: &str, secret: &str) -> Result<(), String> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(account.to_owned(), secret.to_owned());
+        Ok(())
+    }
+
+    fn delete(&self, _dir: &Path, account: &str) -> Result<(), String> {
+        self.tokens.lock().unwrap().remove(account);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lma_credentials_{name}_{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn plain_file() {
+        let dir = test_dir("plain");
+        let credentials = Credentials::plain(dir.clone(), "AniList");
+        assert_eq!(credentials.load(), Ok(None));
+        credentials.save("token").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("tokens")).unwrap(), "token");
+        assert_eq!(credentials.load(), Ok(Some(String::from("token"))));
+        credentials.delete().unwrap();
+        assert!(!dir.join("tokens").exists());
+        credentials.delete().unwrap();
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn encrypted_file() {
+        let dir = test_dir("encrypted");
+        let storage = CredentialStorage {
+            backend: CredentialBackend::EncryptedFile,
+            key_file: None,
+        };
+        assert!(storage.needs_passphrase());
+        assert!(storage.open(&dir, None).is_err());
+        let store = storage.open(&dir, Some("passphrase")).unwrap();
+        let anilist = Credentials::new(store.clone(), dir.clone(), "AniList");
+        let kitsu = Credentials::new(store, dir.clone(), "Kitsu");
+        anilist.save("anilist token").unwrap();
+        kitsu.save("kitsu token").unwrap();
+        anilist.delete().unwrap();
+
+        let contents = fs::read(dir.join("credentials.enc")).unwrap();
+        assert!(contents.starts_with(ENCRYPTED_MAGIC));
+        assert!(!String::from_utf8_lossy(&contents).contains("kitsu token"));
+        assert!(!dir.join("tokens").exists());
+
+        assert!(storage.open(&dir, Some("wrong")).is_err());
+        let store = storage.open(&dir, Some("passphrase")).unwrap();
+        assert_eq!(store.load(&dir, "AniList"), Ok(None));
+        assert_eq!(
+            store.load(&dir, "Kitsu"),
+            Ok(Some(String::from("kitsu token")))
+        );
+
+        // an earlier version saved the token in plain text
+        fs::write(dir.join("tokens"), "old token").unwrap();
+        let anilist = Credentials::new(store.clone(), dir.clone(), "AniList");
+        anilist.take_plain().unwrap();
+        assert!(!dir.join("tokens").exists());
+        assert_eq!(anilist.load(), Ok(Some(String::from("old token"))));
+        fs::write(dir.join("tokens"), "older token").unwrap();
+        anilist.take_plain().unwrap();
+        assert!(!dir.join("tokens").exists());
+        assert_eq!(anilist.load(), Ok(Some(String::from("old token"))));
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn encrypted_file_with_key_file() {
+        let dir = test_dir("key_file");
+        let key_file = dir.join("key");
+        fs::write(&key_file, [7; 32]).unwrap();
+        let storage = CredentialStorage {
+            backend: CredentialBackend::EncryptedFile,
+            key_file: Some(key_file.clone()),
+        };
+        assert!(!storage.needs_passphrase());
+        let store = storage.open(&dir, None).unwrap();
+        store.save(&dir, "Shikimori", "token").unwrap();
+        let store = storage.open(&dir, None).unwrap();
+        assert_eq!(
+            store.load(&dir, "Shikimori"),
+            Ok(Some(String::from("token")))
+        );
+
+        fs::write(&key_file, [8; 32]).unwrap();
+        assert!(storage.open(&dir, None).is_err());
+        _ = fs::remove_dir_all(dir);
+    }
+}
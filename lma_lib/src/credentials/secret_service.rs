@@ -0,0 +1,345 @@
+use super::CredentialStore;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use zbus::blocking::Connection;
+use zbus::zvariant::{DynamicDeserialize, DynamicType, OwnedObjectPath, OwnedValue, Value};
+
+const DESTINATION: &str = "org.freedesktop.secrets";
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+const SERVICE_INTERFACE: &str = "org.freedesktop.Secret.Service";
+const COLLECTION_INTERFACE: &str = "org.freedesktop.Secret.Collection";
+const ITEM_INTERFACE: &str = "org.freedesktop.Secret.Item";
+/// Returned instead of a prompt when none is needed
+const NO_PROMPT: &str = "/";
+const APPLICATION: &str = "lma";
+
+/// Session, parameters, value and content type
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// Tokens in the desktop keyring, talks to it over D-Bus
+pub struct SecretService {
+    connection: Connection,
+    /// None on peer-to-peer connections
+    destination: Option<&'static str>,
+    session: OwnedObjectPath,
+}
+
+impl SecretService {
+    pub fn connect() -> Result<Self, String> {
+        let connection = Connection::session()
+            .map_err(|err| format!("Can't connect to the session bus: {err}"))?;
+        Self::open(connection, Some(DESTINATION))
+    }
+
+    fn open(connection: Connection, destination: Option<&'static str>) -> Result<Self, String> {
+        // plain sessions pass secrets as they are, the bus is local to the user anyway
+        let (_, session): (OwnedValue, OwnedObjectPath) = call(
+            &connection,
+            destination,
+            SERVICE_PATH,
+            SERVICE_INTERFACE,
+            "OpenSession",
+            &("plain", Value::from("")),
+        )?;
+        Ok(Self {
+            connection,
+            destination,
+            session,
+        })
+    }
+
+    fn call<B, R>(&self, path: &str, interface: &str, method: &str, body: &B) -> Result<R, String>
+    where
+        B: Serialize + DynamicType,
+        R: for<'d> DynamicDeserialize<'d>,
+    {
+        call(
+            &self.connection,
+            self.destination,
+            path,
+            interface,
+            method,
+            body,
+        )
+    }
+
+    fn find_item(&self, account: &str) -> Result<Option<OwnedObjectPath>, String> {
+        let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = self.call(
+            SERVICE_PATH,
+            SERVICE_INTERFACE,
+            "SearchItems",
+            &attributes(account),
+        )?;
+        if unlocked.is_empty() && !locked.is_empty() {
+            return Err(String::from("Unlock the keyring to use the saved login"));
+        }
+        Ok(unlocked.into_iter().next())
+    }
+}
+
+impl CredentialStore for SecretService {
+    fn load(&self, _dir: &Path, account: &str) -> Result<Option<String>, String> {
+        let Some(item) = self.find_item(account)? else {
+            return Ok(None);
+        };
+        let secret: Secret = self.call(&item, ITEM_INTERFACE, "GetSecret", &self.session)?;
+        String::from_utf8(secret.2)
+            .map(Some)
+            .map_err(|err| err.to_string())
+    }
+
+    fn save(&self, _dir: &Path, account: &str, secret: &str) -> Result<(), String> {
+        let properties = HashMap::from([
+            (
+                "org.freedesktop.Secret.Item.Label",
+                Value::from(format!("lma {account} login")),
+            ),
+            (
+                "org.freedesktop.Secret.Item.Attributes",
+                Value::from(attributes(account)),
+            ),
+        ]);
+        let secret = (
+            &self.session,
+            Vec::<u8>::new(),
+            secret.as_bytes(),
+            "text/plain",
+        );
+        let (_, prompt): (OwnedObjectPath, OwnedObjectPath) = self.call(
+            DEFAULT_COLLECTION,
+            COLLECTION_INTERFACE,
+            "CreateItem",
+            &(properties, secret, true),
+        )?;
+        check_prompt(&prompt)
+    }
+
+    fn delete(&self, _dir: &Path, account: &str) -> Result<(), String> {
+        let Some(item) = self.find_item(account)? else {
+            return Ok(());
+        };
+        let prompt: OwnedObjectPath = self.call(&item, ITEM_INTERFACE, "Delete", &())?;
+        check_prompt(&prompt)
+    }
+}
+
+fn call<B, R>(
+    connection: &Connection,
+    destination: Option<&str>,
+    path: &str,
+    interface: &str,
+    method: &str,
+    body: &B,
+) -> Result<R, String>
+where
+    B: Serialize + DynamicType,
+    R: for<'d> DynamicDeserialize<'d>,
+{
+    connection
+        .call_method(destination, path, Some(interface), method, body)
+        .and_then(|message| message.body().deserialize())
+        .map_err(|err| format!("Secret Service: {err}"))
+}
+
+fn attributes(account: &str) -> HashMap<&str, &str> {
+    HashMap::from([("application", APPLICATION), ("account", account)])
+}
+
+/// lma has no way to show the keyring's unlock prompts
+fn check_prompt(prompt: &OwnedObjectPath) -> Result<(), String> {
+    if prompt.as_str() == NO_PROMPT {
+        Ok(())
+    } else {
+        Err(String::from("Unlock the keyring to save the login"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+    use zbus::blocking::ConnectionBuilder;
+    use zbus::zvariant::ObjectPath;
+    use zbus::{fdo, interface, Guid, ObjectServer};
+
+    #[derive(Default)]
+    struct Keyring {
+        /// Attributes and secret of every item by its path
+        items: HashMap<String, (HashMap<String, String>, Vec<u8>)>,
+        created: usize,
+        locked: bool,
+    }
+
+    type SharedKeyring = Arc<Mutex<Keyring>>;
+
+    struct FakeService(SharedKeyring);
+
+    #[interface(name = "org.freedesktop.Secret.Service")]
+    impl FakeService {
+        fn open_session(
+            &self,
+            algorithm: &str,
+            _input: Value<'_>,
+        ) -> fdo::Result<(OwnedValue, OwnedObjectPath)> {
+            if algorithm != "plain" {
+                return Err(fdo::Error::NotSupported(algorithm.to_owned()));
+            }
+            Ok((
+                Value::from("").try_into().map_err(zbus::Error::from)?,
+                ObjectPath::from_static_str_unchecked("/org/freedesktop/secrets/session/1").into(),
+            ))
+        }
+
+        fn search_items(
+            &self,
+            attributes: HashMap<String, String>,
+        ) -> (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) {
+            let keyring = self.0.lock().unwrap();
+            let found = keyring
+                .items
+                .iter()
+                .filter(|(_, (item_attributes, _))| item_attributes == &attributes)
+                .map(|(path, _)| ObjectPath::try_from(path.clone()).unwrap().into())
+                .collect();
+            if keyring.locked {
+                (Vec::new(), found)
+            } else {
+                (found, Vec::new())
+            }
+        }
+    }
+
+    struct FakeCollection(SharedKeyring);
+
+    #[interface(name = "org.freedesktop.Secret.Collection")]
+    impl FakeCollection {
+        async fn create_item(
+            &self,
+            #[zbus(object_server)] server: &ObjectServer,
+            properties: HashMap<String, OwnedValue>,
+            secret: Secret,
+            replace: bool,
+        ) -> fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+            let attributes: HashMap<String, String> = properties
+                .get("org.freedesktop.Secret.Item.Attributes")
+                .ok_or_else(|| fdo::Error::InvalidArgs(String::from("No attributes")))?
+                .try_clone()
+                .and_then(TryInto::try_into)
+                .map_err(zbus::Error::from)?;
+            let path = {
+                let mut keyring = self.0.lock().unwrap();
+                if keyring.locked {
+                    return Ok((
+                        ObjectPath::from_static_str_unchecked(NO_PROMPT).into(),
+                        ObjectPath::from_static_str_unchecked("/org/freedesktop/secrets/prompt/1")
+                            .into(),
+                    ));
+                }
+                let existing = keyring
+                    .items
+                    .iter()
+                    .find(|(_, (item_attributes, _))| replace && item_attributes == &attributes)
+                    .map(|(path, _)| path.clone());
+                if let Some(path) = existing {
+                    keyring.items.insert(path, (attributes, secret.2));
+                    return Ok((
+                        ObjectPath::from_static_str_unchecked(NO_PROMPT).into(),
+                        ObjectPath::from_static_str_unchecked(NO_PROMPT).into(),
+                    ));
+                }
+                keyring.created += 1;
+                let path = format!(
+                    "/org/freedesktop/secrets/collection/login/{}",
+                    keyring.created
+                );
+                keyring.items.insert(path.clone(), (attributes, secret.2));
+                path
+            };
+            server
+                .at(path.clone(), FakeItem(self.0.clone(), path.clone()))
+                .await?;
+            Ok((
+                ObjectPath::try_from(path).unwrap().into(),
+                ObjectPath::from_static_str_unchecked(NO_PROMPT).into(),
+            ))
+        }
+    }
+
+    struct FakeItem(SharedKeyring, String);
+
+    #[interface(name = "org.freedesktop.Secret.Item")]
+    impl FakeItem {
+        fn get_secret(&self, session: OwnedObjectPath) -> fdo::Result<Secret> {
+            let keyring = self.0.lock().unwrap();
+            let (_, value) = keyring
+                .items
+                .get(&self.1)
+                .ok_or_else(|| fdo::Error::UnknownObject(self.1.clone()))?;
+            Ok((
+                session,
+                Vec::new(),
+                value.clone(),
+                String::from("text/plain"),
+            ))
+        }
+
+        fn delete(&self) -> OwnedObjectPath {
+            self.0.lock().unwrap().items.remove(&self.1);
+            ObjectPath::from_static_str_unchecked(NO_PROMPT).into()
+        }
+    }
+
+    /// Secret Service on the other end of a socket, the connection has to outlive the test
+    fn connect(keyring: &SharedKeyring) -> (SecretService, Connection) {
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        let guid = Guid::generate();
+        let keyring = keyring.clone();
+        let server = std::thread::spawn(move || {
+            ConnectionBuilder::unix_stream(server_stream)
+                .server(guid)
+                .unwrap()
+                .p2p()
+                .serve_at(SERVICE_PATH, FakeService(keyring.clone()))
+                .unwrap()
+                .serve_at(DEFAULT_COLLECTION, FakeCollection(keyring))
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let client = ConnectionBuilder::unix_stream(client_stream)
+            .p2p()
+            .build()
+            .unwrap();
+        let server = server.join().unwrap();
+        (SecretService::open(client, None).unwrap(), server)
+    }
+
+    #[test]
+    fn keyring() {
+        let keyring = SharedKeyring::default();
+        let (store, _server) = connect(&keyring);
+        let dir = Path::new("");
+        assert_eq!(store.load(dir, "AniList"), Ok(None));
+        store.save(dir, "AniList", "token").unwrap();
+        store.save(dir, "Kitsu", "kitsu token").unwrap();
+        store.save(dir, "AniList", "new token").unwrap();
+        assert_eq!(keyring.lock().unwrap().items.len(), 2);
+        assert_eq!(
+            store.load(dir, "AniList"),
+            Ok(Some(String::from("new token")))
+        );
+        store.delete(dir, "AniList").unwrap();
+        assert_eq!(store.load(dir, "AniList"), Ok(None));
+        assert_eq!(
+            store.load(dir, "Kitsu"),
+            Ok(Some(String::from("kitsu token")))
+        );
+
+        keyring.lock().unwrap().locked = true;
+        assert!(store.load(dir, "Kitsu").is_err());
+        assert!(store.save(dir, "Kitsu", "token").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{RelationType, Show};
+use std::collections::HashMap;
+
+/// Relation between two shows by their ids on the service, as kept in the database
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ShowRelation {
+    pub service_id: usize,
+    pub related_id: usize,
+    pub relation: RelationType,
+}
+
+/// Moves shows of the same franchise right after the first of them and orders them from
+/// prequels to sequels, shows that stand alone keep their place
+pub fn group_franchises(shows: Vec<Show>, relations: &[ShowRelation]) -> Vec<Show> {
+    let positions: HashMap<usize, usize> = shows
+        .iter()
+        .enumerate()
+        .filter(|(_, show)| show.service_id != 0)
+        .map(|(position, show)| (show.service_id, position))
+        .collect();
+    let mut groups = Groups::new(shows.len());
+    // shows that come before each show in the franchise
+    let mut earlier: HashMap<usize, Vec<usize>> = HashMap::new();
+    for relation in relations
+        .iter()
+        .filter(|relation| relation.relation.same_franchise())
+    {
+        let (Some(&show), Some(&related)) = (
+            positions.get(&relation.service_id),
+            positions.get(&relation.related_id),
+        ) else {
+            continue;
+        };
+        groups.join(show, related);
+        match relation.relation {
+            RelationType::Prequel | RelationType::ParentStory => {
+                earlier.entry(show).or_default().push(related);
+            }
+            RelationType::Sequel
+            | RelationType::SideStory
+            | RelationType::SpinOff
+            | RelationType::Summary => earlier.entry(related).or_default().push(show),
+            RelationType::AlternativeVersion | RelationType::Other => {}
+        }
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for position in 0..shows.len() {
+        members
+            .entry(groups.find(position))
+            .or_default()
+            .push(position);
+    }
+    let mut shows: Vec<Option<Show>> = shows.into_iter().map(Some).collect();
+    let mut grouped = Vec::with_capacity(shows.len());
+    for position in 0..shows.len() {
+        let Some(mut group) = members.remove(&groups.find(position)) else {
+            // already placed with the rest of its franchise
+            continue;
+        };
+        let depths = depths(&group, &earlier);
+        group.sort_by_key(|member| (depths.get(member).copied().unwrap_or_default(), *member));
+        let franchise = (group.len() > 1)
+            .then(|| shows[group[0]].as_ref().map(|show| show.local_id))
+            .flatten();
+        for member in group {
+            if let Some(mut show) = shows[member].take() {
+                show.franchise = franchise;
+                grouped.push(show);
+            }
+        }
+    }
+    grouped
+}
+
+/// How many prequels come before each member, cycles stop growing after one pass per member
+fn depths(group: &[usize], earlier: &HashMap<usize, Vec<usize>>) -> HashMap<usize, usize> {
+    let mut depths: HashMap<usize, usize> = group.iter().map(|member| (*member, 0)).collect();
+    for _ in 0..group.len() {
+        let mut changed = false;
+        for member in group {
+            let depth = earlier
+                .get(member)
+                .into_iter()
+                .flatten()
+                .filter_map(|prequel| depths.get(prequel))
+                .map(|depth| depth + 1)
+                .max()
+                .unwrap_or_default();
+            if depth > depths[member] {
+                depths.insert(*member, depth);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    depths
+}
+
+/// Union-find over positions in the list
+struct Groups {
+    parents: Vec<usize>,
+}
+
+impl Groups {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, position: usize) -> usize {
+        let mut root = position;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        self.parents[position] = root;
+        root
+    }
+
+    /// The group keeps the root that comes first in the list
+    fn join(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[a.max(b)] = a.min(b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show(local_id: usize, service_id: usize) -> Show {
+        Show {
+            local_id,
+            title: format!("Show {local_id}"),
+            service_id,
+            episodes: Vec::new(),
+            progress: 0,
+            franchise: None,
+        }
+    }
+
+    fn relation(service_id: usize, related_id: usize, relation: RelationType) -> ShowRelation {
+        ShowRelation {
+            service_id,
+            related_id,
+            relation,
+        }
+    }
+
+    fn order(shows: &[Show]) -> Vec<usize> {
+        shows.iter().map(|show| show.local_id).collect()
+    }
+
+    #[test]
+    fn groups_sequels_after_prequels() {
+        // sorted by title, the second season comes first
+        let shows = vec![show(1, 200), show(2, 300), show(3, 100), show(4, 0)];
+        let relations = [
+            relation(100, 200, RelationType::Sequel),
+            relation(200, 100, RelationType::Prequel),
+            relation(300, 100, RelationType::ParentStory),
+        ];
+        let grouped = group_franchises(shows, &relations);
+        assert_eq!(order(&grouped), [3, 1, 2, 4]);
+        assert_eq!(grouped[0].franchise, Some(3));
+        assert_eq!(grouped[2].franchise, Some(3));
+        assert_eq!(grouped[3].franchise, None, "Stands alone");
+    }
+
+    #[test]
+    fn ignores_other_relations() {
+        let shows = vec![show(1, 100), show(2, 200), show(3, 300)];
+        let relations = [
+            relation(100, 300, RelationType::Other),
+            relation(100, 400, RelationType::Sequel),
+        ];
+        let grouped = group_franchises(shows, &relations);
+        assert_eq!(order(&grouped), [1, 2, 3]);
+        assert!(grouped.iter().all(|show| show.franchise.is_none()));
+    }
+
+    #[test]
+    fn survives_cycles() {
+        let shows = vec![show(1, 100), show(2, 200)];
+        let relations = [
+            relation(100, 200, RelationType::Sequel),
+            relation(200, 100, RelationType::Sequel),
+        ];
+        let grouped = group_franchises(shows, &relations);
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped[1].franchise, grouped[0].franchise);
+    }
+}
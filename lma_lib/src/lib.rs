@@ -1,23 +1,55 @@
 mod api;
-pub use api::{local::Local, mal::MAL};
+mod credentials;
+mod franchise;
+mod mapping;
+mod nfo;
+mod sync;
 pub use api::{
-    AlternativeTitles, EpisodeStatus, Service, ServiceEpisodeDetails, ServiceEpisodeUser,
-    ServiceTitle, ServiceType,
+    anilist::AniList,
+    boxed::{forget_login, new_service, AnyService, DynService},
+    cached::{CacheTtl, Cached},
+    kitsu::Kitsu,
+    local::Local,
+    mal::{MalApp, MAL},
+    mirror::Mirror,
+    shikimori::Shikimori,
 };
+pub use api::{
+    AlternativeTitles, Capabilities, EpisodeStatus, MediaType, RelationType, Season, SeasonName,
+    Service, ServiceAiring, ServiceEpisodeDetails, ServiceEpisodeUser, ServiceFailure,
+    ServiceListEntry, ServiceListPage, ServiceRelation, ServiceSearchPage, ServiceTitle,
+    ServiceType, DEFAULT_REDIRECT_PORT,
+};
+pub use credentials::{CredentialBackend, CredentialStorage, CredentialStore, Credentials};
+use franchise::{group_franchises, ShowRelation};
 pub use lib_mal::*;
+use mapping::MappingEntry;
+pub use nfo::{EpisodeNfo, ShowNfo};
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqliteConnectOptions;
 use sqlx::{Sqlite, SqlitePool};
-use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::OnceLock;
+pub use sync::{
+    service_update, EntryField, FieldChange, ProgressChange, RemoteEntries, SyncDirection,
+    SyncPolicy, SyncProgress, UserEntry,
+};
+use time::OffsetDateTime;
 
 pub struct AnimeList<T: Service + Send + Sync> {
     db_connection: sqlx::Pool<Sqlite>,
+    data_dir: PathBuf,
     pub service: T,
     pub title_sort: TitleSort,
+    pub sync_policy: SyncPolicy,
+    /// Shows of the same franchise follow each other in the list
+    pub group_franchises: bool,
+    /// Offline database from the data directory, read the first time an nfo needs it
+    offline_database: OnceLock<Vec<MappingEntry>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
@@ -75,6 +107,7 @@ impl<T: Service + Send + Sync> AnimeList<T> {
                 progress,
                 episodes: Vec::new(),
                 service_id,
+                franchise: None,
             };
             if episode_number == 0 || !shows.iter().any(|s| s.local_id == show_id) {
                 shows.push(show_with_no_episodes);
@@ -104,9 +137,93 @@ impl<T: Service + Send + Sync> AnimeList<T> {
         for show in &mut shows {
             show.episodes.sort_by_key(|episode| episode.number);
         }
+        if self.group_franchises {
+            let relations = self.get_relations().await?;
+            shows = group_franchises(shows, &relations);
+        }
         Ok(shows)
     }
 
+    /// Relations between shows on the current service, kept since they were last refreshed
+    async fn get_relations(&self) -> Result<Vec<ShowRelation>, String> {
+        let service = self.service.get_service_type();
+        let service = service.as_str();
+        let rows = sqlx::query!(
+            "SELECT service_id, related_id, relation FROM Relations WHERE service = ?1",
+            service
+        )
+        .fetch_all(&self.db_connection)
+        .await
+        .map_err(|e| e.to_string())?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(ShowRelation {
+                    service_id: usize::try_from(row.service_id.unwrap_or_default())
+                        .map_err(|e| e.to_string())?,
+                    related_id: usize::try_from(row.related_id.unwrap_or_default())
+                        .map_err(|e| e.to_string())?,
+                    relation: RelationType::parse(&row.relation.unwrap_or_default()),
+                })
+            })
+            .collect()
+    }
+
+    /// Asks the service for shows related to this one and keeps them in place of the old ones
+    async fn refresh_relations(
+        &mut self,
+        service_id: usize,
+    ) -> Result<Vec<ServiceRelation>, String> {
+        let relations = self.service.get_relations(service_id).await?;
+        let service = self.service.get_service_type();
+        let service = service.as_str();
+        let service_id = u32::try_from(service_id).map_err(|e| e.to_string())?;
+        sqlx::query!(
+            "DELETE FROM Relations WHERE service = ?1 AND service_id = ?2",
+            service,
+            service_id
+        )
+        .execute(&self.db_connection)
+        .await
+        .map_err(|e| e.to_string())?;
+        for related in &relations {
+            let related_id = u32::try_from(related.show.service_id).map_err(|e| e.to_string())?;
+            let relation = related.relation.as_str();
+            sqlx::query!(
+                "REPLACE INTO Relations (service, service_id, related_id, relation) VALUES (?1, ?2, ?3, ?4)",
+                service, service_id, related_id, relation
+            )
+            .execute(&self.db_connection)
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+        Ok(relations)
+    }
+
+    /// Sequels of the show that aren't in the list yet, empty when the service doesn't know relations
+    pub async fn find_sequels(&mut self, local_id: usize) -> Result<Vec<ServiceTitle>, String> {
+        if !self.service.capabilities().relations {
+            return Ok(Vec::new());
+        }
+        let shows = self.get_list().await?;
+        let Some(show) = shows.iter().find(|show| show.local_id == local_id) else {
+            return Ok(Vec::new());
+        };
+        if show.service_id == 0 {
+            return Ok(Vec::new());
+        }
+        let in_list: HashSet<usize> = shows.iter().map(|show| show.service_id).collect();
+        Ok(self
+            .refresh_relations(show.service_id)
+            .await?
+            .into_iter()
+            .filter(|related| {
+                related.relation == RelationType::Sequel
+                    && !in_list.contains(&related.show.service_id)
+            })
+            .map(|related| related.show)
+            .collect())
+    }
+
     /// Returns local id of the added show
     pub async fn add_show(
         &self,
@@ -130,9 +247,166 @@ impl<T: Service + Send + Sync> AnimeList<T> {
 
         let row = rows.first().ok_or("Can't get row")?;
         let local_id: usize = usize::try_from(row.id).map_err(|e| e.to_string())?;
+        let service = self.service.get_service_type();
+        if service != ServiceType::Local {
+            let service_id = usize::try_from(sync_service_id).map_err(|e| e.to_string())?;
+            self.set_service_id(local_id, &service, service_id).await?;
+        }
         Ok(local_id)
     }
 
+    /// Id of the show on a service other than the current one
+    pub async fn get_service_id(
+        &self,
+        local_id: usize,
+        service: &ServiceType,
+    ) -> Result<Option<usize>, String> {
+        let local_id = u32::try_from(local_id).map_err(|e| e.to_string())?;
+        let service = service.as_str();
+        let row = sqlx::query!(
+            "SELECT service_id FROM ServiceIds
+            WHERE show_id = ?1 AND service = ?2",
+            local_id,
+            service
+        )
+        .fetch_optional(&self.db_connection)
+        .await
+        .map_err(|e| e.to_string())?;
+        row.and_then(|row| row.service_id)
+            .map(|service_id| usize::try_from(service_id).map_err(|e| e.to_string()))
+            .transpose()
+    }
+
+    pub async fn set_service_id(
+        &self,
+        local_id: usize,
+        service: &ServiceType,
+        service_id: usize,
+    ) -> Result<(), String> {
+        let local_id = u32::try_from(local_id).map_err(|e| e.to_string())?;
+        let service_id = u32::try_from(service_id).map_err(|e| e.to_string())?;
+        let service = service.as_str();
+        sqlx::query!(
+            "REPLACE INTO ServiceIds (show_id, service, service_id) VALUES (?1, ?2, ?3)",
+            local_id,
+            service,
+            service_id
+        )
+        .execute(&self.db_connection)
+        .await
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Adds ids from an anime-offline-database style file to shows that already
+    /// have an id on one of the services, returns the number of ids added
+    pub async fn import_id_mappings(&self, path: &Path) -> Result<usize, String> {
+        let mappings = mapping::load(path)?;
+        let rows = sqlx::query!("SELECT show_id, service, service_id FROM ServiceIds")
+            .fetch_all(&self.db_connection)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut known = HashMap::new();
+        for row in rows {
+            let (Some(show_id), Some(service), Some(service_id)) =
+                (row.show_id, row.service, row.service_id)
+            else {
+                continue;
+            };
+            if let Some(service) = ServiceType::parse(&service) {
+                known.insert((service, service_id), show_id);
+            }
+        }
+        let mut transaction = self
+            .db_connection
+            .begin()
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut added = 0;
+        for ids in mappings {
+            let Some(show_id) = ids.iter().find_map(|(service, service_id)| {
+                let service_id = i64::try_from(*service_id).ok()?;
+                known.get(&(service.clone(), service_id))
+            }) else {
+                continue;
+            };
+            for (service, service_id) in &ids {
+                let service = service.as_str();
+                let service_id = u32::try_from(*service_id).map_err(|e| e.to_string())?;
+                let result = sqlx::query!(
+                    "INSERT OR IGNORE INTO ServiceIds (show_id, service, service_id) VALUES (?1, ?2, ?3)",
+                    show_id,
+                    service,
+                    service_id
+                )
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| e.to_string())?;
+                added += usize::try_from(result.rows_affected()).map_err(|e| e.to_string())?;
+            }
+        }
+        transaction.commit().await.map_err(|e| e.to_string())?;
+        Ok(added)
+    }
+
+    /// Replaces service ids of all shows with their ids on `to`, MAL ids for Local,
+    /// nothing changes if any of the shows doesn't have one
+    pub async fn migrate_service(
+        &self,
+        from: &ServiceType,
+        to: &ServiceType,
+    ) -> Result<usize, String> {
+        let (from, to) = (from.id_service(), to.id_service());
+        if from == to {
+            return Ok(0);
+        }
+        record_service_ids(&self.db_connection, &from).await?;
+        let to_str = to.as_str();
+        let rows = sqlx::query!(
+            "SELECT Shows.id, Shows.title, ServiceIds.service_id
+            FROM Shows
+            LEFT JOIN ServiceIds ON Shows.id = ServiceIds.show_id AND ServiceIds.service = ?1",
+            to_str
+        )
+        .fetch_all(&self.db_connection)
+        .await
+        .map_err(|e| e.to_string())?;
+        let missing: Vec<_> = rows
+            .iter()
+            .filter(|row| row.service_id.is_none())
+            .map(|row| row.title.clone().unwrap_or_default())
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "No {} ids for: {}",
+                to.as_str(),
+                missing.join(", ")
+            ));
+        }
+        let mut transaction = self
+            .db_connection
+            .begin()
+            .await
+            .map_err(|e| e.to_string())?;
+        // ids are unique, old ones could clash with the new ones
+        sqlx::query!("UPDATE Shows SET sync_service_id = NULL")
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| e.to_string())?;
+        for row in &rows {
+            sqlx::query!(
+                "UPDATE Shows SET sync_service_id = ?1 WHERE id = ?2",
+                row.service_id,
+                row.id
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+        transaction.commit().await.map_err(|e| e.to_string())?;
+        Ok(rows.len())
+    }
+
     pub async fn get_local_show_id(&self, title: &str) -> Result<usize, String> {
         let rows = sqlx::query!(
             "SELECT id FROM Shows 
@@ -149,7 +423,34 @@ impl<T: Service + Send + Sync> AnimeList<T> {
         Ok(local_id)
     }
 
+    pub async fn get_local_show_id_by_service_id(
+        &self,
+        service_id: usize,
+    ) -> Result<usize, String> {
+        let service_id = u32::try_from(service_id).map_err(|e| e.to_string())?;
+        let row = sqlx::query!(
+            "SELECT id FROM Shows 
+            WHERE sync_service_id=?1",
+            service_id
+        )
+        .fetch_optional(&self.db_connection)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Can't get row")?;
+        usize::try_from(row.id.ok_or("Can't get id from service id")?).map_err(|e| e.to_string())
+    }
+
     pub async fn set_progress(&self, id: usize, progress: usize) -> Result<(), String> {
+        self.set_progress_at(id, progress, &sync::current_timestamp()?)
+            .await
+    }
+
+    async fn set_progress_at(
+        &self,
+        id: usize,
+        progress: usize,
+        updated_at: &str,
+    ) -> Result<(), String> {
         let id = u32::try_from(id).map_err(|e| e.to_string())?;
         let progress = u32::try_from(progress).map_err(|e| e.to_string())?;
         sqlx::query!(
@@ -163,9 +464,31 @@ impl<T: Service + Send + Sync> AnimeList<T> {
         .await
         .map_err(|e| e.to_string())?;
 
+        sqlx::query!(
+            "REPLACE INTO ProgressUpdates (show_id, updated_at) VALUES (?1, ?2)",
+            id,
+            updated_at
+        )
+        .execute(&self.db_connection)
+        .await
+        .map_err(|e| e.to_string())?;
+
         Ok(())
     }
 
+    async fn get_progress_updated_at(&self, id: usize) -> Result<Option<String>, String> {
+        let id = u32::try_from(id).map_err(|e| e.to_string())?;
+        let row = sqlx::query!(
+            "SELECT updated_at FROM ProgressUpdates
+            WHERE show_id = ?1",
+            id
+        )
+        .fetch_optional(&self.db_connection)
+        .await
+        .map_err(|e| e.to_string())?;
+        Ok(row.and_then(|row| row.updated_at))
+    }
+
     pub async fn add_episode(
         &self,
         show_id: usize,
@@ -189,41 +512,502 @@ impl<T: Service + Send + Sync> AnimeList<T> {
         Ok(())
     }
 
-    pub async fn update_progress(&mut self) -> Result<(), String> {
+    /// Leaves the path alone, unlike `add_episode`
+    pub async fn update_episode_metadata(
+        &self,
+        show_id: usize,
+        episode_number: usize,
+        title: &str,
+        extra_info: usize,
+        score: f32,
+    ) -> Result<(), String> {
+        let show_id = u32::try_from(show_id).map_err(|e| e.to_string())?;
+        let episode_number = u32::try_from(episode_number).map_err(|e| e.to_string())?;
+        let extra_info = u32::try_from(extra_info).map_err(|e| e.to_string())?;
+        sqlx::query!(
+            "UPDATE Episodes SET title = ?3, extra_info = ?4, score = ?5
+            WHERE show_id = ?1 AND episode_number = ?2",
+            show_id,
+            episode_number,
+            title,
+            extra_info,
+            score
+        )
+        .execute(&self.db_connection)
+        .await
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Gets episode titles, scores, filler/recap flags and related shows from the service again
+    /// for one show, or every show when `local_id` is None. Returns how many episodes were updated
+    pub async fn refresh_metadata(
+        &mut self,
+        local_id: Option<usize>,
+        precise_score: bool,
+        progress: &SyncProgress,
+    ) -> Result<usize, String> {
+        let relations = self.service.capabilities().relations;
+        let shows: Vec<Show> = self
+            .get_list()
+            .await?
+            .into_iter()
+            .filter(|show| local_id.is_none_or(|local_id| show.local_id == local_id))
+            .filter(|show| show.service_id != 0 && (relations || show.has_local_files()))
+            .collect();
+        progress.start(shows.len());
+        let mut updated = 0;
+        let mut failed = Vec::new();
+        for show in shows {
+            match self.refresh_show_metadata(&show, precise_score).await {
+                Ok(count) => updated += count,
+                Err(why) => failed.push(format!("{}: {why}", show.title)),
+            }
+            progress.finish_one(show.service_id);
+        }
+        if failed.is_empty() {
+            Ok(updated)
+        } else {
+            Err(format!("Can't refresh {}", failed.join(", ")))
+        }
+    }
+
+    async fn refresh_show_metadata(
+        &mut self,
+        show: &Show,
+        precise_score: bool,
+    ) -> Result<usize, String> {
+        if self.service.capabilities().relations {
+            self.refresh_relations(show.service_id).await?;
+        }
+        if !show.has_local_files() {
+            return Ok(0);
+        }
+        self.service.invalidate_cache(show.service_id).await?;
+        let details: HashMap<usize, ServiceEpisodeDetails> = self
+            .service
+            .get_episodes(show.service_id, precise_score)
+            .await?
+            .into_iter()
+            .filter_map(|episode| Some((episode.number?, episode)))
+            .collect();
+        let mut updated = 0;
+        for episode in &show.episodes {
+            let Some(details) = details.get(&episode.number) else {
+                continue;
+            };
+            // same priority as when the episode was added, an empty title doesn't replace anything
+            let title = EpisodeNfo::read(&episode.path)
+                .ok()
+                .flatten()
+                .and_then(|nfo| nfo.title)
+                .or_else(|| details.title.clone())
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| episode.title.clone());
+            self.update_episode_metadata(
+                show.local_id,
+                episode.number,
+                &title,
+                extra_info(
+                    details.recap.unwrap_or_default(),
+                    details.filler.unwrap_or_default(),
+                ),
+                details.score.unwrap_or_default(),
+            )
+            .await?;
+            updated += 1;
+        }
+        Ok(updated)
+    }
+
+    /// Fetches entries of every show in parallel, limited by the service,
+    /// dropping the future cancels the fetch without changing anything
+    pub async fn fetch_remote_entries(
+        &mut self,
+        progress: &SyncProgress,
+    ) -> Result<RemoteEntries, String> {
         if !self.service.is_logged_in() {
-            return Err(String::from("Can't progress, user not logged in"));
+            return Err(String::from("Can't sync, user not logged in"));
+        }
+        let service_ids: Vec<usize> = self
+            .get_list()
+            .await?
+            .iter()
+            .map(|show| show.service_id)
+            .collect();
+        progress.start(service_ids.len());
+        self.service
+            .get_user_entries_details(&service_ids, progress)
+            .await
+    }
+
+    /// Next episodes of the shows being watched, soonest first
+    pub async fn airing_schedule(&mut self) -> Result<Vec<AiringShow>, String> {
+        let watching = EpisodeStatus::Watching.as_str();
+        let rows = sqlx::query!(
+            "SELECT Shows.id, Shows.title, Shows.sync_service_id, Shows.progress
+            FROM Shows
+            JOIN UserEntries ON Shows.id = UserEntries.show_id
+            WHERE UserEntries.status = ?1",
+            watching
+        )
+        .fetch_all(&self.db_connection)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let mut shows = HashMap::new();
+        for row in rows {
+            let local_id = usize::try_from(row.id).map_err(|e| e.to_string())?;
+            let service_id = usize::try_from(row.sync_service_id.unwrap_or_default())
+                .map_err(|e| e.to_string())?;
+            let progress =
+                usize::try_from(row.progress.unwrap_or_default()).map_err(|e| e.to_string())?;
+            // shows without an id on the service have nothing to ask about
+            if service_id != 0 {
+                shows.insert(
+                    service_id,
+                    (local_id, row.title.unwrap_or_default(), progress),
+                );
+            }
         }
+        let service_ids: Vec<usize> = shows.keys().copied().collect();
+        let mut schedule: Vec<AiringShow> = self
+            .service
+            .get_airing_schedule(&service_ids)
+            .await?
+            .into_iter()
+            .filter_map(|airing| {
+                let (local_id, title, progress) = shows.remove(&airing.service_id)?;
+                Some(AiringShow {
+                    local_id,
+                    title,
+                    progress,
+                    episode: airing.episode,
+                    airs_at: airing.airs_at,
+                })
+            })
+            .collect();
+        schedule.sort_by_key(|show| show.airs_at);
+        Ok(schedule)
+    }
+
+    /// Syncs progress using the current policy, returns changes the user has to decide on
+    pub async fn update_progress(
+        &mut self,
+        remote: &RemoteEntries,
+    ) -> Result<Vec<ProgressChange>, String> {
+        let changes = self.plan_progress_sync(remote).await?;
+        self.apply_progress_changes(&changes).await?;
+        Ok(changes
+            .into_iter()
+            .filter(|change| change.resolution.is_none())
+            .collect())
+    }
+
+    /// Dry run of the sync, lists every show where local and service progress differ
+    pub async fn plan_progress_sync(
+        &self,
+        remote: &RemoteEntries,
+    ) -> Result<Vec<ProgressChange>, String> {
+        let mut changes = Vec::new();
         for show in self.get_list().await? {
-            let service_id = show.service_id;
-            let user_entry_details = self.service.get_user_entry_details(service_id).await?;
-            let user_service_progress_current = user_entry_details
-                .map(|details| details.progress)
-                .unwrap_or_default()
+            let user_entry_details = remote.get(&show.service_id);
+            let on_service_list = user_entry_details.is_some();
+            let (remote_progress, remote_updated_at) = user_entry_details
+                .map(|details| {
+                    (
+                        details.progress.unwrap_or_default(),
+                        details.updated_at.clone(),
+                    )
+                })
                 .unwrap_or_default();
+            if remote_progress == show.progress {
+                continue;
+            }
+            let resolution = if on_service_list {
+                let local_updated_at = self.get_progress_updated_at(show.local_id).await?;
+                self.sync_policy.resolve(
+                    show.progress,
+                    remote_progress,
+                    local_updated_at.as_deref().and_then(sync::parse_timestamp),
+                    remote_updated_at.as_deref().and_then(sync::parse_timestamp),
+                )
+            } else {
+                // nothing to lose on the service side
+                Some(SyncDirection::Push)
+            };
+            changes.push(ProgressChange {
+                local_id: show.local_id,
+                service_id: show.service_id,
+                title: show.title,
+                local_progress: show.progress,
+                remote_progress,
+                remote_updated_at,
+                resolution,
+            });
+        }
+        Ok(changes)
+    }
 
-            let local_progress_current = show.progress;
-            match user_service_progress_current.cmp(&local_progress_current) {
-                Ordering::Greater => self
-                    .set_progress(show.local_id, user_service_progress_current)
-                    .await
-                    .map_err(|e| format!("Can't set progress: {e}")),
-                Ordering::Less => {
+    /// Applies resolved changes, unresolved ones are skipped
+    pub async fn apply_progress_changes(
+        &mut self,
+        changes: &[ProgressChange],
+    ) -> Result<(), String> {
+        for change in changes {
+            match change.resolution {
+                Some(SyncDirection::Pull) => {
+                    let updated_at = match &change.remote_updated_at {
+                        Some(updated_at) => updated_at.clone(),
+                        None => sync::current_timestamp()?,
+                    };
+                    self.set_progress_at(change.local_id, change.remote_progress, &updated_at)
+                        .await
+                        .map_err(|e| format!("Can't set progress: {e}"))?;
+                }
+                Some(SyncDirection::Push) => {
                     let actual_progress = self
                         .service
-                        .set_progress(service_id, local_progress_current)
+                        .set_progress(change.service_id, change.local_progress, None)
                         .await
-                        .unwrap_or(local_progress_current);
+                        .unwrap_or(change.local_progress);
                     // in case of going beyond number of episodes
-                    if actual_progress < local_progress_current {
-                        self.set_progress(show.local_id, actual_progress)
+                    if actual_progress < change.local_progress {
+                        self.set_progress(change.local_id, actual_progress)
                             .await
                             .map_err(|e| format!("Can't set progress: {e}"))?;
                     }
-                    Ok(())
                 }
-                Ordering::Equal => Ok(()),
-            }?;
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Two-way sync of status, score and dates the service supports, returns everything that changed
+    pub async fn sync_entries(
+        &mut self,
+        remote_entries: &RemoteEntries,
+    ) -> Result<Vec<FieldChange>, String> {
+        let mut changes = Vec::new();
+        let capabilities = self.service.capabilities();
+        for show in self.get_list().await? {
+            let Some(remote) = remote_entries.get(&show.service_id) else {
+                continue;
+            };
+            let remote_entry = UserEntry::from(remote);
+            let remote_updated_at = remote.updated_at.as_deref().and_then(sync::parse_timestamp);
+            let (local_entry, timestamps) = self.get_stored_user_entry(show.local_id).await?;
+            let mut pushed_fields = Vec::new();
+            for field in EntryField::ALL
+                .into_iter()
+                .filter(|field| field.is_supported(&capabilities))
+            {
+                let local_value = local_entry.get(field);
+                let remote_value = remote_entry.get(field);
+                let Some(direction) = self.sync_policy.resolve_field(
+                    local_value.as_deref(),
+                    remote_value.as_deref(),
+                    timestamps
+                        .get(&field)
+                        .and_then(|ts| sync::parse_timestamp(ts)),
+                    remote_updated_at,
+                ) else {
+                    continue;
+                };
+                if direction == SyncDirection::Push && local_value.is_none() && !field.can_clear() {
+                    continue;
+                }
+                let (old_value, new_value) = match direction {
+                    SyncDirection::Pull => {
+                        let updated_at = match &remote.updated_at {
+                            Some(updated_at) => updated_at.clone(),
+                            None => sync::current_timestamp()?,
+                        };
+                        self.set_user_entry_field_at(
+                            show.local_id,
+                            field,
+                            remote_value.clone(),
+                            &updated_at,
+                        )
+                        .await?;
+                        (local_value, remote_value)
+                    }
+                    SyncDirection::Push => {
+                        pushed_fields.push((field, local_value.clone()));
+                        (remote_value, local_value)
+                    }
+                };
+                changes.push(FieldChange {
+                    local_id: show.local_id,
+                    title: show.title.clone(),
+                    field,
+                    old_value,
+                    new_value,
+                    direction,
+                });
+            }
+            if !pushed_fields.is_empty() {
+                self.service
+                    .update_user_entry(show.service_id, &sync::service_update(&pushed_fields))
+                    .await?;
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Adds shows from the user's list on the service that aren't in lma yet,
+    /// they have no local files until linked with a folder
+    pub async fn import_user_list(&mut self) -> Result<ImportSummary, String> {
+        if !self.service.is_logged_in() {
+            return Err(String::from("Can't import, user not logged in"));
+        }
+        let mut known_ids: HashSet<usize> = self
+            .get_list()
+            .await?
+            .into_iter()
+            .map(|show| show.service_id)
+            .collect();
+        let mut summary = ImportSummary::default();
+        let mut offset = Some(0);
+        while let Some(current_offset) = offset {
+            let page = self.service.get_user_list(current_offset).await?;
+            offset = page.next_offset;
+            for entry in page.entries {
+                if !known_ids.insert(entry.service_id) {
+                    continue;
+                }
+                if self.has_title(&entry.title).await? {
+                    summary.skipped.push(entry.title);
+                    continue;
+                }
+                let local_id = self.add_show(&entry.title, entry.service_id, 0).await?;
+                let updated_at = match &entry.user.updated_at {
+                    Some(updated_at) => updated_at.clone(),
+                    None => sync::current_timestamp()?,
+                };
+                self.set_progress_at(
+                    local_id,
+                    entry.user.progress.unwrap_or_default(),
+                    &updated_at,
+                )
+                .await?;
+                let user_entry = UserEntry::from(&entry.user);
+                for field in EntryField::ALL {
+                    if let Some(value) = user_entry.get(field) {
+                        self.set_user_entry_field_at(local_id, field, Some(value), &updated_at)
+                            .await?;
+                    }
+                }
+                summary.imported += 1;
+            }
         }
+        Ok(summary)
+    }
+
+    async fn has_title(&self, title: &str) -> Result<bool, String> {
+        let row = sqlx::query!("SELECT id FROM Shows WHERE title = ?1", title)
+            .fetch_optional(&self.db_connection)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(row.is_some())
+    }
+
+    pub async fn get_user_entry(&self, local_id: usize) -> Result<UserEntry, String> {
+        Ok(self.get_stored_user_entry(local_id).await?.0)
+    }
+
+    async fn get_stored_user_entry(
+        &self,
+        local_id: usize,
+    ) -> Result<(UserEntry, HashMap<EntryField, String>), String> {
+        let local_id = u32::try_from(local_id).map_err(|e| e.to_string())?;
+        let Some(row) = sqlx::query!(
+            "SELECT status, status_updated_at, score, score_updated_at, start_date,
+                start_date_updated_at, finish_date, finish_date_updated_at
+            FROM UserEntries
+            WHERE show_id = ?1",
+            local_id
+        )
+        .fetch_optional(&self.db_connection)
+        .await
+        .map_err(|e| e.to_string())?
+        else {
+            return Ok((UserEntry::default(), HashMap::new()));
+        };
+
+        let entry = UserEntry {
+            status: row.status.map(|status| EpisodeStatus::parse(&status)),
+            score: row.score.and_then(|score| u8::try_from(score).ok()),
+            start_date: row.start_date,
+            finish_date: row.finish_date,
+        };
+        let timestamps = [
+            (EntryField::Status, row.status_updated_at),
+            (EntryField::Score, row.score_updated_at),
+            (EntryField::StartDate, row.start_date_updated_at),
+            (EntryField::FinishDate, row.finish_date_updated_at),
+        ]
+        .into_iter()
+        .filter_map(|(field, timestamp)| timestamp.map(|timestamp| (field, timestamp)))
+        .collect();
+        Ok((entry, timestamps))
+    }
+
+    /// Local change made by the user, goes out on the next sync
+    pub async fn set_user_entry_field(
+        &self,
+        local_id: usize,
+        field: EntryField,
+        value: Option<String>,
+    ) -> Result<(), String> {
+        self.set_user_entry_field_at(local_id, field, value, &sync::current_timestamp()?)
+            .await
+    }
+
+    async fn set_user_entry_field_at(
+        &self,
+        local_id: usize,
+        field: EntryField,
+        value: Option<String>,
+        updated_at: &str,
+    ) -> Result<(), String> {
+        let local_id = u32::try_from(local_id).map_err(|e| e.to_string())?;
+        let score = value.as_deref().and_then(|score| score.parse::<u8>().ok());
+        let query = match field {
+            EntryField::Status => sqlx::query!(
+                "INSERT INTO UserEntries (show_id, status, status_updated_at) VALUES (?1, ?2, ?3)
+                ON CONFLICT(show_id) DO UPDATE SET status = ?2, status_updated_at = ?3",
+                local_id,
+                value,
+                updated_at
+            ),
+            EntryField::Score => sqlx::query!(
+                "INSERT INTO UserEntries (show_id, score, score_updated_at) VALUES (?1, ?2, ?3)
+                ON CONFLICT(show_id) DO UPDATE SET score = ?2, score_updated_at = ?3",
+                local_id,
+                score,
+                updated_at
+            ),
+            EntryField::StartDate => sqlx::query!(
+                "INSERT INTO UserEntries (show_id, start_date, start_date_updated_at) VALUES (?1, ?2, ?3)
+                ON CONFLICT(show_id) DO UPDATE SET start_date = ?2, start_date_updated_at = ?3",
+                local_id,
+                value,
+                updated_at
+            ),
+            EntryField::FinishDate => sqlx::query!(
+                "INSERT INTO UserEntries (show_id, finish_date, finish_date_updated_at) VALUES (?1, ?2, ?3)
+                ON CONFLICT(show_id) DO UPDATE SET finish_date = ?2, finish_date_updated_at = ?3",
+                local_id,
+                value,
+                updated_at
+            ),
+        };
+        query
+            .execute(&self.db_connection)
+            .await
+            .map_err(|e| e.to_string())?;
         Ok(())
     }
 
@@ -242,7 +1026,50 @@ impl<T: Service + Send + Sync> AnimeList<T> {
         Ok(files)
     }
 
+    /// Video files as episodes, numbers and titles come from their .nfo files when there are some
+    pub fn get_local_episodes(path: &PathBuf) -> Result<Vec<Episode>, std::io::Error> {
+        Ok(Self::get_video_file_paths(path)?
+            .into_iter()
+            .enumerate()
+            .map(|(k, path)| {
+                let nfo = EpisodeNfo::read(&path).ok().flatten().unwrap_or_default();
+                Episode {
+                    number: nfo.episode.unwrap_or(k + 1),
+                    title: nfo.title.unwrap_or_default(),
+                    file_deleted: !path.exists(),
+                    path,
+                    ..Default::default()
+                }
+            })
+            .collect())
+    }
+
+    /// Id on the current service from the show's nfo, other sites' ids are looked up in
+    /// the offline database if it's in the data directory
+    pub fn nfo_service_id(&self, nfo: &ShowNfo) -> Option<usize> {
+        let service = self.service.get_service_type().id_service();
+        nfo.service_id(&service).or_else(|| {
+            self.offline_database()
+                .iter()
+                .find(|entry| nfo.ids.iter().any(|(site, id)| entry.has_source(site, *id)))?
+                .service_id(&service)
+        })
+    }
+
+    /// Empty when there's no offline database or it can't be read
+    fn offline_database(&self) -> &[MappingEntry] {
+        self.offline_database.get_or_init(|| {
+            mapping::find_offline_database(&self.data_dir)
+                .and_then(|path| mapping::load_entries(&path).ok())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Title from tvshow.nfo if the folder has one, otherwise guessed from the file names
     pub fn guess_shows_title(&self, path: &PathBuf) -> Result<String, String> {
+        if let Some(title) = ShowNfo::read(path)?.and_then(|nfo| nfo.title) {
+            return Ok(title);
+        }
         let mut fname = String::new();
         let guessed_title = Self::get_video_file_paths(path)
             .map_err(|err| err.to_string())?
@@ -301,6 +1128,21 @@ impl<T: Service + Send + Sync> AnimeList<T> {
             .await
             .map_err(|e| e.to_string())?;
 
+        sqlx::query!("DELETE FROM UserEntries WHERE show_id = ?1", show_id)
+            .execute(&self.db_connection)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        sqlx::query!("DELETE FROM ProgressUpdates WHERE show_id = ?1", show_id)
+            .execute(&self.db_connection)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        sqlx::query!("DELETE FROM ServiceIds WHERE show_id = ?1", show_id)
+            .execute(&self.db_connection)
+            .await
+            .map_err(|e| e.to_string())?;
+
         sqlx::query!("DELETE FROM Shows WHERE id = ?1", show_id)
             .execute(&self.db_connection)
             .await
@@ -330,6 +1172,46 @@ pub struct Show {
     pub service_id: usize,
     pub episodes: Vec<Episode>,
     pub progress: usize,
+    /// Local id of the first show of its franchise, None when no other show of it is in the list
+    pub franchise: Option<usize>,
+}
+
+impl Show {
+    /// Shows imported from the service have no episodes until linked with a folder
+    pub fn has_local_files(&self) -> bool {
+        !self.episodes.is_empty()
+    }
+}
+
+/// Outcome of importing the user's list from the service
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct ImportSummary {
+    pub imported: usize,
+    /// Titles a different show in the list already has
+    pub skipped: Vec<String>,
+}
+
+/// Next episode of a show the user is watching
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AiringShow {
+    pub local_id: usize,
+    pub title: String,
+    pub progress: usize,
+    /// Not every service knows which episode it is
+    pub episode: Option<usize>,
+    pub airs_at: OffsetDateTime,
+}
+
+/// Flags stored in the extra_info column
+pub const fn extra_info(recap: bool, filler: bool) -> usize {
+    let mut extra_info: usize = 0;
+    if recap {
+        extra_info |= 1 << 0;
+    }
+    if filler {
+        extra_info |= 1 << 1;
+    }
+    extra_info
 }
 
 #[derive(Default, Clone)]
@@ -347,6 +1229,8 @@ pub async fn create<T: Service + Send + Sync>(
     service: T,
     data_path: &Path,
     title_sort: &TitleSort,
+    sync_policy: &SyncPolicy,
+    group_franchises: bool,
 ) -> Result<AnimeList<T>, String> {
     let path = data_path.join("database.db3");
     let url = format!("sqlite:{}", path.to_string_lossy());
@@ -358,7 +1242,11 @@ pub async fn create<T: Service + Send + Sync>(
         .map_err(|err| format!("Can't create db connection {err}"))?;
     let result = sqlx::query!("
         CREATE TABLE IF NOT EXISTS Shows (id INTEGER PRIMARY KEY AUTOINCREMENT, title TEXT UNIQUE, sync_service_id INTEGER UNIQUE, progress INTEGER);
-        CREATE TABLE IF NOT EXISTS Episodes (show_id INTEGER, episode_number INTEGER, path TEXT, title TEXT, extra_info INTEGER, score REAL, PRIMARY KEY (show_id, episode_number), FOREIGN KEY (show_id) REFERENCES Shows(id))
+        CREATE TABLE IF NOT EXISTS Episodes (show_id INTEGER, episode_number INTEGER, path TEXT, title TEXT, extra_info INTEGER, score REAL, PRIMARY KEY (show_id, episode_number), FOREIGN KEY (show_id) REFERENCES Shows(id));
+        CREATE TABLE IF NOT EXISTS ProgressUpdates (show_id INTEGER PRIMARY KEY, updated_at TEXT, FOREIGN KEY (show_id) REFERENCES Shows(id));
+        CREATE TABLE IF NOT EXISTS UserEntries (show_id INTEGER PRIMARY KEY, status TEXT, status_updated_at TEXT, score INTEGER, score_updated_at TEXT, start_date TEXT, start_date_updated_at TEXT, finish_date TEXT, finish_date_updated_at TEXT, FOREIGN KEY (show_id) REFERENCES Shows(id));
+        CREATE TABLE IF NOT EXISTS ServiceIds (show_id INTEGER, service TEXT, service_id INTEGER, PRIMARY KEY (show_id, service), UNIQUE (service, service_id), FOREIGN KEY (show_id) REFERENCES Shows(id));
+        CREATE TABLE IF NOT EXISTS Relations (service TEXT, service_id INTEGER, related_id INTEGER, relation TEXT, PRIMARY KEY (service, service_id, related_id))
     ")
     .execute(&db_pool)
     .await;
@@ -377,9 +1265,32 @@ pub async fn create<T: Service + Send + Sync>(
             }
         }
     };
+    let service_type = service.get_service_type();
+    if service_type != ServiceType::Local {
+        record_service_ids(&db_pool, &service_type).await?;
+    }
     Ok(AnimeList {
         db_connection: db_pool,
+        data_dir: data_path.to_path_buf(),
         service,
         title_sort: title_sort.clone(),
+        sync_policy: sync_policy.clone(),
+        group_franchises,
+        offline_database: OnceLock::new(),
     })
 }
+
+/// Shows added before ids were kept per service have their id on `service` recorded
+async fn record_service_ids(db_pool: &SqlitePool, service: &ServiceType) -> Result<(), String> {
+    let service = service.as_str();
+    sqlx::query!(
+        "INSERT OR IGNORE INTO ServiceIds (show_id, service, service_id)
+        SELECT id, ?1, sync_service_id FROM Shows
+        WHERE sync_service_id IS NOT NULL AND id NOT IN (SELECT show_id FROM ServiceIds)",
+        service
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Can't record service ids: {e}"))?;
+    Ok(())
+}
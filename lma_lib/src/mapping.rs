@@ -0,0 +1,205 @@
+use crate::ServiceType;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Ids of one show on every service that has it
+pub type ServiceIds = Vec<(ServiceType, usize)>;
+
+/// anime-offline-database style file
+#[derive(Deserialize)]
+struct MappingFile {
+    data: Vec<MappingEntry>,
+}
+
+#[derive(Deserialize)]
+pub struct MappingEntry {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub synonyms: Vec<String>,
+    pub episodes: Option<usize>,
+    #[serde(rename = "type")]
+    pub media_type: Option<String>,
+    #[serde(rename = "animeSeason")]
+    pub anime_season: Option<AnimeSeason>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AnimeSeason {
+    /// Like "SPRING", "UNDEFINED" when not known
+    pub season: Option<String>,
+    pub year: Option<u32>,
+}
+
+impl MappingEntry {
+    /// Id of the show on the given service, if the file has it
+    pub fn service_id(&self, service: &ServiceType) -> Option<usize> {
+        self.sources
+            .iter()
+            .flat_map(|url| parse_source(url))
+            .find(|(source_service, _)| source_service == service)
+            .map(|(_, id)| id)
+    }
+    /// Whether the file lists the show under the given id on the site, like "anidb"
+    pub fn has_source(&self, site: &str, id: usize) -> bool {
+        let hosts: &[&str] = match site {
+            "anidb" => &["anidb.net"],
+            "mal" | "myanimelist" => &["myanimelist.net"],
+            "anilist" => &["anilist.co"],
+            "kitsu" => &["kitsu.io", "kitsu.app"],
+            _ => &[],
+        };
+        self.sources
+            .iter()
+            .filter_map(|url| source_id(url))
+            .any(|(host, source_id)| source_id == id && hosts.contains(&host))
+    }
+}
+
+/// anime-offline-database dumps looked for in the data directory
+const OFFLINE_DATABASES: [&str; 2] = [
+    "anime-offline-database-minified.json",
+    "anime-offline-database.json",
+];
+
+pub fn find_offline_database(data_dir: &Path) -> Option<PathBuf> {
+    OFFLINE_DATABASES
+        .iter()
+        .map(|file_name| data_dir.join(file_name))
+        .find(|path| path.exists())
+}
+
+pub fn load(path: &Path) -> Result<Vec<ServiceIds>, String> {
+    let data = fs::read_to_string(path).map_err(|err| format!("Can't read mapping file: {err}"))?;
+    parse(&data)
+}
+
+pub fn load_entries(path: &Path) -> Result<Vec<MappingEntry>, String> {
+    let data = fs::read_to_string(path).map_err(|err| format!("Can't read mapping file: {err}"))?;
+    parse_entries(&data)
+}
+
+pub fn parse_entries(data: &str) -> Result<Vec<MappingEntry>, String> {
+    let file: MappingFile =
+        serde_json::from_str(data).map_err(|err| format!("Can't parse mapping file: {err}"))?;
+    Ok(file.data)
+}
+
+pub fn parse(data: &str) -> Result<Vec<ServiceIds>, String> {
+    Ok(parse_entries(data)?
+        .into_iter()
+        .map(|entry| {
+            entry
+                .sources
+                .iter()
+                .flat_map(|url| parse_source(url))
+                .collect()
+        })
+        .filter(|ids: &ServiceIds| ids.len() > 1)
+        .collect())
+}
+
+/// Services the source url belongs to, Shikimori uses the same ids as MAL
+fn parse_source(url: &str) -> Vec<(ServiceType, usize)> {
+    let Some((host, id)) = source_id(url) else {
+        return Vec::new();
+    };
+    let services: &[ServiceType] = match host {
+        "myanimelist.net" => &[ServiceType::MAL, ServiceType::Shikimori],
+        "anilist.co" => &[ServiceType::AniList],
+        "kitsu.io" | "kitsu.app" => &[ServiceType::Kitsu],
+        "shikimori.one" | "shikimori.me" => &[ServiceType::Shikimori],
+        _ => &[],
+    };
+    services
+        .iter()
+        .map(|service| (service.clone(), id))
+        .collect()
+}
+
+/// Host and id of the source url
+fn source_id(url: &str) -> Option<(&str, usize)> {
+    let url = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.");
+    let (host, path) = url.split_once('/')?;
+    // anime/21, animes/21-one-piece
+    let id = path.split('/').nth(1).map(|id| {
+        id.chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>()
+    })?;
+    Some((host, id.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sources() {
+        let data = r#"{ "data": [
+            { "title": "One Piece", "sources": [
+                "https://anidb.net/anime/69",
+                "https://anilist.co/anime/21",
+                "https://kitsu.app/anime/12",
+                "https://myanimelist.net/anime/21"
+            ] },
+            { "title": "Only one service", "sources": ["https://anilist.co/anime/5"] }
+        ] }"#;
+        let ids = parse(data).expect("Parsed mapping");
+        assert_eq!(ids.len(), 1, "Entries with one id can't map anything");
+        assert_eq!(
+            ids[0],
+            vec![
+                (ServiceType::AniList, 21),
+                (ServiceType::Kitsu, 12),
+                (ServiceType::MAL, 21),
+                (ServiceType::Shikimori, 21),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_source_urls() {
+        assert_eq!(
+            parse_source("https://shikimori.one/animes/z30230-diamond-no-ace"),
+            Vec::new(),
+            "Ids with a prefix aren't MAL ids"
+        );
+        assert_eq!(
+            parse_source("https://shikimori.one/animes/30230-diamond-no-ace"),
+            vec![(ServiceType::Shikimori, 30230)]
+        );
+        assert_eq!(
+            parse_source("https://kitsu.io/anime/12"),
+            vec![(ServiceType::Kitsu, 12)]
+        );
+        assert_eq!(parse_source("not a url"), Vec::new());
+    }
+
+    #[test]
+    fn entry_sources() {
+        let data = r#"{ "data": [ { "title": "One Piece", "episodes": 1000, "sources": [
+            "https://anidb.net/anime/69",
+            "https://myanimelist.net/anime/21"
+        ] } ] }"#;
+        let entries = parse_entries(data).expect("Parsed entries");
+        assert!(entries[0].has_source("anidb", 69));
+        assert!(!entries[0].has_source("anidb", 21));
+        assert!(!entries[0].has_source("unknown", 69));
+        assert_eq!(entries[0].service_id(&ServiceType::Shikimori), Some(21));
+        assert_eq!(entries[0].service_id(&ServiceType::Kitsu), None);
+    }
+
+    #[test]
+    fn invalid_file() {
+        assert!(parse("[]").is_err());
+    }
+}
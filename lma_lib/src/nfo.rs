@@ -0,0 +1,176 @@
+use crate::ServiceType;
+use roxmltree::Node;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Sites whose ids are read from `uniqueid` and `<site>id` elements
+const ID_TYPES: [&str; 6] = [
+    "anidb",
+    "mal",
+    "myanimelist",
+    "anilist",
+    "kitsu",
+    "shikimori",
+];
+
+/// Details from a Kodi/Jellyfin tvshow.nfo
+#[derive(Default, PartialEq, Eq, Debug)]
+pub struct ShowNfo {
+    pub title: Option<String>,
+    /// Ids by the site they're from, like "anidb" or "mal"
+    pub ids: HashMap<String, usize>,
+}
+
+/// Details from the .nfo next to an episode
+#[derive(Default, PartialEq, Eq, Debug)]
+pub struct EpisodeNfo {
+    pub title: Option<String>,
+    pub episode: Option<usize>,
+}
+
+impl ShowNfo {
+    /// Reads tvshow.nfo in the folder, None when there is none
+    pub fn read(folder: &Path) -> Result<Option<Self>, String> {
+        read_file(&folder.join("tvshow.nfo"))?
+            .map(|data| Self::parse(&data))
+            .transpose()
+    }
+
+    pub fn parse(data: &str) -> Result<Self, String> {
+        with_element(data, "tvshow", |node| {
+            let mut ids = HashMap::new();
+            for child in node.children().filter(Node::is_element) {
+                let tag = child.tag_name().name().to_lowercase();
+                let id_type = if tag == "uniqueid" {
+                    child.attribute("type").map(str::to_lowercase)
+                } else {
+                    tag.strip_suffix("id").map(str::to_owned)
+                };
+                let id = child.text().and_then(|id| id.trim().parse().ok());
+                if let (Some(id_type), Some(id)) = (id_type, id) {
+                    if ID_TYPES.contains(&id_type.as_str()) {
+                        ids.entry(id_type).or_insert(id);
+                    }
+                }
+            }
+            Self {
+                title: child_text(node, "title"),
+                ids,
+            }
+        })
+    }
+
+    /// Id on the service if the file has one, Local uses MAL ids
+    pub fn service_id(&self, service: &ServiceType) -> Option<usize> {
+        let id_types: &[&str] = match service {
+            ServiceType::MAL | ServiceType::Local => &["mal", "myanimelist"],
+            ServiceType::AniList => &["anilist"],
+            ServiceType::Kitsu => &["kitsu"],
+            ServiceType::Shikimori => &["shikimori", "mal", "myanimelist"],
+        };
+        id_types
+            .iter()
+            .find_map(|id_type| self.ids.get(*id_type).copied())
+    }
+}
+
+impl EpisodeNfo {
+    /// Reads the .nfo with the same name as the video file, None when there is none
+    pub fn read(video_path: &Path) -> Result<Option<Self>, String> {
+        read_file(&video_path.with_extension("nfo"))?
+            .map(|data| Self::parse(&data))
+            .transpose()
+    }
+
+    /// Files with more than one episode use the first one
+    pub fn parse(data: &str) -> Result<Self, String> {
+        with_element(data, "episodedetails", |node| Self {
+            title: child_text(node, "title"),
+            episode: child_text(node, "episode").and_then(|number| number.parse().ok()),
+        })
+    }
+}
+
+fn read_file(path: &Path) -> Result<Option<String>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    fs::read_to_string(path)
+        .map(Some)
+        .map_err(|err| format!("Can't read {}: {err}", path.to_string_lossy()))
+}
+
+/// Kodi allows several episodes in one file and a url after the xml, so it all gets a common root
+fn with_element<R>(data: &str, name: &str, read: impl FnOnce(Node) -> R) -> Result<R, String> {
+    let data = data.trim_start_matches('\u{feff}').trim_start();
+    let data = if data.starts_with("<?xml") {
+        data.split_once("?>").map_or("", |(_, rest)| rest)
+    } else {
+        data
+    };
+    let wrapped = format!("<nfo>{data}</nfo>");
+    let document = roxmltree::Document::parse(&wrapped)
+        .map_err(|err| format!("Can't parse nfo file: {err}"))?;
+    let node = document
+        .root_element()
+        .children()
+        .find(|node| node.has_tag_name(name))
+        .ok_or_else(|| format!("No {name} in the nfo file"))?;
+    Ok(read(node))
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_show() {
+        let data = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes" ?>
+            <tvshow>
+                <title>Cowboy Bebop</title>
+                <plot>Space bounty hunters</plot>
+                <uniqueid type="AniDB" default="true">23</uniqueid>
+                <uniqueid type="tvdb">76885</uniqueid>
+                <malid>1</malid>
+            </tvshow>
+            https://anidb.net/anime/23"#;
+        let nfo = ShowNfo::parse(data).expect("Parsed nfo");
+        assert_eq!(nfo.title.as_deref(), Some("Cowboy Bebop"));
+        assert_eq!(nfo.ids.len(), 2, "Only anime sites are kept");
+        assert_eq!(nfo.ids.get("anidb"), Some(&23));
+        assert_eq!(nfo.service_id(&ServiceType::MAL), Some(1));
+        assert_eq!(nfo.service_id(&ServiceType::Shikimori), Some(1));
+        assert_eq!(nfo.service_id(&ServiceType::AniList), None);
+    }
+
+    #[test]
+    fn parse_multi_episode() {
+        let data = "<episodedetails><title>Asteroid Blues</title><season>1</season><episode>1</episode></episodedetails>
+            <episodedetails><title>Stray Dog Strut</title><episode>2</episode></episodedetails>";
+        let nfo = EpisodeNfo::parse(data).expect("Parsed nfo");
+        assert_eq!(
+            nfo,
+            EpisodeNfo {
+                title: Some("Asteroid Blues".to_owned()),
+                episode: Some(1)
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_nfo() {
+        assert!(ShowNfo::parse("<episodedetails></episodedetails>").is_err());
+        assert!(EpisodeNfo::parse("<episodedetails>").is_err());
+        assert_eq!(ShowNfo::read(Path::new("not a folder")), Ok(None));
+    }
+}
@@ -0,0 +1,520 @@
+use crate::{Capabilities, EpisodeStatus, ServiceEpisodeUser};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::atomic::{self, AtomicUsize};
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::{Date, OffsetDateTime};
+
+/// Decides which side wins when local and service progress differ
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum SyncPolicy {
+    MaxWins,
+    RemoteWins,
+    LocalWins,
+    MostRecent,
+    Ask,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SyncDirection {
+    /// Local progress gets sent to the service
+    Push,
+    /// Progress from the service overrides the local one
+    Pull,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ProgressChange {
+    pub local_id: usize,
+    pub service_id: usize,
+    pub title: String,
+    pub local_progress: usize,
+    pub remote_progress: usize,
+    pub remote_updated_at: Option<String>,
+    /// None when the user has to decide
+    pub resolution: Option<SyncDirection>,
+}
+
+impl SyncPolicy {
+    /// Returns None if both sides are the same or the user should be asked
+    pub fn resolve(
+        &self,
+        local_progress: usize,
+        remote_progress: usize,
+        local_updated_at: Option<OffsetDateTime>,
+        remote_updated_at: Option<OffsetDateTime>,
+    ) -> Option<SyncDirection> {
+        if local_progress == remote_progress {
+            return None;
+        }
+        let max_wins = || match remote_progress.cmp(&local_progress) {
+            Ordering::Greater => Some(SyncDirection::Pull),
+            Ordering::Less => Some(SyncDirection::Push),
+            Ordering::Equal => None,
+        };
+        match self {
+            Self::MaxWins => max_wins(),
+            Self::RemoteWins => Some(SyncDirection::Pull),
+            Self::LocalWins => Some(SyncDirection::Push),
+            Self::MostRecent => match (local_updated_at, remote_updated_at) {
+                (Some(local), Some(remote)) => match remote.cmp(&local) {
+                    Ordering::Greater => Some(SyncDirection::Pull),
+                    Ordering::Less => Some(SyncDirection::Push),
+                    Ordering::Equal => max_wins(),
+                },
+                // no local timestamp means it was never changed in lma
+                (None, Some(_)) => Some(SyncDirection::Pull),
+                _ => max_wins(),
+            },
+            Self::Ask => None,
+        }
+    }
+}
+
+/// User list fields kept in sync besides progress
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum EntryField {
+    Status,
+    Score,
+    StartDate,
+    FinishDate,
+}
+
+impl EntryField {
+    pub const ALL: [Self; 4] = [Self::Status, Self::Score, Self::StartDate, Self::FinishDate];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Status => "Status",
+            Self::Score => "Score",
+            Self::StartDate => "Start date",
+            Self::FinishDate => "Finish date",
+        }
+    }
+
+    /// Services always keep a status, the other fields can be emptied
+    pub const fn can_clear(self) -> bool {
+        !matches!(self, Self::Status)
+    }
+
+    /// Unsupported fields are only kept locally
+    pub const fn is_supported(self, capabilities: &Capabilities) -> bool {
+        match self {
+            Self::Status => capabilities.status,
+            Self::Score => capabilities.score,
+            Self::StartDate | Self::FinishDate => capabilities.dates,
+        }
+    }
+
+    /// Checks user input, empty input clears the field
+    pub fn validate(self, input: &str) -> Result<Option<String>, String> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(None);
+        }
+        match self {
+            Self::Status => match EpisodeStatus::parse(input) {
+                EpisodeStatus::None => Err(format!("Unknown status: {input}")),
+                status => Ok(Some(status.as_str().to_owned())),
+            },
+            Self::Score => match input.parse::<u8>() {
+                Ok(score @ 1..=10) => Ok(Some(score.to_string())),
+                _ => Err(format!("Score has to be a number from 1 to 10: {input}")),
+            },
+            Self::StartDate | Self::FinishDate => {
+                Date::parse(input, format_description!("[year]-[month]-[day]"))
+                    .map(|date| Some(date.to_string()))
+                    .map_err(|err| format!("Date has to look like 2023-01-31: {err}"))
+            }
+        }
+    }
+}
+
+/// Local copy of the user's list entry
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct UserEntry {
+    pub status: Option<EpisodeStatus>,
+    pub score: Option<u8>,
+    pub start_date: Option<String>,
+    pub finish_date: Option<String>,
+}
+
+impl UserEntry {
+    pub fn get(&self, field: EntryField) -> Option<String> {
+        match field {
+            EntryField::Status => self.status.map(|status| status.as_str().to_owned()),
+            EntryField::Score => self.score.map(|score| score.to_string()),
+            EntryField::StartDate => self.start_date.clone(),
+            EntryField::FinishDate => self.finish_date.clone(),
+        }
+    }
+
+    pub fn set(&mut self, field: EntryField, value: Option<String>) {
+        match field {
+            EntryField::Status => self.status = value.map(|status| EpisodeStatus::parse(&status)),
+            EntryField::Score => self.score = value.and_then(|score| score.parse().ok()),
+            EntryField::StartDate => self.start_date = value,
+            EntryField::FinishDate => self.finish_date = value,
+        }
+    }
+}
+
+/// Changed fields as an update for the service, cleared ones go out as a score of 0 or an empty date
+pub fn service_update(fields: &[(EntryField, Option<String>)]) -> ServiceEpisodeUser {
+    let mut update = ServiceEpisodeUser::default();
+    for (field, value) in fields {
+        match field {
+            EntryField::Status => {
+                update.status = value.as_deref().map(EpisodeStatus::parse);
+            }
+            EntryField::Score => {
+                update.score = Some(
+                    value
+                        .as_deref()
+                        .and_then(|score| score.parse().ok())
+                        .unwrap_or(0),
+                );
+            }
+            EntryField::StartDate => update.start_date = Some(value.clone().unwrap_or_default()),
+            EntryField::FinishDate => update.finish_date = Some(value.clone().unwrap_or_default()),
+        }
+    }
+    update
+}
+
+impl From<&ServiceEpisodeUser> for UserEntry {
+    fn from(entry: &ServiceEpisodeUser) -> Self {
+        // services return empty values for fields that were never set
+        let not_empty = |value: &Option<String>| value.clone().filter(|str| !str.is_empty());
+        Self {
+            status: entry.status.filter(|status| status != &EpisodeStatus::None),
+            score: entry.score.filter(|score| score > &0),
+            start_date: not_empty(&entry.start_date),
+            finish_date: not_empty(&entry.finish_date),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FieldChange {
+    pub local_id: usize,
+    pub title: String,
+    pub field: EntryField,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub direction: SyncDirection,
+}
+
+impl SyncPolicy {
+    /// Fields other than progress can't be compared so newer value wins unless one side is forced
+    pub fn resolve_field(
+        &self,
+        local_value: Option<&str>,
+        remote_value: Option<&str>,
+        local_updated_at: Option<OffsetDateTime>,
+        remote_updated_at: Option<OffsetDateTime>,
+    ) -> Option<SyncDirection> {
+        if local_value == remote_value {
+            return None;
+        }
+        match (self, local_value, remote_value) {
+            (Self::RemoteWins, _, Some(_)) => Some(SyncDirection::Pull),
+            (Self::LocalWins, Some(_), _) | (_, Some(_), None) => Some(SyncDirection::Push),
+            // never set locally
+            (_, None, Some(_)) if local_updated_at.is_none() => Some(SyncDirection::Pull),
+            // cleared by the user
+            (Self::LocalWins, None, _) => Some(SyncDirection::Push),
+            _ => match (local_updated_at, remote_updated_at) {
+                (Some(local), Some(remote)) if local <= remote => Some(SyncDirection::Pull),
+                (Some(_), _) => Some(SyncDirection::Push),
+                // local value without a timestamp wasn't set by the user
+                (None, _) => Some(SyncDirection::Pull),
+            },
+        }
+    }
+}
+
+pub fn parse_timestamp(timestamp: &str) -> Option<OffsetDateTime> {
+    OffsetDateTime::parse(timestamp, &Rfc3339).ok()
+}
+
+pub fn current_timestamp() -> Result<String, String> {
+    OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .map_err(|err| format!("Can't format current time: {err}"))
+}
+
+/// User's list entries from the service by service id, shows missing from the list are skipped
+pub type RemoteEntries = HashMap<usize, ServiceEpisodeUser>;
+
+/// Shared between a running sync and whatever displays it
+#[derive(Default, Debug)]
+pub struct SyncProgress {
+    total: AtomicUsize,
+    done: AtomicUsize,
+    last_service_id: AtomicUsize,
+}
+
+impl SyncProgress {
+    pub fn start(&self, total: usize) {
+        self.total.store(total, atomic::Ordering::Relaxed);
+        self.done.store(0, atomic::Ordering::Relaxed);
+        self.last_service_id.store(0, atomic::Ordering::Relaxed);
+    }
+
+    pub fn finish_one(&self, service_id: usize) {
+        self.last_service_id
+            .store(service_id, atomic::Ordering::Relaxed);
+        self.done.fetch_add(1, atomic::Ordering::Relaxed);
+    }
+
+    pub fn total(&self) -> usize {
+        self.total.load(atomic::Ordering::Relaxed)
+    }
+
+    pub fn done(&self) -> usize {
+        self.done.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Service id of the most recently finished show
+    pub fn last_service_id(&self) -> Option<usize> {
+        match self.last_service_id.load(atomic::Ordering::Relaxed) {
+            0 => None,
+            service_id => Some(service_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamp(str: &str) -> Option<OffsetDateTime> {
+        parse_timestamp(str)
+    }
+
+    #[test]
+    fn same_progress() {
+        for policy in [
+            SyncPolicy::MaxWins,
+            SyncPolicy::RemoteWins,
+            SyncPolicy::LocalWins,
+            SyncPolicy::MostRecent,
+            SyncPolicy::Ask,
+        ] {
+            assert_eq!(policy.resolve(5, 5, None, None), None);
+        }
+    }
+
+    #[test]
+    fn max_wins() {
+        let policy = SyncPolicy::MaxWins;
+        assert_eq!(policy.resolve(3, 5, None, None), Some(SyncDirection::Pull));
+        assert_eq!(policy.resolve(5, 3, None, None), Some(SyncDirection::Push));
+    }
+
+    #[test]
+    fn fixed_side_wins() {
+        assert_eq!(
+            SyncPolicy::RemoteWins.resolve(5, 3, None, None),
+            Some(SyncDirection::Pull)
+        );
+        assert_eq!(
+            SyncPolicy::LocalWins.resolve(3, 5, None, None),
+            Some(SyncDirection::Push)
+        );
+    }
+
+    #[test]
+    fn most_recent() {
+        let policy = SyncPolicy::MostRecent;
+        let older = timestamp("2017-11-11T19:51:22+00:00");
+        let newer = timestamp("2023-01-01T10:00:00+00:00");
+        assert_eq!(
+            policy.resolve(5, 3, older, newer),
+            Some(SyncDirection::Pull)
+        );
+        assert_eq!(
+            policy.resolve(3, 5, newer, older),
+            Some(SyncDirection::Push)
+        );
+        assert_eq!(policy.resolve(5, 3, None, newer), Some(SyncDirection::Pull));
+        // falls back to max wins without timestamps
+        assert_eq!(policy.resolve(5, 3, None, None), Some(SyncDirection::Push));
+    }
+
+    #[test]
+    fn ask() {
+        assert_eq!(SyncPolicy::Ask.resolve(3, 5, None, None), None);
+    }
+
+    #[test]
+    fn field_newer_wins() {
+        let policy = SyncPolicy::MaxWins;
+        let older = timestamp("2017-11-11T19:51:22+00:00");
+        let newer = timestamp("2023-01-01T10:00:00+00:00");
+        assert_eq!(
+            policy.resolve_field(Some("completed"), Some("watching"), newer, older),
+            Some(SyncDirection::Push)
+        );
+        assert_eq!(
+            policy.resolve_field(Some("completed"), Some("watching"), older, newer),
+            Some(SyncDirection::Pull)
+        );
+        assert_eq!(
+            policy.resolve_field(Some("completed"), Some("watching"), None, older),
+            Some(SyncDirection::Pull)
+        );
+        assert_eq!(policy.resolve_field(Some("8"), Some("8"), None, None), None);
+    }
+
+    #[test]
+    fn field_missing_on_one_side() {
+        let policy = SyncPolicy::RemoteWins;
+        assert_eq!(
+            policy.resolve_field(None, Some("2023-01-01"), None, None),
+            Some(SyncDirection::Pull)
+        );
+        assert_eq!(
+            policy.resolve_field(Some("2023-01-01"), None, None, None),
+            Some(SyncDirection::Push)
+        );
+    }
+
+    #[test]
+    fn field_forced_side() {
+        let newer = timestamp("2023-01-01T10:00:00+00:00");
+        assert_eq!(
+            SyncPolicy::RemoteWins.resolve_field(Some("7"), Some("8"), newer, None),
+            Some(SyncDirection::Pull)
+        );
+        assert_eq!(
+            SyncPolicy::LocalWins.resolve_field(Some("7"), Some("8"), None, newer),
+            Some(SyncDirection::Push)
+        );
+    }
+
+    #[test]
+    fn clear_then_sync() {
+        let older = timestamp("2017-11-11T19:51:22+00:00");
+        let newer = timestamp("2023-01-01T10:00:00+00:00");
+        let policy = SyncPolicy::MaxWins;
+        assert_eq!(
+            policy.resolve_field(None, Some("8"), newer, older),
+            Some(SyncDirection::Push)
+        );
+        assert_eq!(
+            policy.resolve_field(None, Some("8"), older, newer),
+            Some(SyncDirection::Pull),
+            "Changed on the service after the clear"
+        );
+        assert_eq!(
+            SyncPolicy::LocalWins.resolve_field(None, Some("8"), older, newer),
+            Some(SyncDirection::Push)
+        );
+        assert_eq!(
+            SyncPolicy::RemoteWins.resolve_field(None, Some("8"), newer, older),
+            Some(SyncDirection::Pull)
+        );
+    }
+
+    #[test]
+    fn cleared_fields_update() {
+        let update = service_update(&[
+            (EntryField::Score, None),
+            (EntryField::FinishDate, None),
+            (EntryField::StartDate, Some(String::from("2023-01-01"))),
+        ]);
+        assert_eq!(update.score, Some(0));
+        assert_eq!(update.finish_date, Some(String::new()));
+        assert_eq!(update.start_date, Some(String::from("2023-01-01")));
+        assert_eq!(update.status, None);
+        assert!(!EntryField::Status.can_clear());
+    }
+
+    #[test]
+    fn validate_fields() {
+        assert_eq!(
+            EntryField::Status.validate("On hold"),
+            Ok(Some(String::from("on_hold")))
+        );
+        assert!(EntryField::Status.validate("binging").is_err());
+        assert_eq!(
+            EntryField::Score.validate(" 9 "),
+            Ok(Some(String::from("9")))
+        );
+        assert!(EntryField::Score.validate("11").is_err());
+        assert_eq!(
+            EntryField::StartDate.validate("2023-01-31"),
+            Ok(Some(String::from("2023-01-31")))
+        );
+        assert!(EntryField::FinishDate.validate("31.01.2023").is_err());
+        assert_eq!(EntryField::FinishDate.validate(""), Ok(None));
+    }
+
+    #[test]
+    fn supported_fields() {
+        let no_dates = Capabilities {
+            dates: false,
+            ..Capabilities::ALL
+        };
+        assert!(EntryField::Score.is_supported(&no_dates));
+        assert!(!EntryField::FinishDate.is_supported(&no_dates));
+        assert!(!EntryField::Status.is_supported(&Capabilities::default()));
+    }
+
+    #[test]
+    fn user_entry_fields() {
+        let mut entry = UserEntry::default();
+        entry.set(EntryField::Status, Some(String::from("on_hold")));
+        entry.set(EntryField::Score, Some(String::from("7")));
+        entry.set(EntryField::FinishDate, Some(String::from("2023-01-01")));
+        assert_eq!(entry.status, Some(EpisodeStatus::OnHold));
+        assert_eq!(entry.get(EntryField::Score), Some(String::from("7")));
+        assert_eq!(entry.get(EntryField::StartDate), None);
+
+        entry.set(EntryField::Score, Some(String::from("not a number")));
+        assert_eq!(entry.score, None);
+    }
+
+    #[test]
+    fn user_entry_from_service() {
+        let service_entry = ServiceEpisodeUser {
+            status: Some(EpisodeStatus::Watching),
+            score: Some(0),
+            start_date: Some(String::from("2023-01-01")),
+            finish_date: Some(String::new()),
+            ..Default::default()
+        };
+        let entry = UserEntry::from(&service_entry);
+        assert_eq!(
+            entry,
+            UserEntry {
+                status: Some(EpisodeStatus::Watching),
+                score: None,
+                start_date: Some(String::from("2023-01-01")),
+                finish_date: None,
+            }
+        );
+    }
+
+    #[test]
+    fn progress_counting() {
+        let progress = SyncProgress::default();
+        progress.start(2);
+        assert_eq!(progress.last_service_id(), None);
+        progress.finish_one(21);
+        assert_eq!((progress.done(), progress.total()), (1, 2));
+        assert_eq!(progress.last_service_id(), Some(21));
+        progress.start(3);
+        assert_eq!((progress.done(), progress.total()), (0, 3));
+    }
+
+    #[test]
+    fn parse_service_timestamp() {
+        assert!(parse_timestamp("2017-11-11T19:51:22+00:00").is_some());
+        assert!(parse_timestamp("not a date").is_none());
+    }
+}
@@ -2,19 +2,35 @@ use crate::app;
 use crate::config::Config;
 use crate::handle_input;
 use crate::ui::main_menu::StatefulList;
+use crate::ui::popup::account::AccountPopup;
+use crate::ui::popup::edit_entry::EntryPopup;
 use crate::ui::popup::episode_mismatch::MismatchPopup;
 use crate::ui::popup::first_setup::SetupPopup;
+use crate::ui::popup::import_summary::ImportPopup;
 use crate::ui::popup::insert_episode::InsertEpisodePopup;
 use crate::ui::popup::insert_show::InsertPopup;
+use crate::ui::popup::login::{self, LoginPopup};
+use crate::ui::popup::progress_sync::SyncPopup;
+use crate::ui::popup::schedule::SchedulePopup;
+use crate::ui::popup::sequel::SequelPopup;
+use crate::ui::popup::sync_progress;
+use crate::ui::popup::sync_summary::SummaryPopup;
 use crate::ui::popup::title_selection::TitlesPopup;
 use crate::ui::{ui, FocusedWindow};
-use crossterm::event::{self, Event, KeyEventKind};
-use lma_lib::{AnimeList, Service};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use lma_lib::{
+    AnimeList, AnyService, CredentialStore, RemoteEntries, Season, Service, SyncProgress,
+};
 use ratatui::{backend::Backend, Terminal};
 use std::error::Error;
+use std::future::Future;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
 
+/// Gives up on the browser coming back after this long
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(300);
+
 pub struct App<T: Service> {
     pub focused_window: FocusedWindow,
     pub insert_popup: InsertPopup,
@@ -22,16 +38,35 @@ pub struct App<T: Service> {
     pub titles_popup: TitlesPopup,
     pub mismatch_popup: MismatchPopup,
     pub first_setup_popup: SetupPopup,
+    pub sync_popup: SyncPopup,
+    pub summary_popup: SummaryPopup,
+    pub entry_popup: EntryPopup,
+    pub login_popup: LoginPopup,
+    pub account_popup: AccountPopup,
+    pub schedule_popup: SchedulePopup,
+    pub sequel_popup: SequelPopup,
+    pub import_popup: ImportPopup,
     pub list_state: StatefulList,
     pub anime_list: AnimeList<T>,
     pub config: Config,
+    pub token_store: Arc<dyn CredentialStore>,
     error: String,
 }
 
 impl<T: Service> App<T> {
-    pub async fn build(config: Config) -> Result<Self, String> {
-        let service = T::new(config.data_dir.clone()).await?;
-        let anime_list = lma_lib::create(service, &config.data_dir, &config.title_sort).await?;
+    pub async fn build(
+        config: Config,
+        service: T,
+        token_store: Arc<dyn CredentialStore>,
+    ) -> Result<Self, String> {
+        let anime_list = lma_lib::create(
+            service,
+            &config.data_dir,
+            &config.title_sort,
+            &config.sync_policy,
+            config.group_franchises,
+        )
+        .await?;
         Ok(Self {
             list_state: StatefulList::new(&anime_list).await?,
             focused_window: FocusedWindow::MainMenu,
@@ -40,8 +75,17 @@ impl<T: Service> App<T> {
             titles_popup: TitlesPopup::default(),
             mismatch_popup: MismatchPopup::default(),
             first_setup_popup: SetupPopup::new(),
+            sync_popup: SyncPopup::default(),
+            summary_popup: SummaryPopup::default(),
+            entry_popup: EntryPopup::default(),
+            login_popup: LoginPopup::default(),
+            account_popup: AccountPopup::default(),
+            schedule_popup: SchedulePopup::default(),
+            sequel_popup: SequelPopup::default(),
+            import_popup: ImportPopup::default(),
             anime_list,
             config,
+            token_store,
             error: String::new(),
         })
     }
@@ -51,22 +95,189 @@ impl<T: Service> App<T> {
         rt: &Runtime,
         terminal: &mut Terminal<B>,
     ) -> Result<(), String> {
-        rt.block_on(self.anime_list.service.auth());
+        let service = &mut self.anime_list.service;
+        service.set_redirect_port(self.config.redirect_port);
+        rt.block_on(service.auth());
         self.focused_window = FocusedWindow::Login;
-        terminal
-            .draw(|f| ui(f, self, rt))
-            .map_err(|err| err.to_string())?;
-        if !self.anime_list.service.is_logged_in() {
-            rt.block_on(self.anime_list.service.login())?; // freezes the app as it waits
+        self.login_popup = LoginPopup::default();
+        if service.is_logged_in() || service.needs_credentials() {
+            // logging in with credentials happens after they are confirmed
+            return Ok(());
+        }
+        let url = service.get_url().unwrap_or_default();
+        if url.starts_with("https://") || url.starts_with("http://") {
+            _ = open::that(&url);
+        }
+        let outcome = wait_for_login(
+            rt,
+            terminal,
+            &mut self.login_popup,
+            &url,
+            &self.config,
+            service.login(),
+        );
+        let result = match outcome {
+            Ok(LoginOutcome::LoggedIn) => Ok(()),
+            Ok(LoginOutcome::Pasted(redirect_url)) => {
+                rt.block_on(service.login_with_redirect(&redirect_url))
+            }
+            Ok(LoginOutcome::Cancelled) => {
+                self.focused_window = FocusedWindow::MainMenu;
+                Ok(())
+            }
+            Err(err) => Err(err),
+        };
+        if result.is_err() {
             self.focused_window = FocusedWindow::MainMenu;
-            terminal
-                .draw(|f| ui(f, self, rt))
-                .map_err(|err| err.to_string())?;
-            self.focused_window = FocusedWindow::Login;
+        }
+        result
+    }
+
+    /// Asks the service who is logged in and shows what can be done with the account
+    pub fn open_account(&mut self, rt: &Runtime) {
+        let service = &mut self.anime_list.service;
+        let logged_in = service.is_logged_in();
+        let username = if logged_in {
+            rt.block_on(service.get_username())
+        } else {
+            Ok(None)
+        };
+        self.account_popup = AccountPopup::new(logged_in, username);
+        self.focused_window = FocusedWindow::Account;
+    }
+
+    /// Lists this season's shows and when episodes of the shows being watched air
+    pub fn open_schedule(&mut self, rt: &Runtime) -> Result<(), String> {
+        let season = Season::current();
+        let page = rt.block_on(self.anime_list.service.get_season(season, 0))?;
+        let airing = if self.anime_list.service.capabilities().schedule {
+            Some(rt.block_on(self.anime_list.airing_schedule())?)
+        } else {
+            None
+        };
+        self.schedule_popup = SchedulePopup::new(season, page, airing);
+        self.focused_window = FocusedWindow::Schedule;
+        Ok(())
+    }
+
+    /// Asks about adding sequels of a finished show that aren't in the list yet
+    pub fn offer_sequels(&mut self, rt: &Runtime, local_id: usize) -> Result<(), String> {
+        let sequels = rt.block_on(self.anime_list.find_sequels(local_id))?;
+        // relations were just refreshed, the show may have a franchise now
+        rt.block_on(self.list_state.update_cache(&self.anime_list))?;
+        let Some(show) = self.list_state.show_by_local_id(local_id) else {
+            return Ok(());
+        };
+        if !sequels.is_empty() {
+            self.sequel_popup = SequelPopup::new(show.title.clone(), sequels);
+            self.focused_window = FocusedWindow::Sequel;
+        }
+        Ok(())
+    }
+
+    /// Adds the selected sequel without local files, like shows imported from the service
+    pub fn add_sequel(&mut self, rt: &Runtime) -> Result<(), String> {
+        self.focused_window = FocusedWindow::MainMenu;
+        let Some(sequel) = self.sequel_popup.selected_sequel().cloned() else {
+            return Ok(());
+        };
+        rt.block_on(
+            self.anime_list
+                .add_show(&sequel.title, sequel.service_id, 0),
+        )?;
+        rt.block_on(self.anime_list.service.init_show(sequel.service_id))?;
+        rt.block_on(self.list_state.update_cache(&self.anime_list))
+    }
+
+    /// Fetches the user's entries while drawing progress, None when cancelled with the close key
+    pub fn fetch_remote_entries<B: Backend>(
+        &mut self,
+        rt: &Runtime,
+        terminal: &mut Terminal<B>,
+    ) -> Result<Option<RemoteEntries>, String> {
+        let progress = SyncProgress::default();
+        let fetch = self.anime_list.fetch_remote_entries(&progress);
+        with_progress(
+            rt,
+            terminal,
+            "Synchronizing with the service",
+            &progress,
+            &self.list_state,
+            &self.config,
+            fetch,
+        )
+    }
+
+    /// Gets episode details from the service again for one show or the whole list,
+    /// shows already refreshed stay that way when cancelled
+    pub fn refresh_metadata<B: Backend>(
+        &mut self,
+        rt: &Runtime,
+        terminal: &mut Terminal<B>,
+        local_id: Option<usize>,
+    ) -> Result<(), String> {
+        let progress = SyncProgress::default();
+        let refresh =
+            self.anime_list
+                .refresh_metadata(local_id, self.config.precise_score, &progress);
+        let result = with_progress(
+            rt,
+            terminal,
+            "Refreshing metadata",
+            &progress,
+            &self.list_state,
+            &self.config,
+            refresh,
+        );
+        rt.block_on(self.list_state.update_cache(&self.anime_list))?;
+        result.map(|_| ())
+    }
+
+    /// Syncs progress and list entries, opens popups for conflicts and a summary of changes
+    pub fn sync_with_service<B: Backend>(
+        &mut self,
+        rt: &Runtime,
+        terminal: &mut Terminal<B>,
+    ) -> Result<(), String> {
+        let Some(remote) = self.fetch_remote_entries(rt, terminal)? else {
+            return Ok(());
+        };
+        let unresolved = rt.block_on(self.anime_list.update_progress(&remote))?;
+        let entry_changes = rt.block_on(self.anime_list.sync_entries(&remote))?;
+        rt.block_on(self.list_state.update_cache(&self.anime_list))?;
+        self.summary_popup = SummaryPopup::new(entry_changes);
+        if !unresolved.is_empty() {
+            self.sync_popup = SyncPopup::new(unresolved);
+            self.focused_window = FocusedWindow::ProgressSync;
+        } else if !self.summary_popup.is_empty() {
+            self.focused_window = FocusedWindow::SyncSummary;
         }
         Ok(())
     }
 
+    /// Summary is shown only when something has changed
+    pub fn show_sync_summary(&mut self) {
+        self.focused_window = if self.summary_popup.is_empty() {
+            FocusedWindow::MainMenu
+        } else {
+            FocusedWindow::SyncSummary
+        };
+    }
+
+    /// Shows changes that didn't reach mirrored services
+    pub fn report_failures(&mut self) {
+        let failures: Vec<_> = self
+            .anime_list
+            .service
+            .take_failures()
+            .into_iter()
+            .map(|failure| format!("{:?}: {}", failure.service, failure.error))
+            .collect();
+        if !failures.is_empty() {
+            self.set_error(format!("Not mirrored, {}", failures.join(", ")));
+        }
+    }
+
     pub fn set_error(&mut self, error: String) {
         if self.error.is_empty() {
             self.error = error;
@@ -82,22 +293,137 @@ impl<T: Service> App<T> {
     }
 }
 
-pub fn run<B: Backend, T: Service>(
+impl App<AnyService> {
+    /// Swaps the service for the one in the config, the list itself stays the same
+    pub async fn reload_service(&mut self) -> Result<(), String> {
+        let mirrors: Vec<_> = self.config.mirror_service().into_iter().cloned().collect();
+        self.anime_list.service = lma_lib::new_service(
+            self.config.service(),
+            &mirrors,
+            self.config.data_dir.clone(),
+            &self.config.cache_ttl,
+            &self.config.mal,
+            &self.token_store,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+enum LoginOutcome {
+    LoggedIn,
+    /// Address the browser ended up on
+    Pasted(String),
+    Cancelled,
+}
+
+/// Draws the login popup until the redirect comes back, an address gets pasted or it times out
+fn wait_for_login<B: Backend>(
+    rt: &Runtime,
     terminal: &mut Terminal<B>,
-    mut app: app::App<T>,
+    popup: &mut LoginPopup,
+    url: &str,
+    config: &Config,
+    login: impl Future<Output = Result<(), String>>,
+) -> Result<LoginOutcome, String> {
+    rt.block_on(async {
+        tokio::pin!(login);
+        let deadline = tokio::time::Instant::now() + LOGIN_TIMEOUT;
+        let mut redraw = tokio::time::interval(Duration::from_millis(100));
+        loop {
+            tokio::select! {
+                result = &mut login => return result.map(|()| LoginOutcome::LoggedIn),
+                () = tokio::time::sleep_until(deadline) => {
+                    return Err(String::from(
+                        "Login timed out, try again or paste the address the browser ended up on",
+                    ));
+                }
+                _ = redraw.tick() => {
+                    let time_left = deadline.saturating_duration_since(tokio::time::Instant::now());
+                    terminal
+                        .draw(|f| login::build_waiting(f, popup, url, time_left, config))
+                        .map_err(|err| err.to_string())?;
+                    while event::poll(Duration::ZERO).map_err(|err| err.to_string())? {
+                        let Event::Key(key) = event::read().map_err(|err| err.to_string())? else {
+                            continue;
+                        };
+                        if key.kind != KeyEventKind::Press {
+                            continue;
+                        }
+                        if key.code == config.key_binds.close {
+                            return Ok(LoginOutcome::Cancelled);
+                        } else if key.code == config.key_binds.confirmation
+                            && !popup.redirect_url().is_empty()
+                        {
+                            return Ok(LoginOutcome::Pasted(popup.redirect_url().to_owned()));
+                        }
+                        match key.code {
+                            KeyCode::Char(c) => popup.push_redirect(c),
+                            KeyCode::Backspace => popup.pop_redirect(),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Draws progress until the future is done, None when cancelled with the close key
+fn with_progress<B: Backend, R>(
+    rt: &Runtime,
+    terminal: &mut Terminal<B>,
+    title: &str,
+    progress: &SyncProgress,
+    list_state: &StatefulList,
+    config: &Config,
+    future: impl Future<Output = Result<R, String>>,
+) -> Result<Option<R>, String> {
+    rt.block_on(async {
+        tokio::pin!(future);
+        let mut redraw = tokio::time::interval(Duration::from_millis(100));
+        loop {
+            tokio::select! {
+                result = &mut future => return result.map(Some),
+                _ = redraw.tick() => {
+                    let last_title = progress
+                        .last_service_id()
+                        .and_then(|service_id| list_state.title_by_service_id(service_id));
+                    terminal
+                        .draw(|f| sync_progress::build(f, title, progress, last_title, config))
+                        .map_err(|err| err.to_string())?;
+                    while event::poll(Duration::ZERO).map_err(|err| err.to_string())? {
+                        if let Event::Key(key) = event::read().map_err(|err| err.to_string())? {
+                            if key.kind == KeyEventKind::Press
+                                && key.code == config.key_binds.close
+                            {
+                                return Ok(None);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+pub fn run<B: Backend>(
+    terminal: &mut Terminal<B>,
+    mut app: app::App<AnyService>,
     tick_rate: Duration,
     rt: &Runtime,
 ) -> Result<(), Box<dyn Error>> {
     if !app.config.config_file_path.exists() {
         app.focused_window = FocusedWindow::FirstSetup;
-    } else if app.config.data_dir.join("tokens").exists() {
+    } else if app.anime_list.service.is_logged_in() {
         if app.config.update_progress_on_start {
-            println!("Updating your progress - please wait");
-            rt.block_on(app.anime_list.update_progress())?;
+            app.sync_with_service(rt, terminal)?;
         }
     } else {
         app.focused_window = FocusedWindow::Login;
-        app.handle_login(rt, terminal)?;
+        if let Err(err) = app.handle_login(rt, terminal) {
+            app.set_error(err);
+        }
     }
     let mut last_tick = Instant::now();
     terminal.clear()?;
@@ -124,23 +450,41 @@ pub fn run<B: Backend, T: Service>(
                             }
                         }
                         FocusedWindow::FirstSetup => {
-                            if handle_input::first_setup(key, &mut app)?.is_none() {
-                                return Ok(());
-                            }
+                            handle_input::first_setup(key, &mut app, rt, terminal)?;
                         }
-                        FocusedWindow::Login => handle_input::login(key, &mut app, rt),
+                        FocusedWindow::Login => handle_input::login(key, &mut app, rt, terminal),
                         FocusedWindow::InsertPopup => handle_input::insert_popup(&mut app, key),
                         FocusedWindow::InsertEpisodePopup => {
                             handle_input::insert_episode_popup(&mut app, key);
                         }
                         FocusedWindow::TitleSelection => {
-                            handle_input::title_selection(key, &mut app);
+                            handle_input::title_selection(key, &mut app, rt);
                         }
                         FocusedWindow::EpisodeMismatch => {
                             handle_input::mismatch_popup(key, &mut app);
                         }
+                        FocusedWindow::ProgressSync => {
+                            handle_input::progress_sync(key, &mut app, rt);
+                        }
+                        FocusedWindow::SyncSummary => handle_input::sync_summary(key, &mut app),
+                        FocusedWindow::ImportSummary => {
+                            handle_input::import_summary(key, &mut app);
+                        }
+                        FocusedWindow::EditEntry => handle_input::edit_entry(key, &mut app, rt),
+                        FocusedWindow::Schedule => handle_input::schedule(key, &mut app, rt),
+                        FocusedWindow::Sequel => {
+                            if let Err(err) = handle_input::sequel(key, &mut app, rt) {
+                                app.set_error(err);
+                            }
+                        }
+                        FocusedWindow::Account => {
+                            if let Err(err) = handle_input::account(key, &mut app, rt, terminal) {
+                                app.set_error(err);
+                            }
+                        }
                         FocusedWindow::Error => handle_input::error(key, &mut app),
                     }
+                    app.report_failures();
                 }
             }
         }
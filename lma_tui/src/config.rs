@@ -1,7 +1,9 @@
 use crate::ui::main_menu::HeaderType;
 use crossterm::event::KeyCode;
 use directories::ProjectDirs;
-use lma_lib::{ServiceType, TitleSort};
+use lma_lib::{
+    CacheTtl, CredentialStorage, MalApp, ServiceType, SyncPolicy, TitleSort, DEFAULT_REDIRECT_PORT,
+};
 use ratatui::style::Color as TermColor;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -13,10 +15,17 @@ use std::{
 #[allow(clippy::struct_field_names)]
 pub struct Config {
     service: ServiceType,
+    mirror_service: Option<ServiceType>,
     pub config_file_path: PathBuf,
     pub data_dir: PathBuf,
     pub colors: TermColors,
     pub title_sort: TitleSort,
+    pub group_franchises: bool,
+    pub sync_policy: SyncPolicy,
+    pub cache_ttl: CacheTtl,
+    pub redirect_port: u16,
+    pub mal: MalApp,
+    pub credentials: CredentialStorage,
     pub key_binds: KeyBinds,
     pub headers: Headers,
     pub path_instead_of_title: bool,
@@ -83,9 +92,22 @@ impl Default for HeadersFile {
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 struct ConfigFile {
     service: Option<ServiceType>,
+    /// Service that gets a copy of every change, none by default
+    mirror_service: Option<ServiceType>,
     data_dir: Option<PathBuf>,
     colors: Option<Colors>,
     title_sort: Option<TitleSort>,
+    /// Prequels, sequels and side stories follow each other in the list
+    group_franchises: Option<bool>,
+    sync_policy: Option<SyncPolicy>,
+    /// Hours before show metadata is requested again
+    cache_ttl: Option<CacheTtl>,
+    /// Port the browser gets sent back to after logging in
+    redirect_port: Option<u16>,
+    /// Own MAL API app instead of the built-in one
+    mal: Option<MalApp>,
+    /// Where login tokens are kept, a plain file by default
+    credentials: Option<CredentialStorage>,
     key_binds: Option<KeyBinds>,
     headers: Option<HeadersFile>,
     path_instead_of_title: Option<bool>,
@@ -102,7 +124,14 @@ impl Default for ConfigFile {
             data_dir: Some(PathBuf::new()),
             colors: Some(Colors::default()),
             service: Some(ServiceType::MAL),
+            mirror_service: None,
             title_sort: Some(TitleSort::LocalIdAsc),
+            group_franchises: Some(true),
+            sync_policy: Some(SyncPolicy::MaxWins),
+            cache_ttl: Some(CacheTtl::default()),
+            redirect_port: Some(DEFAULT_REDIRECT_PORT),
+            mal: Some(MalApp::default()),
+            credentials: Some(CredentialStorage::default()),
             key_binds: Some(KeyBinds::default()),
             headers: Some(HeadersFile::default()),
             precise_score: Some(true),
@@ -116,6 +145,7 @@ impl Default for ConfigFile {
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(default)]
 pub struct KeyBinds {
     pub move_up: KeyCode,
     pub move_down: KeyCode,
@@ -131,6 +161,14 @@ pub struct KeyBinds {
     pub progress_inc: KeyCode,
     pub progress_dec: KeyCode,
     pub login: KeyCode,
+    pub account: KeyCode,
+    pub schedule: KeyCode,
+    pub sync_preview: KeyCode,
+    pub edit_entry: KeyCode,
+    pub import_list: KeyCode,
+    pub toggle_nsfw: KeyCode,
+    pub refresh_metadata: KeyCode,
+    pub refresh_library: KeyCode,
 }
 
 impl Default for KeyBinds {
@@ -150,6 +188,14 @@ impl Default for KeyBinds {
             progress_inc: KeyCode::Char('.'),
             progress_dec: KeyCode::Char(','),
             login: KeyCode::Char('l'),
+            account: KeyCode::Char('a'),
+            schedule: KeyCode::Char('c'),
+            sync_preview: KeyCode::Char('s'),
+            edit_entry: KeyCode::Char('i'),
+            import_list: KeyCode::Char('m'),
+            toggle_nsfw: KeyCode::Char('h'),
+            refresh_metadata: KeyCode::Char('r'),
+            refresh_library: KeyCode::Char('u'),
         }
     }
 }
@@ -267,16 +313,18 @@ impl Config {
         parse_config(config_file_path, default_config)
     }
 
-    pub fn create_personalized(&self, service: ServiceType) -> Result<(), String> {
+    pub fn create_personalized(&mut self, service: ServiceType) -> Result<(), String> {
         let default_config = ConfigFile {
             data_dir: Some(self.data_dir.clone()),
-            service: Some(service),
+            service: Some(service.clone()),
             ..Default::default()
         };
         let default_config_str = toml::to_string(&default_config)
             .map_err(|err| format!("Can't serialized the config: {err}"))?;
         fs::write(&self.config_file_path, default_config_str)
-            .map_err(|err| format!("Can't save default config: {err}"))
+            .map_err(|err| format!("Can't save default config: {err}"))?;
+        self.service = service;
+        Ok(())
     }
 
     pub fn default() -> Result<Self, String> {
@@ -302,6 +350,24 @@ impl Config {
     pub const fn service(&self) -> &ServiceType {
         &self.service
     }
+
+    /// Saves the service to the config file keeping the rest of it
+    pub fn set_service(&mut self, service: ServiceType) -> Result<(), String> {
+        let data = fs::read_to_string(&self.config_file_path)
+            .map_err(|err| format!("Config can't be read: {err}"))?;
+        let mut config_file = parse_config_file(&data)?;
+        config_file.service = Some(service.clone());
+        let config_str = toml::to_string(&config_file)
+            .map_err(|err| format!("Can't serialized the config: {err}"))?;
+        fs::write(&self.config_file_path, config_str)
+            .map_err(|err| format!("Can't save the config: {err}"))?;
+        self.service = service;
+        Ok(())
+    }
+
+    pub const fn mirror_service(&self) -> Option<&ServiceType> {
+        self.mirror_service.as_ref()
+    }
 }
 
 fn parse_config(config_file_path: PathBuf, default_config: ConfigFile) -> Result<Config, String> {
@@ -327,7 +393,14 @@ fn parse_config(config_file_path: PathBuf, default_config: ConfigFile) -> Result
         colors: get_setting_or_default!(colors).try_into()?,
         data_dir: get_setting_or_default!(data_dir),
         service: get_setting_or_default!(service),
+        mirror_service: config_file.mirror_service,
         title_sort: get_setting_or_default!(title_sort),
+        group_franchises: get_setting_or_default!(group_franchises),
+        sync_policy: get_setting_or_default!(sync_policy),
+        cache_ttl: get_setting_or_default!(cache_ttl),
+        redirect_port: get_setting_or_default!(redirect_port),
+        mal: get_setting_or_default!(mal),
+        credentials: get_setting_or_default!(credentials),
         key_binds: get_setting_or_default!(key_binds),
         headers: get_setting_or_default!(headers).try_into()?,
         precise_score: get_setting_or_default!(precise_score),
@@ -354,6 +427,7 @@ fn create_dirs(config_dir: &Path, data_dir: &Path) -> Result<(), String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use lma_lib::CredentialBackend;
 
     #[test]
     fn default_config_creation() {
@@ -373,12 +447,21 @@ mod tests {
             service = \"MAL\"
             data_dir = \"\"
             title_sort = \"LocalIdAsc\"
+            group_franchises = false
+            sync_policy = \"MostRecent\"
             path_instead_of_title = false
             update_progress_on_start = true
             relative_episode_score = true
             autofill_title = true
             english_show_titles = true
             precise_score = true
+            redirect_port = 8080
+            [cache_ttl]
+            episode_count = 12
+            [mal]
+            client_id = \"own_client\"
+            [credentials]
+            backend = \"EncryptedFile\"
             [headers]
             shows = \"title\"
             episodes = \"title\"
@@ -414,10 +497,27 @@ mod tests {
             Char = \",\"
             [key_binds.login]
             Char = \"l\"
+            [key_binds.account]
+            Char = \"a\"
+            [key_binds.schedule]
+            Char = \"c\"
+            [key_binds.sync_preview]
+            Char = \"s\"
+            [key_binds.edit_entry]
+            Char = \"i\"
+            [key_binds.import_list]
+            Char = \"m\"
+            [key_binds.toggle_nsfw]
+            Char = \"h\"
+            [key_binds.refresh_metadata]
+            Char = \"r\"
+            [key_binds.refresh_library]
+            Char = \"u\"
         ";
         let parsed_config_file = parse_config_file(config_string).expect("Parsed config");
         let expected_config_file = ConfigFile {
             service: Some(ServiceType::MAL),
+            mirror_service: None,
             data_dir: Some(PathBuf::new()),
             colors: Some(Colors {
                 text: Some(Color {
@@ -437,6 +537,21 @@ mod tests {
                 }),
             }),
             title_sort: Some(TitleSort::LocalIdAsc),
+            group_franchises: Some(false),
+            sync_policy: Some(SyncPolicy::MostRecent),
+            cache_ttl: Some(CacheTtl {
+                episode_count: 12,
+                ..Default::default()
+            }),
+            redirect_port: Some(8080),
+            mal: Some(MalApp {
+                client_id: Some(String::from("own_client")),
+                redirect_uri: None,
+            }),
+            credentials: Some(CredentialStorage {
+                backend: CredentialBackend::EncryptedFile,
+                key_file: None,
+            }),
             key_binds: Some(KeyBinds {
                 move_up: KeyCode::Up,
                 move_down: KeyCode::Down,
@@ -452,6 +567,14 @@ mod tests {
                 progress_inc: KeyCode::Char('.'),
                 progress_dec: KeyCode::Char(','),
                 login: KeyCode::Char('l'),
+                account: KeyCode::Char('a'),
+                schedule: KeyCode::Char('c'),
+                sync_preview: KeyCode::Char('s'),
+                edit_entry: KeyCode::Char('i'),
+                import_list: KeyCode::Char('m'),
+                toggle_nsfw: KeyCode::Char('h'),
+                refresh_metadata: KeyCode::Char('r'),
+                refresh_library: KeyCode::Char('u'),
             }),
             path_instead_of_title: Some(false),
             update_progress_on_start: Some(true),
@@ -467,6 +590,30 @@ mod tests {
         assert_eq!(parsed_config_file, expected_config_file);
     }
 
+    #[test]
+    fn missing_key_binds_default() {
+        let config_string = "
+            [key_binds]
+            move_down = \"Up\"
+        ";
+        let parsed_config_file = parse_config_file(config_string).expect("Parsed config");
+        let key_binds = parsed_config_file.key_binds.expect("Key binds section");
+        assert_eq!(key_binds.move_down, KeyCode::Up);
+        assert_eq!(key_binds.sync_preview, KeyBinds::default().sync_preview);
+    }
+
+    #[test]
+    fn mirror_service() {
+        let parsed_config_file =
+            parse_config_file("mirror_service = \"AniList\"").expect("Parsed config");
+        assert_eq!(
+            parsed_config_file.mirror_service,
+            Some(ServiceType::AniList)
+        );
+        let parsed_config_file = parse_config_file("").expect("Parsed config");
+        assert_eq!(parsed_config_file.mirror_service, None);
+    }
+
     #[test]
     fn failing_parse_config_1() {
         let config_string = "
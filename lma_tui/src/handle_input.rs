@@ -1,9 +1,14 @@
 use crate::app::App;
+use crate::ui::popup::account::{AccountAction, AccountPopup};
+use crate::ui::popup::edit_entry::EntryPopup;
+use crate::ui::popup::import_summary::ImportPopup;
 use crate::ui::popup::insert_episode::InsertEpisodePopup;
 use crate::ui::popup::insert_show::{InsertPopup, InsertState};
+use crate::ui::popup::progress_sync::SyncPopup;
+use crate::ui::popup::sync_summary::SummaryPopup;
 use crate::ui::{FocusedWindow, SelectionDirection};
 use crossterm::event::{self, KeyCode};
-use lma_lib::Service;
+use lma_lib::{service_update, AnyService, Service, SyncDirection};
 use ratatui::{backend::Backend, Terminal};
 use tokio::runtime::Runtime;
 
@@ -27,10 +32,26 @@ pub fn main_menu<B: Backend, T: Service>(
                 .move_selection(&SelectionDirection::Previous, &app.anime_list),
         )?;
     } else if key.code == key_binds.progress_inc {
+        let before = app.list_state.selected_show().map(|show| show.progress);
         rt.block_on(
             app.list_state
                 .move_progress(&SelectionDirection::Next, &mut app.anime_list),
         )?;
+        // offered once, when the last episode gets watched
+        if let Some(show) = app.list_state.selected_show() {
+            if before != Some(show.progress) {
+                let (local_id, service_id, progress) =
+                    (show.local_id, show.service_id, show.progress);
+                // local files can be only a part of the show, so it has to be the service's count
+                let episode_count = rt
+                    .block_on(app.anime_list.service.get_episode_count(service_id))
+                    .ok()
+                    .flatten();
+                if episode_count == Some(progress) {
+                    app.offer_sequels(rt, local_id)?;
+                }
+            }
+        }
     } else if key.code == key_binds.progress_dec {
         rt.block_on(
             app.list_state
@@ -50,31 +71,138 @@ pub fn main_menu<B: Backend, T: Service>(
         app.insert_episode_popup.state = InsertState::Inputting;
     } else if key.code == key_binds.login {
         app.handle_login(rt, terminal)?;
-        rt.block_on(app.anime_list.update_progress())?;
+        if app.anime_list.service.is_logged_in() {
+            app.sync_with_service(rt, terminal)?;
+        }
+    } else if key.code == key_binds.account {
+        app.open_account(rt);
+    } else if key.code == key_binds.schedule {
+        app.open_schedule(rt)?;
+    } else if key.code == key_binds.sync_preview {
+        if let Some(remote) = app.fetch_remote_entries(rt, terminal)? {
+            let changes = rt.block_on(app.anime_list.plan_progress_sync(&remote))?;
+            app.sync_popup = SyncPopup::new(changes);
+            app.summary_popup = SummaryPopup::default();
+            app.focused_window = FocusedWindow::ProgressSync;
+        }
+    } else if key.code == key_binds.import_list {
+        let summary = rt.block_on(app.anime_list.import_user_list())?;
+        rt.block_on(app.list_state.update_cache(&app.anime_list))?;
+        app.import_popup = ImportPopup::new(summary);
+        app.focused_window = FocusedWindow::ImportSummary;
+    } else if key.code == key_binds.refresh_metadata {
+        if let Some(show) = app.list_state.selected_show() {
+            let local_id = show.local_id;
+            app.refresh_metadata(rt, terminal, Some(local_id))?;
+        }
+    } else if key.code == key_binds.refresh_library {
+        app.refresh_metadata(rt, terminal, None)?;
+    } else if key.code == key_binds.edit_entry {
+        if let Some(show) = app.list_state.selected_show() {
+            let entry = rt.block_on(app.anime_list.get_user_entry(show.local_id))?;
+            app.entry_popup =
+                EntryPopup::new(show.local_id, show.service_id, show.title.clone(), entry);
+            app.focused_window = FocusedWindow::EditEntry;
+        }
     }
     Ok(Some(true))
 }
 
-pub fn login<T: Service>(key: event::KeyEvent, app: &mut App<T>, rt: &Runtime) {
-    if key.code == app.config.key_binds.close {
+pub fn account<B: Backend, T: Service>(
+    key: event::KeyEvent,
+    app: &mut App<T>,
+    rt: &Runtime,
+    terminal: &mut Terminal<B>,
+) -> Result<(), String> {
+    let key_binds = &app.config.key_binds;
+    if key.code == key_binds.close {
+        app.focused_window = FocusedWindow::MainMenu;
+    } else if key.code == key_binds.move_down {
+        app.account_popup.move_selection(&SelectionDirection::Next);
+    } else if key.code == key_binds.move_up {
+        app.account_popup
+            .move_selection(&SelectionDirection::Previous);
+    } else if key.code == key_binds.confirmation {
+        let action = app.account_popup.selected_action();
+        if action != AccountAction::LogIn {
+            rt.block_on(app.anime_list.service.logout())?;
+            app.account_popup = AccountPopup::default();
+        }
+        if action == AccountAction::LogOut {
+            return Ok(());
+        }
+        app.handle_login(rt, terminal)?;
+        if app.anime_list.service.is_logged_in() {
+            app.sync_with_service(rt, terminal)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn login<B: Backend, T: Service>(
+    key: event::KeyEvent,
+    app: &mut App<T>,
+    rt: &Runtime,
+    terminal: &mut Terminal<B>,
+) {
+    let key_binds = &app.config.key_binds;
+    if key.code == key_binds.close {
         app.focused_window = FocusedWindow::MainMenu;
         if let Err(err) = rt.block_on(app.list_state.update_cache(&app.anime_list)) {
             app.set_error(err);
         };
+    } else if app.anime_list.service.needs_credentials() && !app.anime_list.service.is_logged_in() {
+        credentials(key, app, rt, terminal);
     }
 }
 
-pub fn first_setup<T: Service>(
+fn credentials<B: Backend, T: Service>(
     key: event::KeyEvent,
     app: &mut App<T>,
-) -> Result<Option<bool>, String> {
+    rt: &Runtime,
+    terminal: &mut Terminal<B>,
+) {
+    let key_binds = &app.config.key_binds;
+    if key.code == key_binds.confirmation {
+        app.anime_list
+            .service
+            .set_credentials(app.login_popup.username(), app.login_popup.password());
+        app.focused_window = FocusedWindow::MainMenu;
+        if let Err(err) = rt
+            .block_on(app.anime_list.service.login())
+            .and_then(|()| app.sync_with_service(rt, terminal))
+        {
+            app.set_error(err);
+        }
+    } else if key.code == key_binds.move_down || key.code == key_binds.move_up {
+        app.login_popup.switch_line();
+    } else {
+        match key.code {
+            KeyCode::Char(c) => app.login_popup.push(c),
+            KeyCode::Backspace => app.login_popup.pop(),
+            _ => {}
+        }
+    }
+}
+
+pub fn first_setup<B: Backend>(
+    key: event::KeyEvent,
+    app: &mut App<AnyService>,
+    rt: &Runtime,
+    terminal: &mut Terminal<B>,
+) -> Result<(), String> {
     let key_binds = &app.config.key_binds;
     if key.code == key_binds.confirmation {
         if app.first_setup_popup.next_page() {
             app.first_setup_popup.reset();
             let selected_service = app.first_setup_popup.selected_service();
             app.config.create_personalized(selected_service.clone())?;
-            return Ok(None);
+            rt.block_on(app.reload_service())?;
+            if app.anime_list.service.is_logged_in() {
+                app.focused_window = FocusedWindow::MainMenu;
+            } else {
+                app.handle_login(rt, terminal)?;
+            }
         }
     } else if key.code == key_binds.close {
         app.first_setup_popup.previous_page();
@@ -85,7 +213,7 @@ pub fn first_setup<T: Service>(
         app.first_setup_popup
             .move_selection(&SelectionDirection::Previous);
     }
-    Ok(Some(true))
+    Ok(())
 }
 
 pub fn error<T: Service>(key: event::KeyEvent, app: &mut App<T>) {
@@ -170,19 +298,33 @@ pub fn insert_episode_popup<T: Service>(app: &mut App<T>, key: event::KeyEvent)
     }
 }
 
-pub fn title_selection<T: Service>(key: event::KeyEvent, app: &mut App<T>) {
+pub fn title_selection<T: Service>(key: event::KeyEvent, app: &mut App<T>, rt: &Runtime) {
     let key_binds = &app.config.key_binds;
     if key.code == key_binds.move_down {
+        // past the last title the next page gets loaded
+        if let Some((query, offset)) = app.titles_popup.next_page() {
+            match rt.block_on(app.anime_list.service.search_title(&query, offset)) {
+                Ok(page) => app.titles_popup.add_page(page),
+                Err(err) => app.set_error(err),
+            }
+        }
         app.titles_popup.move_selection(&SelectionDirection::Next);
+    } else if key.code == key_binds.forwards {
+        app.titles_popup.cycle_media_type(&SelectionDirection::Next);
+    } else if key.code == key_binds.backwards {
+        app.titles_popup
+            .cycle_media_type(&SelectionDirection::Previous);
+    } else if key.code == key_binds.toggle_nsfw {
+        app.titles_popup.toggle_nsfw();
     } else if key.code == key_binds.move_up {
         app.titles_popup
             .move_selection(&SelectionDirection::Previous);
     } else if key.code == key_binds.confirmation {
-        app.insert_popup.service_id = app
-            .titles_popup
-            .selected_show()
-            .map(|show| show.service_id)
-            .unwrap_or_default();
+        if let Some(show) = app.titles_popup.selected_show() {
+            app.insert_popup.set_details(show.clone());
+        } else {
+            app.insert_popup.service_id = 0;
+        }
         app.insert_popup.state = InsertState::Next;
         app.focused_window = FocusedWindow::InsertPopup;
     } else if key.code == key_binds.close {
@@ -190,6 +332,55 @@ pub fn title_selection<T: Service>(key: event::KeyEvent, app: &mut App<T>) {
     }
 }
 
+pub fn schedule<T: Service>(key: event::KeyEvent, app: &mut App<T>, rt: &Runtime) {
+    let key_binds = &app.config.key_binds;
+    if key.code == key_binds.move_down {
+        // past the last show the next page gets loaded
+        if let Some((season, offset)) = app.schedule_popup.next_page() {
+            match rt.block_on(app.anime_list.service.get_season(season, offset)) {
+                Ok(page) => app.schedule_popup.add_page(page),
+                Err(err) => app.set_error(err),
+            }
+        }
+        app.schedule_popup.move_selection(&SelectionDirection::Next);
+    } else if key.code == key_binds.move_up {
+        app.schedule_popup
+            .move_selection(&SelectionDirection::Previous);
+    } else if key.code == key_binds.forwards || key.code == key_binds.backwards {
+        let current = app.schedule_popup.season();
+        let season = if key.code == key_binds.forwards {
+            current.next()
+        } else {
+            current.previous()
+        };
+        match rt.block_on(app.anime_list.service.get_season(season, 0)) {
+            Ok(page) => app.schedule_popup.set_season(season, page),
+            Err(err) => app.set_error(err),
+        }
+    } else if key.code == key_binds.close {
+        app.focused_window = FocusedWindow::MainMenu;
+    }
+}
+
+pub fn sequel<T: Service>(
+    key: event::KeyEvent,
+    app: &mut App<T>,
+    rt: &Runtime,
+) -> Result<(), String> {
+    let key_binds = &app.config.key_binds;
+    if key.code == key_binds.move_down {
+        app.sequel_popup.move_selection(&SelectionDirection::Next);
+    } else if key.code == key_binds.move_up {
+        app.sequel_popup
+            .move_selection(&SelectionDirection::Previous);
+    } else if key.code == key_binds.confirmation {
+        app.add_sequel(rt)?;
+    } else if key.code == key_binds.close {
+        app.focused_window = FocusedWindow::MainMenu;
+    }
+    Ok(())
+}
+
 pub fn mismatch_popup<T: Service>(key: event::KeyEvent, app: &mut App<T>) {
     let key_binds = &app.config.key_binds;
     if key.code == key_binds.close {
@@ -207,3 +398,110 @@ pub fn mismatch_popup<T: Service>(key: event::KeyEvent, app: &mut App<T>) {
         _ => {}
     }
 }
+
+pub fn progress_sync<T: Service>(key: event::KeyEvent, app: &mut App<T>, rt: &Runtime) {
+    let key_binds = &app.config.key_binds;
+    if key.code == key_binds.move_down {
+        app.sync_popup.move_selection(&SelectionDirection::Next);
+    } else if key.code == key_binds.move_up {
+        app.sync_popup.move_selection(&SelectionDirection::Previous);
+    } else if key.code == key_binds.forwards {
+        app.sync_popup.resolve_selected(SyncDirection::Push);
+    } else if key.code == key_binds.backwards {
+        app.sync_popup.resolve_selected(SyncDirection::Pull);
+    } else if key.code == key_binds.confirmation {
+        let sync_result = rt.block_on(async {
+            app.anime_list
+                .apply_progress_changes(app.sync_popup.changes())
+                .await?;
+            app.list_state.update_cache(&app.anime_list).await
+        });
+        app.sync_popup = SyncPopup::default();
+        app.show_sync_summary();
+        if let Err(err) = sync_result {
+            app.set_error(err);
+        }
+    } else if key.code == key_binds.close {
+        app.sync_popup = SyncPopup::default();
+        app.show_sync_summary();
+    }
+}
+
+pub fn sync_summary<T: Service>(key: event::KeyEvent, app: &mut App<T>) {
+    let key_binds = &app.config.key_binds;
+    if key.code == key_binds.move_down {
+        app.summary_popup.move_selection(&SelectionDirection::Next);
+    } else if key.code == key_binds.move_up {
+        app.summary_popup
+            .move_selection(&SelectionDirection::Previous);
+    } else if key.code == key_binds.close || key.code == key_binds.confirmation {
+        app.summary_popup = SummaryPopup::default();
+        app.focused_window = FocusedWindow::MainMenu;
+    }
+}
+
+pub fn import_summary<T: Service>(key: event::KeyEvent, app: &mut App<T>) {
+    let key_binds = &app.config.key_binds;
+    if key.code == key_binds.move_down {
+        app.import_popup.move_selection(&SelectionDirection::Next);
+    } else if key.code == key_binds.move_up {
+        app.import_popup
+            .move_selection(&SelectionDirection::Previous);
+    } else if key.code == key_binds.close || key.code == key_binds.confirmation {
+        app.import_popup = ImportPopup::default();
+        app.focused_window = FocusedWindow::MainMenu;
+    }
+}
+
+pub fn edit_entry<T: Service>(key: event::KeyEvent, app: &mut App<T>, rt: &Runtime) {
+    let key_binds = &app.config.key_binds;
+    if key.code == key_binds.close {
+        app.focused_window = FocusedWindow::MainMenu;
+    } else if key.code == key_binds.confirmation {
+        if let Err(err) = rt.block_on(save_entry(app)) {
+            app.set_error(err);
+        }
+        app.focused_window = FocusedWindow::MainMenu;
+    } else if key.code == key_binds.move_down {
+        app.entry_popup
+            .move_line_selection(&SelectionDirection::Next);
+    } else if key.code == key_binds.move_up {
+        app.entry_popup
+            .move_line_selection(&SelectionDirection::Previous);
+    } else {
+        match key.code {
+            KeyCode::Char(c) => app.entry_popup.push(c),
+            KeyCode::Backspace => app.entry_popup.pop(),
+            _ => {}
+        }
+    }
+}
+
+async fn save_entry<T: Service>(app: &mut App<T>) -> Result<(), String> {
+    let changed_fields = app.entry_popup.changed_fields()?;
+    if changed_fields.is_empty() {
+        return Ok(());
+    }
+    let capabilities = app.anime_list.service.capabilities();
+    if let Some((field, _)) = changed_fields.iter().find(|(field, value)| {
+        value.is_none() && field.is_supported(&capabilities) && !field.can_clear()
+    }) {
+        return Err(format!("{} can't be cleared on the service", field.name()));
+    }
+    let mut update = Vec::new();
+    for (field, value) in changed_fields {
+        app.anime_list
+            .set_user_entry_field(app.entry_popup.local_id, field, value.clone())
+            .await?;
+        if field.is_supported(&capabilities) {
+            update.push((field, value));
+        }
+    }
+    if app.anime_list.service.is_logged_in() && !update.is_empty() {
+        app.anime_list
+            .service
+            .update_user_entry(app.entry_popup.service_id, &service_update(&update))
+            .await?;
+    }
+    Ok(())
+}
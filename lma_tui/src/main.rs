@@ -1,37 +1,58 @@
 mod app;
 mod config;
 mod handle_input;
+mod migrate;
 mod ui;
 use config::Config;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
 use crossterm::execute;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
 };
-use lma_lib::{Local, MALClient, ServiceType, MAL};
-use ratatui::{backend::CrosstermBackend, Terminal};
+use lma_lib::CredentialStore;
+use ratatui::backend::CrosstermBackend;
+use ratatui::Terminal;
+use std::env;
 use std::error::Error;
-use std::io::{self, Stdout};
+use std::io::{self, Stdout, Write};
+use std::sync::Arc;
 use std::time::Duration;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let mut terminal = setup_terminal()?;
+/// Skips the passphrase prompt of the encrypted credentials file
+const PASSPHRASE_VAR: &str = "LMA_CREDENTIALS_PASSPHRASE";
 
+fn main() -> Result<(), Box<dyn Error>> {
     let tick_rate = Duration::from_millis(250);
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()?;
 
-    let config = Config::default()?;
-    let run_result = match config.service() {
-        ServiceType::MAL => {
-            let app = rt.block_on(app::App::<MAL<MALClient>>::build(config))?;
-            app::run(&mut terminal, app, tick_rate, &rt)
+    let mut config = Config::default()?;
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("migrate") => {
+            println!("{}", migrate::run(&mut config, &args[1..], &rt)?);
+            return Ok(());
         }
-        ServiceType::Local => {
-            let app = rt.block_on(app::App::<Local>::build(config))?;
-            app::run(&mut terminal, app, tick_rate, &rt)
-        }
-    };
+        Some(_) => Err(migrate::USAGE)?,
+        None => {}
+    }
+
+    // migrating doesn't log in, so it doesn't need the passphrase
+    let token_store = open_token_store(&config)?;
+    let mirrors: Vec<_> = config.mirror_service().into_iter().cloned().collect();
+    let service = rt.block_on(lma_lib::new_service(
+        config.service(),
+        &mirrors,
+        config.data_dir.clone(),
+        &config.cache_ttl,
+        &config.mal,
+        &token_store,
+    ))?;
+    let app = rt.block_on(app::App::build(config, service, token_store))?;
+
+    let mut terminal = setup_terminal()?;
+    let run_result = app::run(&mut terminal, app, tick_rate, &rt);
 
     restore_terminal(&mut terminal)?;
 
@@ -42,6 +63,51 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+fn open_token_store(config: &Config) -> Result<Arc<dyn CredentialStore>, Box<dyn Error>> {
+    let passphrase = if config.credentials.needs_passphrase() {
+        match env::var(PASSPHRASE_VAR) {
+            Ok(passphrase) => Some(passphrase),
+            Err(_) => Some(read_passphrase()?),
+        }
+    } else {
+        None
+    };
+    Ok(config
+        .credentials
+        .open(&config.data_dir, passphrase.as_deref())?)
+}
+
+/// Asks for the passphrase without showing it, before the UI takes over the terminal
+fn read_passphrase() -> Result<String, Box<dyn Error>> {
+    print!("Passphrase of the credentials file: ");
+    io::stdout().flush()?;
+    enable_raw_mode()?;
+    let mut passphrase = String::new();
+    let result = loop {
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => break Ok(passphrase),
+            KeyCode::Esc => break Err("No passphrase given"),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                break Err("No passphrase given")
+            }
+            KeyCode::Char(char) => passphrase.push(char),
+            KeyCode::Backspace => {
+                passphrase.pop();
+            }
+            _ => {}
+        }
+    };
+    disable_raw_mode()?;
+    println!();
+    Ok(result?)
+}
+
 fn setup_terminal() -> Result<Terminal<CrosstermBackend<Stdout>>, Box<dyn Error>> {
     let mut stdout = io::stdout();
     enable_raw_mode()?;
@@ -0,0 +1,46 @@
+use crate::config::Config;
+use lma_lib::{Local, ServiceType};
+use std::path::Path;
+use tokio::runtime::Runtime;
+
+pub const USAGE: &str = "Usage: lma migrate <MAL|AniList|Kitsu|Shikimori|Local> [mapping file]";
+
+/// Switches the library to another service, ids come from an optional
+/// anime-offline-database style mapping file and the ones already known
+pub fn run(config: &mut Config, args: &[String], rt: &Runtime) -> Result<String, String> {
+    let to = args
+        .first()
+        .and_then(|service| ServiceType::parse(service))
+        .ok_or(USAGE)?;
+    let from = config.service().clone();
+    // the database doesn't need a working service
+    let anime_list = rt.block_on(lma_lib::create(
+        Local::default(),
+        &config.data_dir,
+        &config.title_sort,
+        &config.sync_policy,
+        config.group_franchises,
+    ))?;
+    let mut report = Vec::new();
+    if let Some(mapping_path) = args.get(1) {
+        let added = rt.block_on(anime_list.import_id_mappings(Path::new(mapping_path)))?;
+        report.push(format!("Imported {added} ids"));
+    }
+    let migrated = rt.block_on(anime_list.migrate_service(&from, &to))?;
+    if from.id_service() == to.id_service() {
+        report.push(format!("Shows keep their {} ids", to.id_service().as_str()));
+    } else {
+        report.push(format!(
+            "Moved {migrated} shows from {} to {}",
+            from.as_str(),
+            to.as_str()
+        ));
+    }
+    if from != to {
+        config.set_service(to)?;
+        report.push(String::from(
+            "Log in to the new service on the next start, the old one stays logged in until you log out of it",
+        ));
+    }
+    Ok(report.join("\n"))
+}
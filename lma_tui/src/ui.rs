@@ -20,6 +20,13 @@ pub enum FocusedWindow {
     EpisodeMismatch,
     Error,
     FirstSetup,
+    ProgressSync,
+    SyncSummary,
+    EditEntry,
+    Account,
+    Schedule,
+    Sequel,
+    ImportSummary,
 }
 
 #[derive(PartialEq, Eq)]
@@ -52,6 +59,34 @@ pub fn ui<T: Service>(frame: &mut Frame, app: &mut app::App<T>, rt: &Runtime) {
                 popup::first_setup::build(frame, app);
                 Ok(())
             }
+            FocusedWindow::ProgressSync => {
+                popup::progress_sync::build(frame, app);
+                Ok(())
+            }
+            FocusedWindow::SyncSummary => {
+                popup::sync_summary::build(frame, app);
+                Ok(())
+            }
+            FocusedWindow::EditEntry => {
+                popup::edit_entry::build(frame, app);
+                Ok(())
+            }
+            FocusedWindow::Account => {
+                popup::account::build(frame, app);
+                Ok(())
+            }
+            FocusedWindow::Schedule => {
+                popup::schedule::build(frame, app);
+                Ok(())
+            }
+            FocusedWindow::Sequel => {
+                popup::sequel::build(frame, app);
+                Ok(())
+            }
+            FocusedWindow::ImportSummary => {
+                popup::import_summary::build(frame, app);
+                Ok(())
+            }
             // main menu is always drawn and error is drawn independently
             FocusedWindow::MainMenu | FocusedWindow::Error => Ok(()),
         }
@@ -1,5 +1,5 @@
 mod episodes;
-mod help;
+pub mod help;
 mod shows;
 use super::SelectionDirection;
 use crate::app::App;
@@ -89,6 +89,19 @@ impl StatefulList {
         self.selected_local_id = self.list_cache.get(index).map_or(0, |show| show.local_id);
     }
 
+    pub fn title_by_service_id(&self, service_id: usize) -> Option<&str> {
+        self.list_cache
+            .iter()
+            .find(|show| show.service_id == service_id)
+            .map(|show| show.title.as_str())
+    }
+
+    pub fn show_by_local_id(&self, local_id: usize) -> Option<&Show> {
+        self.list_cache
+            .iter()
+            .find(|show| show.local_id == local_id)
+    }
+
     pub fn selected_show(&self) -> Option<&Show> {
         self.shows_state
             .selected()
@@ -113,7 +126,7 @@ impl StatefulList {
             .unwrap_or_default();
         let actual_progress = shows
             .service
-            .set_progress(selected_show.service_id, progress)
+            .set_progress(selected_show.service_id, progress, None)
             .await?;
         shows
             .set_progress(selected_show.local_id, actual_progress)
@@ -172,6 +185,14 @@ impl StatefulList {
 
     pub async fn update_cache<T: Service>(&mut self, shows: &AnimeList<T>) -> Result<(), String> {
         self.list_cache = shows.get_list().await?;
+        // grouping franchises can move the selected show
+        let index = self
+            .list_cache
+            .iter()
+            .position(|show| show.local_id == self.selected_local_id);
+        if index.is_some() && self.shows_state.selected().is_some() {
+            self.shows_state.select(index);
+        }
         Ok(())
     }
 
@@ -462,6 +483,7 @@ mod tests {
                 service_id: 100 + i,
                 episodes: generate_test_episodes(count),
                 progress: i % 4,
+                franchise: None,
             };
             shows.push(show);
         }
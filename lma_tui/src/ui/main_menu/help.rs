@@ -11,8 +11,10 @@ use ratatui::widgets::Paragraph;
 use ratatui::{text::Line, Frame};
 
 pub fn render<T: Service>(app: &App<T>, area: Rect, frame: &mut Frame) {
+    let service = &app.anime_list.service;
     let help = build_help(
         &app.focused_window,
+        service.needs_credentials() && !service.is_logged_in(),
         &app.insert_popup.state,
         &app.insert_episode_popup.state,
         app.config.colors.highlight_dark,
@@ -23,6 +25,7 @@ pub fn render<T: Service>(app: &App<T>, area: Rect, frame: &mut Frame) {
 
 fn build_help<'a>(
     focused_window: &FocusedWindow,
+    credentials_form: bool,
     insert_show_state: &InsertState,
     insert_episode_state: &InsertState,
     bg_color: Color,
@@ -37,9 +40,27 @@ fn build_help<'a>(
     let start_input = HelpItem::new("Start inputting", &Action::EnterInput, key_binds, bg_color);
     let confirm = HelpItem::new("Confirm", &Action::Confirmation, key_binds, bg_color);
     let login = HelpItem::new("Login", &Action::Login, key_binds, bg_color);
+    let account = HelpItem::new("Account", &Action::Account, key_binds, bg_color);
+    let schedule = HelpItem::new("Schedule", &Action::Schedule, key_binds, bg_color);
     let progress = HelpItem::new("Progress", &Action::Progress, key_binds, bg_color);
     let insert_episode = HelpItem::new("Add episode", &Action::NewEpisode, key_binds, bg_color);
     let quit = HelpItem::new("Quit", &Action::Quit, key_binds, bg_color);
+    let sync = HelpItem::new("Sync preview", &Action::SyncPreview, key_binds, bg_color);
+    let edit_entry = HelpItem::new("Edit entry", &Action::EditEntry, key_binds, bg_color);
+    let import = HelpItem::new("Import list", &Action::ImportList, key_binds, bg_color);
+    let refresh = HelpItem::new(
+        "Refresh show",
+        &Action::RefreshMetadata,
+        key_binds,
+        bg_color,
+    );
+    let refresh_all = HelpItem::new("Refresh all", &Action::RefreshLibrary, key_binds, bg_color);
+    let keep_local = HelpItem::new("Keep local", &Action::Forwards, key_binds, bg_color);
+    let use_service = HelpItem::new("Use service", &Action::Backwards, key_binds, bg_color);
+    let filter_type = HelpItem::new("Filter type", &Action::Forwards, key_binds, bg_color);
+    let next_season = HelpItem::new("Next season", &Action::Forwards, key_binds, bg_color);
+    let previous_season = HelpItem::new("Previous season", &Action::Backwards, key_binds, bg_color);
+    let toggle_nsfw = HelpItem::new("Hide NSFW", &Action::ToggleNsfw, key_binds, bg_color);
 
     let mut information = Vec::new();
     match focused_window {
@@ -48,8 +69,15 @@ fn build_help<'a>(
             information.extend(insert);
             information.extend(delete);
             information.extend(login);
+            information.extend(account);
             information.extend(insert_episode);
             information.extend(progress);
+            information.extend(sync);
+            information.extend(schedule);
+            information.extend(edit_entry);
+            information.extend(import);
+            information.extend(refresh);
+            information.extend(refresh_all);
             information.extend(quit);
         }
         FocusedWindow::InsertPopup => {
@@ -79,6 +107,10 @@ fn build_help<'a>(
             }
         }
         FocusedWindow::Login => {
+            if credentials_form {
+                information.extend(navigation);
+                information.extend(confirm);
+            }
             information.extend(close_window);
         }
         FocusedWindow::FirstSetup => {
@@ -88,6 +120,30 @@ fn build_help<'a>(
         }
         FocusedWindow::TitleSelection => {
             information.extend(navigation);
+            information.extend(filter_type);
+            information.extend(toggle_nsfw);
+            information.extend(close_window);
+        }
+        FocusedWindow::ProgressSync => {
+            information.extend(navigation);
+            information.extend(keep_local);
+            information.extend(use_service);
+            information.extend(confirm);
+            information.extend(close_window);
+        }
+        FocusedWindow::Schedule => {
+            information.extend(navigation);
+            information.extend(previous_season);
+            information.extend(next_season);
+            information.extend(close_window);
+        }
+        FocusedWindow::SyncSummary | FocusedWindow::ImportSummary => {
+            information.extend(navigation);
+            information.extend(close_window);
+        }
+        FocusedWindow::EditEntry | FocusedWindow::Account | FocusedWindow::Sequel => {
+            information.extend(navigation);
+            information.extend(confirm);
             information.extend(close_window);
         }
         FocusedWindow::EpisodeMismatch | FocusedWindow::Error => {
@@ -110,6 +166,16 @@ enum Action {
     NewShow,
     NewEpisode,
     Login,
+    Account,
+    Schedule,
+    SyncPreview,
+    EditEntry,
+    ImportList,
+    ToggleNsfw,
+    RefreshMetadata,
+    RefreshLibrary,
+    Forwards,
+    Backwards,
 }
 
 struct HelpItem<'a> {
@@ -185,10 +251,20 @@ fn key_to_abbr(key: &KeyBinds, name: &Action) -> String {
         Action::NewShow => keycode_to_key(key.new_show),
         Action::NewEpisode => keycode_to_key(key.new_episode),
         Action::Login => keycode_to_key(key.login),
+        Action::Account => keycode_to_key(key.account),
+        Action::Schedule => keycode_to_key(key.schedule),
+        Action::SyncPreview => keycode_to_key(key.sync_preview),
+        Action::EditEntry => keycode_to_key(key.edit_entry),
+        Action::ImportList => keycode_to_key(key.import_list),
+        Action::ToggleNsfw => keycode_to_key(key.toggle_nsfw),
+        Action::RefreshMetadata => keycode_to_key(key.refresh_metadata),
+        Action::RefreshLibrary => keycode_to_key(key.refresh_library),
+        Action::Forwards => keycode_to_key(key.forwards),
+        Action::Backwards => keycode_to_key(key.backwards),
     }
 }
 
-fn keycode_to_key(keycode: KeyCode) -> String {
+pub fn keycode_to_key(keycode: KeyCode) -> String {
     match keycode {
         KeyCode::Backspace => "Backspace".to_string(),
         KeyCode::Enter => "Enter".to_string(),
@@ -20,6 +20,13 @@ pub fn render<T: Service>(app: &mut App<T>, area: Rect, frame: &mut Frame) {
         .iter()
         .map(|show| {
             let mut title = show.title.clone();
+            if show.franchise.is_some_and(|first| first != show.local_id) {
+                // under the first show of its franchise
+                title.insert_str(0, "└ ");
+            }
+            if !show.has_local_files() {
+                title.push_str(" (no local files)");
+            }
             if selected_show_id == Some(show.local_id)
                 && app.list_state.selection == Selection::Show
             {
@@ -46,7 +53,9 @@ pub fn render<T: Service>(app: &mut App<T>, area: Rect, frame: &mut Frame) {
 
 fn get_style(show: &Show, colors: &TermColors) -> Style {
     let mut style = Style::default().fg(colors.text);
-    if show.progress >= show.episodes.len() {
+    if !show.has_local_files() {
+        style = style.add_modifier(Modifier::ITALIC);
+    } else if show.progress >= show.episodes.len() {
         style = style.add_modifier(Modifier::DIM);
     }
     style
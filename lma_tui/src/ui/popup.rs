@@ -1,10 +1,18 @@
+pub mod account;
+pub mod edit_entry;
 pub mod episode_mismatch;
 pub mod error;
 pub mod first_setup;
+pub mod import_summary;
 #[allow(clippy::module_name_repetitions)]
 pub mod insert_episode;
 pub mod insert_show;
 pub mod login;
+pub mod progress_sync;
+pub mod schedule;
+pub mod sequel;
+pub mod sync_progress;
+pub mod sync_summary;
 pub mod title_selection;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 
@@ -0,0 +1,153 @@
+use super::centered_rect;
+use crate::app::App;
+use crate::ui::{self, SelectionDirection};
+use lma_lib::Service;
+use ratatui::layout::{Alignment, Constraint, Direction, Layout, Margin};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AccountAction {
+    LogIn,
+    /// Logs out and right back in, the browser or the form picks the account
+    SwitchAccount,
+    LogOut,
+}
+
+impl AccountAction {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::LogIn => "Log in",
+            Self::SwitchAccount => "Log in as someone else",
+            Self::LogOut => "Log out",
+        }
+    }
+}
+
+/// Account the service is logged in to and what can be done with it
+pub struct AccountPopup {
+    logged_in: bool,
+    /// Error when the service couldn't be asked for it
+    username: Result<Option<String>, String>,
+    state: ListState,
+}
+
+impl Default for AccountPopup {
+    fn default() -> Self {
+        Self::new(false, Ok(None))
+    }
+}
+
+impl AccountPopup {
+    pub fn new(logged_in: bool, username: Result<Option<String>, String>) -> Self {
+        Self {
+            logged_in,
+            username,
+            state: ListState::default().with_selected(Some(0)),
+        }
+    }
+
+    const fn actions(&self) -> &'static [AccountAction] {
+        if self.logged_in {
+            &[AccountAction::SwitchAccount, AccountAction::LogOut]
+        } else {
+            &[AccountAction::LogIn]
+        }
+    }
+
+    pub fn move_selection(&mut self, direction: &SelectionDirection) {
+        let i = ui::select_element(self.actions().len(), self.state.selected(), direction);
+        self.state.select(Some(i));
+    }
+
+    pub fn selected_action(&self) -> AccountAction {
+        let actions = self.actions();
+        let index = self.state.selected().unwrap_or_default();
+        actions.get(index).copied().unwrap_or(actions[0])
+    }
+}
+
+pub fn build<T: Service>(frame: &mut Frame, app: &mut App<T>) {
+    let area = centered_rect(70, 70, frame.size());
+    let inner_area = area.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    let block = Block::default().title("Account").borders(Borders::ALL);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+
+    let main_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(inner_area);
+
+    let popup = &app.account_popup;
+    let mut service = app.config.service().as_str().to_owned();
+    if let Some(mirror) = app.config.mirror_service() {
+        service.push_str(&format!(", mirrored to {}", mirror.as_str()));
+    }
+    let account = match (&popup.username, popup.logged_in) {
+        (_, false) => Span::styled(
+            "Not logged in",
+            Style::default().fg(app.config.colors.text_deleted),
+        ),
+        (Ok(Some(username)), true) => Span::styled(
+            format!("Logged in as {username}"),
+            Style::default().fg(app.config.colors.highlight),
+        ),
+        (Ok(None), true) => Span::styled(
+            "Logged in",
+            Style::default().fg(app.config.colors.highlight),
+        ),
+        (Err(err), true) => Span::raw(format!("Logged in, the account name is unknown: {err}")),
+    };
+    let info = vec![
+        Line::from(vec![
+            Span::raw("Service: "),
+            Span::styled(service, Style::default().add_modifier(Modifier::BOLD)),
+        ]),
+        Line::from(account),
+    ];
+    frame.render_widget(
+        Paragraph::new(info)
+            .wrap(Wrap { trim: true })
+            .alignment(Alignment::Center),
+        main_chunks[0],
+    );
+
+    let actions: Vec<_> = popup
+        .actions()
+        .iter()
+        .map(|action| {
+            ListItem::new(action.as_str()).style(Style::default().fg(app.config.colors.text))
+        })
+        .collect();
+    let actions = List::new(actions).highlight_style(
+        Style::default()
+            .fg(app.config.colors.highlight)
+            .add_modifier(Modifier::BOLD),
+    );
+    frame.render_stateful_widget(actions, main_chunks[1], &mut app.account_popup.state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn account_actions() {
+        let mut popup = AccountPopup::new(true, Ok(Some(String::from("user"))));
+        assert_eq!(popup.selected_action(), AccountAction::SwitchAccount);
+        popup.move_selection(&SelectionDirection::Next);
+        assert_eq!(popup.selected_action(), AccountAction::LogOut);
+        popup.move_selection(&SelectionDirection::Next);
+        assert_eq!(popup.selected_action(), AccountAction::SwitchAccount);
+
+        let mut popup = AccountPopup::new(false, Ok(None));
+        popup.move_selection(&SelectionDirection::Previous);
+        assert_eq!(popup.selected_action(), AccountAction::LogIn);
+    }
+}
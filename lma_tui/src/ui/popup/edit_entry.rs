@@ -0,0 +1,179 @@
+use super::centered_rect;
+use crate::app::App;
+use crate::ui::SelectionDirection;
+use lma_lib::{EntryField, Service, UserEntry};
+use ratatui::layout::{Alignment, Constraint, Direction, Layout, Margin};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Wrap};
+use ratatui::Frame;
+
+#[derive(Default)]
+pub struct EntryPopup {
+    pub local_id: usize,
+    pub service_id: usize,
+    title: String,
+    original: UserEntry,
+    values: [String; EntryField::ALL.len()],
+    selected_line: usize,
+}
+
+impl EntryPopup {
+    pub fn new(local_id: usize, service_id: usize, title: String, entry: UserEntry) -> Self {
+        let values = EntryField::ALL.map(|field| entry.get(field).unwrap_or_default());
+        Self {
+            local_id,
+            service_id,
+            title,
+            original: entry,
+            values,
+            selected_line: 0,
+        }
+    }
+
+    pub fn move_line_selection(&mut self, direction: &SelectionDirection) {
+        let line_count = self.values.len();
+        self.selected_line = match direction {
+            SelectionDirection::Next => (self.selected_line + 1) % line_count,
+            SelectionDirection::Previous => (self.selected_line + line_count - 1) % line_count,
+        };
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.values[self.selected_line].push(c);
+    }
+
+    pub fn pop(&mut self) {
+        self.values[self.selected_line].pop();
+    }
+
+    /// Validated fields that differ from the entry the popup was opened with
+    pub fn changed_fields(&self) -> Result<Vec<(EntryField, Option<String>)>, String> {
+        let mut changed = Vec::new();
+        for (field, value) in EntryField::ALL.into_iter().zip(&self.values) {
+            let value = field.validate(value)?;
+            if value != self.original.get(field) {
+                changed.push((field, value));
+            }
+        }
+        Ok(changed)
+    }
+}
+
+pub fn build<T: Service>(frame: &mut Frame, app: &App<T>) {
+    let area = centered_rect(70, 70, frame.size());
+    let text_area = area.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+
+    let main_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(2), Constraint::Percentage(85)].as_ref())
+        .split(text_area);
+    let title_area = main_chunks[0];
+    let text_area = main_chunks[1];
+
+    let title_line = vec![
+        Line::from(vec![Span::raw("Your list entry for:")]),
+        Line::from(vec![Span::styled(
+            app.entry_popup.title.clone(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )]),
+    ];
+
+    let mut input_form: Vec<_> = EntryField::ALL
+        .into_iter()
+        .zip(&app.entry_popup.values)
+        .map(|(field, value)| {
+            Line::from(vec![
+                Span::raw(format!("{}: ", field.name())),
+                Span::raw(value.clone()),
+            ])
+        })
+        .collect();
+    frame.set_cursor(
+        text_area.x
+            + u16::try_from(
+                input_form
+                    .get(app.entry_popup.selected_line)
+                    .map(Line::width)
+                    .unwrap_or_default(),
+            )
+            .unwrap_or_default(),
+        text_area.y + u16::try_from(app.entry_popup.selected_line).unwrap_or_default(),
+    );
+    // after the cursor so it stays at the end of the value
+    let capabilities = app.anime_list.service.capabilities();
+    for (line, field) in input_form.iter_mut().zip(EntryField::ALL) {
+        if !field.is_supported(&capabilities) {
+            line.spans.push(Span::styled(
+                "  (only kept in lma)",
+                Style::default().add_modifier(Modifier::DIM),
+            ));
+        }
+    }
+    input_form.push(Line::from(""));
+    input_form.push(Line::from(Span::styled(
+        "Status: watching, completed, on hold, dropped, plan to watch. Dates: YYYY-MM-DD",
+        Style::default().add_modifier(Modifier::DIM),
+    )));
+
+    let block = Block::default().title("Edit entry").borders(Borders::ALL);
+    let title = Paragraph::new(title_line)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    let form = Paragraph::new(input_form);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+    frame.render_widget(title, title_area);
+    frame.render_widget(form, text_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lma_lib::EpisodeStatus;
+
+    fn generate_popup() -> EntryPopup {
+        EntryPopup::new(
+            1,
+            101,
+            String::from("Test show"),
+            UserEntry {
+                status: Some(EpisodeStatus::Watching),
+                score: Some(7),
+                start_date: None,
+                finish_date: None,
+            },
+        )
+    }
+
+    #[test]
+    fn no_changes() {
+        let popup = generate_popup();
+        assert_eq!(popup.changed_fields(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn change_score() {
+        let mut popup = generate_popup();
+        popup.move_line_selection(&SelectionDirection::Next);
+        popup.pop();
+        popup.push('9');
+        assert_eq!(
+            popup.changed_fields(),
+            Ok(vec![(EntryField::Score, Some(String::from("9")))])
+        );
+    }
+
+    #[test]
+    fn invalid_date() {
+        let mut popup = generate_popup();
+        popup.move_line_selection(&SelectionDirection::Previous);
+        for c in "yesterday".chars() {
+            popup.push(c);
+        }
+        assert!(popup.changed_fields().is_err());
+    }
+}
@@ -28,7 +28,13 @@ impl SetupPopup {
             service_list: ServiceList {
                 selected_service: ServiceType::Local,
                 state: ListState::default(),
-                services: vec![String::from("Local"), String::from("MAL")],
+                services: vec![
+                    String::from("Local"),
+                    String::from("MAL"),
+                    String::from("AniList"),
+                    String::from("Kitsu"),
+                    String::from("Shikimori"),
+                ],
             },
         }
     }
@@ -50,7 +56,7 @@ impl SetupPopup {
 
     pub fn move_selection(&mut self, direction: &SelectionDirection) {
         let i = ui::select_element(
-            2, // hardcoded length
+            self.service_list.services.len(),
             self.service_list.state.selected(),
             direction,
         );
@@ -62,6 +68,9 @@ impl SetupPopup {
         self.service_list.selected_service =
             match self.service_list.services.get(index).map(String::as_str) {
                 Some("MAL") => ServiceType::MAL,
+                Some("AniList") => ServiceType::AniList,
+                Some("Kitsu") => ServiceType::Kitsu,
+                Some("Shikimori") => ServiceType::Shikimori,
                 Some("Local" | &_) | None => ServiceType::Local,
             }
     }
@@ -185,7 +194,7 @@ fn render_third_page(frame: &mut Frame, area: Rect, config_path: &Path) {
     };
     let content = vec![
         Line::from(Span::raw("That's everything")),
-        Line::from(Span::raw("Relaunch the application for configs to apply")),
+        Line::from(Span::raw("The selected service will be used right away")),
         Line::from(Span::raw("")),
         Line::from(Span::raw(
             "You can also edit your config manually after closing the app:",
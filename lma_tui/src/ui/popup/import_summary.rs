@@ -0,0 +1,107 @@
+use super::centered_rect;
+use crate::app::App;
+use crate::ui::{self, SelectionDirection};
+use lma_lib::{ImportSummary, Service};
+use ratatui::layout::{Alignment, Constraint, Direction, Layout, Margin};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+
+/// How many shows got imported and which ones had to be skipped
+#[derive(Default)]
+pub struct ImportPopup {
+    summary: ImportSummary,
+    state: ListState,
+}
+
+impl ImportPopup {
+    pub fn new(summary: ImportSummary) -> Self {
+        let selected = (!summary.skipped.is_empty()).then_some(0);
+        Self {
+            summary,
+            state: ListState::default().with_selected(selected),
+        }
+    }
+
+    pub fn move_selection(&mut self, direction: &SelectionDirection) {
+        if self.summary.skipped.is_empty() {
+            return;
+        }
+        let i = ui::select_element(self.summary.skipped.len(), self.state.selected(), direction);
+        self.state.select(Some(i));
+    }
+
+    fn message(&self) -> String {
+        let imported = format!("Imported {} shows from the service.", self.summary.imported);
+        match self.summary.skipped.len() {
+            0 => imported,
+            skipped => format!(
+                "{imported} Skipped {skipped}, a different show in the list has the same title:"
+            ),
+        }
+    }
+}
+
+pub fn build<T: Service>(frame: &mut Frame, app: &mut App<T>) {
+    let area = centered_rect(60, 50, frame.size());
+    let inner_area = area.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    let block = Block::default().title("Import").borders(Borders::ALL);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(1)].as_ref())
+        .split(inner_area);
+
+    let popup = &app.import_popup;
+    frame.render_widget(
+        Paragraph::new(popup.message())
+            .style(Style::default().fg(app.config.colors.text))
+            .wrap(Wrap { trim: true })
+            .alignment(Alignment::Center),
+        chunks[0],
+    );
+
+    let skipped: Vec<_> = popup
+        .summary
+        .skipped
+        .iter()
+        .map(|title| {
+            ListItem::new(title.clone()).style(Style::default().fg(app.config.colors.text))
+        })
+        .collect();
+    let skipped = List::new(skipped).highlight_style(
+        Style::default()
+            .fg(app.config.colors.highlight)
+            .add_modifier(Modifier::BOLD),
+    );
+    frame.render_stateful_widget(skipped, chunks[1], &mut app.import_popup.state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_message() {
+        let popup = ImportPopup::new(ImportSummary {
+            imported: 3,
+            skipped: Vec::new(),
+        });
+        assert_eq!(popup.message(), "Imported 3 shows from the service.");
+
+        let mut popup = ImportPopup::new(ImportSummary {
+            imported: 1,
+            skipped: vec![String::from("Show"), String::from("Other show")],
+        });
+        assert!(popup
+            .message()
+            .ends_with("Skipped 2, a different show in the list has the same title:"));
+        popup.move_selection(&SelectionDirection::Next);
+        assert_eq!(popup.state.selected(), Some(1));
+    }
+}
@@ -1,8 +1,9 @@
 use super::{centered_rect, episode_mismatch::MismatchPopup, title_selection::TitlesPopup};
 use crate::app::App;
 use crate::ui::{FocusedWindow, SelectionDirection};
-use lma_lib::{AnimeList, Episode, Service, ServiceType};
+use lma_lib::{AnimeList, Episode, Service, ServiceTitle, ServiceType, ShowNfo};
 use ratatui::layout::Margin;
+use ratatui::style::Style;
 use ratatui::text::{Line, Span};
 use ratatui::widgets::{Block, Borders, Clear, Paragraph};
 use ratatui::Frame;
@@ -15,6 +16,12 @@ pub struct InsertPopup {
     pub path: PathBuf,
     title: String,
     pub service_id: usize,
+    /// Last service id that was looked up
+    checked_id: usize,
+    /// What the checked id points to
+    details: Option<ServiceTitle>,
+    /// Why the checked id was rejected
+    id_error: String,
     episode_count: usize,
     pub state: InsertState,
     pub data: String,
@@ -52,6 +59,29 @@ impl InsertPopup {
         }
         self.selected_line == 0
     }
+    /// Show picked from the search results doesn't need another lookup
+    pub fn set_details(&mut self, details: ServiceTitle) {
+        self.service_id = details.service_id;
+        self.checked_id = details.service_id;
+        self.details = Some(details);
+        self.id_error = String::new();
+    }
+    /// Line with the service id, without the id itself
+    fn service_id_info(&self) -> String {
+        if !self.id_error.is_empty() {
+            return format!(" {}", self.id_error);
+        }
+        let Some(details) = self.details.as_ref().filter(|details| !details.title.is_empty())
+        else {
+            return String::new();
+        };
+        let info: Vec<String> = std::iter::once(details.title.clone())
+            .chain(details.media_type.map(|media_type| media_type.as_str().to_owned()))
+            .chain(details.year.map(|year| year.to_string()))
+            .chain(details.episodes.map(|episodes| format!("{episodes} episodes")))
+            .collect();
+        format!(" ({})", info.join(", "))
+    }
 }
 
 pub fn build<T: Service>(
@@ -72,7 +102,7 @@ pub fn build<T: Service>(
         InsertState::None => {}
     }
 
-    let input_form = vec![
+    let mut input_form = vec![
         Line::from(vec![
             Span::raw("Path to the folder: "),
             Span::raw(app.insert_popup.path.to_string_lossy()),
@@ -103,6 +133,16 @@ pub fn build<T: Service>(
             text_area.y + u16::try_from(app.insert_popup.current_line()).unwrap_or_default(),
         );
     }
+    // added after the cursor is placed so it stays at the end of the id
+    let info_color = if app.insert_popup.id_error.is_empty() {
+        app.config.colors.secondary
+    } else {
+        app.config.colors.text_deleted
+    };
+    input_form[2].spans.push(Span::styled(
+        app.insert_popup.service_id_info(),
+        Style::default().fg(info_color),
+    ));
 
     let block = Block::default().title("Insert show").borders(Borders::ALL);
     // .wrap(Wrap { trim: true }); messes up the cursor position
@@ -117,13 +157,20 @@ fn handle_inputting_state<T: Service>(app: &mut App<T>) {
     match app.insert_popup.current_line() {
         0 => app.insert_popup.path = app.insert_popup.data.clone().into(),
         1 => app.insert_popup.title = app.insert_popup.data.clone(),
-        2 => app.insert_popup.service_id = parse_number(&mut app.insert_popup.data),
+        2 => {
+            app.insert_popup.service_id = parse_number(&mut app.insert_popup.data);
+            if app.insert_popup.service_id != app.insert_popup.checked_id {
+                app.insert_popup.details = None;
+                app.insert_popup.id_error = String::new();
+            }
+        }
         3 => app.insert_popup.episode_count = parse_number(&mut app.insert_popup.data),
         _ => {}
     }
 }
 
 async fn handle_next_state<T: Service>(app: &mut App<T>) -> Result<(), String> {
+    check_service_id(app).await;
     match app.insert_popup.current_line() {
         1 if !app.insert_popup.path.to_string_lossy().is_empty()
             && app.insert_popup.title.is_empty() =>
@@ -133,13 +180,15 @@ async fn handle_next_state<T: Service>(app: &mut App<T>) -> Result<(), String> {
         }
         2 if !app.insert_popup.title.is_empty()
             && app.insert_popup.service_id == 0
-            && app.anime_list.service.get_service_type() != ServiceType::Local =>
+            && app.anime_list.service.capabilities().search =>
         {
             // when there's title and no service id
             handle_third_line(app).await
         }
         3 if (app.insert_popup.service_id != 0
-            || app.anime_list.service.get_service_type() == ServiceType::Local)
+            || !app.anime_list.service.capabilities().search
+            || is_local(app))
+            && app.insert_popup.id_error.is_empty()
             && app.insert_popup.episode_count == 0
             && !app.insert_popup.path.to_string_lossy().is_empty() =>
         {
@@ -161,6 +210,27 @@ async fn handle_next_state<T: Service>(app: &mut App<T>) -> Result<(), String> {
     Ok(())
 }
 
+/// Looks up the id once, an invalid one gets an error next to it
+async fn check_service_id<T: Service>(app: &mut App<T>) {
+    let service_id = app.insert_popup.service_id;
+    if service_id == app.insert_popup.checked_id {
+        return;
+    }
+    app.insert_popup.checked_id = service_id;
+    app.insert_popup.details = None;
+    app.insert_popup.id_error = String::new();
+    if service_id == 0 {
+        return;
+    }
+    match app.anime_list.service.get_title_details(service_id).await {
+        Ok(details) => app.insert_popup.details = Some(details),
+        Err(err) => {
+            let service = app.anime_list.service.get_service_type();
+            app.insert_popup.id_error = format!("is not a valid {} ID: {err}", service.as_str());
+        }
+    }
+}
+
 fn handle_second_line<T: Service>(app: &mut App<T>) -> Result<(), String> {
     // trim path
     let matches: &[_] = &['"', '\''];
@@ -176,17 +246,23 @@ fn handle_second_line<T: Service>(app: &mut App<T>) -> Result<(), String> {
                 }
             });
     app.insert_popup.title = app.anime_list.guess_shows_title(&app.insert_popup.path)?;
+    // with an id from the nfo there's no need to pick the show from a list
+    if let Some(nfo) = ShowNfo::read(&app.insert_popup.path)? {
+        if let Some(service_id) = app.anime_list.nfo_service_id(&nfo) {
+            app.insert_popup.service_id = service_id;
+        }
+    }
     Ok(())
 }
 
 async fn handle_third_line<T: Service>(app: &mut App<T>) -> Result<(), String> {
     // create a popup to select the exact show from a sync service
-    let items = app
+    let page = app
         .anime_list
         .service
-        .search_title(&app.insert_popup.title)
+        .search_title(&app.insert_popup.title, 0)
         .await?;
-    app.titles_popup = TitlesPopup::new(items);
+    app.titles_popup = TitlesPopup::new(&app.insert_popup.title, page);
     app.focused_window = FocusedWindow::TitleSelection;
     Ok(())
 }
@@ -208,23 +284,13 @@ async fn handle_forth_line<T: Service>(app: &mut App<T>) -> Result<(), String> {
         .unwrap_or_default();
 
     app.insert_popup.episode_count = episode_count;
+    // shows missing from the offline database take whatever files there are
     if episode_count == video_files_count
-        || app.anime_list.service.get_service_type() == ServiceType::Local
+        || !app.anime_list.service.capabilities().search
+        || (is_local(app) && episode_count == 0)
     {
-        app.insert_popup.episodes = AnimeList::<T>::get_video_file_paths(&app.insert_popup.path)
-            .unwrap_or_default()
-            .into_iter()
-            .enumerate()
-            .map(|(k, path)| Episode {
-                number: k + 1,
-                path: path.clone(),
-                title: String::new(),
-                file_deleted: !path.exists(),
-                score: 0.0,
-                recap: false,
-                filler: false,
-            })
-            .collect();
+        app.insert_popup.episodes =
+            AnimeList::<T>::get_local_episodes(&app.insert_popup.path).unwrap_or_default();
     } else if episode_count > video_files_count {
         app.mismatch_popup = MismatchPopup::new(episode_count, video_files_count);
         app.focused_window = FocusedWindow::EpisodeMismatch;
@@ -255,13 +321,29 @@ async fn fill_title<T: Service>(app: &mut App<T>) -> Result<(), String> {
                 .await?,
         );
     }
-    if app.anime_list.service.get_service_type() != ServiceType::Local {
-        app.insert_popup.title = title.expect("Has to be set at this point");
+    // Local doesn't know titles of shows missing from the offline database
+    if let Some(title) = title.filter(|title| !title.is_empty()) {
+        if app.anime_list.service.capabilities().search {
+            app.insert_popup.title = title;
+        }
     }
     Ok(())
 }
 
+/// Local lets shows without an id in and adds more folders of a show after its episodes
+fn is_local<T: Service>(app: &App<T>) -> bool {
+    app.anime_list.service.get_service_type() == ServiceType::Local
+}
+
 async fn handle_save_state<T: Service>(app: &mut App<T>) -> Result<(), String> {
+    check_service_id(app).await;
+    if !app.insert_popup.id_error.is_empty() {
+        // back to the id so it can be fixed
+        app.insert_popup.selected_line = 2;
+        app.insert_popup.data = app.insert_popup.service_id.to_string();
+        app.insert_popup.state = InsertState::Inputting;
+        return Ok(());
+    }
     match app
         .anime_list
         .add_show(&app.insert_popup.title, app.insert_popup.service_id, 0)
@@ -277,15 +359,24 @@ async fn handle_save_state<T: Service>(app: &mut App<T>) -> Result<(), String> {
         Err(why) => {
             if why.contains("constraint failed") {
                 // show with this sync_service_id or title already exists
-                // get local_id of the show with the same title
-                if let Ok(local_id) = app
+                // get local_id of the show with the same title or service id,
+                // the latter links folders to shows imported from the service
+                let local_id = match app
                     .anime_list
                     .get_local_show_id(&app.insert_popup.title)
                     .await
                 {
+                    Ok(local_id) => Ok(local_id),
+                    Err(_) => {
+                        app.anime_list
+                            .get_local_show_id_by_service_id(app.insert_popup.service_id)
+                            .await
+                    }
+                };
+                if let Ok(local_id) = local_id {
                     insert_episodes(app, local_id).await?;
                 }
-                // don't do anything more if can't get the id by title
+                // don't do anything more if can't get the id
             } else {
                 eprintln!("{why}");
             }
@@ -312,7 +403,7 @@ async fn insert_episodes<T: Service>(app: &mut App<T>, local_id: usize) -> Resul
     )
     .await?;
     // surely I can be smarter about it
-    let episode_offset = if app.anime_list.service.get_service_type() == ServiceType::Local {
+    let episode_offset = if is_local(app) {
         app.anime_list
             .get_list()
             .await
@@ -338,7 +429,12 @@ async fn insert_episodes<T: Service>(app: &mut App<T>, local_id: usize) -> Resul
                 local_id,
                 episode.number + episode_offset,
                 &episode.path.to_string_lossy(),
-                &details.title,
+                // titles from the episode's nfo take priority
+                if episode.title.is_empty() {
+                    &details.title
+                } else {
+                    &episode.title
+                },
                 generate_extra_info(details.recap, details.filler),
                 details.score.unwrap_or_default(),
             )
@@ -363,6 +459,9 @@ pub async fn get_episodes_info<T: Service>(
     id: usize,
     precise_score: bool,
 ) -> Result<HashMap<usize, EpisodeDetails>, String> {
+    if !service.capabilities().episode_metadata {
+        return Ok(HashMap::new());
+    }
     let episodes_details = service.get_episodes(id, precise_score).await?;
     Ok(episodes_details
         .iter()
@@ -381,14 +480,7 @@ pub async fn get_episodes_info<T: Service>(
 }
 
 pub const fn generate_extra_info(recap: bool, filler: bool) -> usize {
-    let mut extra_info: usize = 0;
-    if recap {
-        extra_info |= 1 << 0;
-    }
-    if filler {
-        extra_info |= 1 << 1;
-    }
-    extra_info
+    lma_lib::extra_info(recap, filler)
 }
 
 /// Clears the string on an invalid number
@@ -405,6 +497,11 @@ fn parse_number(str: &mut String) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Config;
+    use crate::handle_input;
+    use crossterm::event::KeyEvent;
+    use lma_lib::Local;
+    use std::fs;
 
     #[test]
     fn test_current_line() {
@@ -440,6 +537,76 @@ mod tests {
         assert_eq!(insert_popup.current_line(), 0);
     }
 
+    #[test]
+    fn service_id_info() {
+        let mut insert_popup = InsertPopup::default();
+        assert_eq!(insert_popup.service_id_info(), "");
+        insert_popup.set_details(ServiceTitle {
+            service_id: 30230,
+            title: "Diamond no Ace: Second Season".to_owned(),
+            media_type: Some(lma_lib::MediaType::TV),
+            episodes: Some(51),
+            ..Default::default()
+        });
+        assert_eq!(insert_popup.service_id, 30230);
+        assert_eq!(
+            insert_popup.service_id_info(),
+            " (Diamond no Ace: Second Season, TV, 51 episodes)"
+        );
+    }
+
+    #[test]
+    fn local_insert_flow() {
+        let dir = std::env::temp_dir().join(format!("lma_local_insert_{}", std::process::id()));
+        let show_dir = dir.join("Diamond no Ace S2");
+        fs::create_dir_all(&show_dir).unwrap();
+        for episode in 1..=2 {
+            fs::write(
+                show_dir.join(format!("Diamond no Ace - {episode:02}.mkv")),
+                "",
+            )
+            .unwrap();
+        }
+        let offline_database = r#"{ "data": [{
+            "sources": ["https://myanimelist.net/anime/30230"],
+            "title": "Diamond no Ace: Second Season",
+            "type": "TV",
+            "episodes": 51,
+            "animeSeason": { "season": "SPRING", "year": 2015 }
+        }] }"#;
+        fs::write(dir.join("anime-offline-database.json"), offline_database).unwrap();
+
+        let rt = Runtime::new().unwrap();
+        let config = Config::build(&dir, &dir).unwrap();
+        let token_store = config.credentials.open(&dir, None).unwrap();
+        let local = rt.block_on(Local::new(dir.clone())).unwrap();
+        let mut app = rt.block_on(App::build(config, local, token_store)).unwrap();
+        assert!(app.anime_list.service.capabilities().search);
+
+        // title is guessed from the files
+        app.insert_popup.path = show_dir;
+        app.insert_popup.selected_line = 1;
+        rt.block_on(handle_next_state(&mut app)).unwrap();
+        assert_eq!(app.insert_popup.title, "Diamond no Ace");
+
+        // and looked up in the offline database
+        app.insert_popup.selected_line = 2;
+        rt.block_on(handle_next_state(&mut app)).unwrap();
+        assert!(app.focused_window == FocusedWindow::TitleSelection);
+        let confirm = KeyEvent::from(app.config.key_binds.confirmation);
+        handle_input::title_selection(confirm, &mut app, &rt);
+        assert_eq!(app.insert_popup.service_id, 30230);
+
+        // 2 files of 51 episodes
+        app.insert_popup.selected_line = 3;
+        rt.block_on(handle_next_state(&mut app)).unwrap();
+        assert_eq!(app.insert_popup.title, "Diamond no Ace: Second Season");
+        assert_eq!(app.insert_popup.episode_count, 51);
+        assert!(app.focused_window == FocusedWindow::EpisodeMismatch);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_generate_extra_info() {
         let result = generate_extra_info(false, false);
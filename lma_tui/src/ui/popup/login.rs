@@ -1,42 +1,214 @@
 use super::centered_rect;
 use crate::app::App;
+use crate::config::Config;
+use crate::ui::main_menu::help::keycode_to_key;
 use lma_lib::Service;
-use ratatui::layout::{Alignment, Margin};
+use ratatui::layout::{Alignment, Constraint, Direction, Layout, Margin, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
 use ratatui::widgets::{Block, Borders, Clear, Paragraph, Wrap};
 use ratatui::Frame;
+use std::time::Duration;
+
+/// Credentials for services that don't log in through the browser,
+/// or the address the browser ended up on for those that do
+#[derive(Default)]
+pub struct LoginPopup {
+    username: String,
+    password: String,
+    selected_line: usize,
+    redirect_url: String,
+}
+
+impl LoginPopup {
+    /// There are only two lines so both directions do the same
+    pub fn switch_line(&mut self) {
+        self.selected_line = 1 - self.selected_line;
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.selected_field().push(c);
+    }
+
+    pub fn pop(&mut self) {
+        self.selected_field().pop();
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    pub fn push_redirect(&mut self, c: char) {
+        self.redirect_url.push(c);
+    }
+
+    pub fn pop_redirect(&mut self) {
+        self.redirect_url.pop();
+    }
+
+    pub fn redirect_url(&self) -> &str {
+        self.redirect_url.trim()
+    }
+
+    fn selected_field(&mut self) -> &mut String {
+        if self.selected_line == 0 {
+            &mut self.username
+        } else {
+            &mut self.password
+        }
+    }
+}
 
 pub fn build<T: Service>(frame: &mut Frame, app: &App<T>) {
-    let area = centered_rect(70, 70, frame.size());
-    let text_area = area.inner(&Margin {
-        vertical: 1,
-        horizontal: 1,
-    });
-
-    let login_info = app.anime_list.service.get_url().map_or_else(
-        || {
-            vec![Line::from(Span::styled(
-                "You are already logged in",
-                Style::default().fg(Color::Green),
-            ))]
-        },
-        |url| {
-            _ = open::that(url.clone());
-            vec![
-                Line::from(Span::raw("Login using the link below")),
-                Line::from(Span::styled(
-                    url,
-                    Style::default().add_modifier(Modifier::BOLD),
-                )),
-            ]
-        },
+    let text_area = render_block(frame);
+    let service = &app.anime_list.service;
+    if service.needs_credentials() && !service.is_logged_in() {
+        build_credentials(frame, &app.login_popup, text_area);
+        return;
+    }
+    let logged_in = Paragraph::new(Line::from(Span::styled(
+        "You are already logged in",
+        Style::default().fg(Color::Green),
+    )))
+    .alignment(Alignment::Center);
+    frame.render_widget(logged_in, text_area);
+}
+
+/// Drawn on its own while the service's redirect is awaited
+pub fn build_waiting(
+    frame: &mut Frame,
+    popup: &LoginPopup,
+    url: &str,
+    time_left: Duration,
+    config: &Config,
+) {
+    let text_area = render_block(frame);
+    let main_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(6), Constraint::Percentage(50)].as_ref())
+        .split(text_area);
+    let info_area = main_chunks[0];
+    let paste_area = main_chunks[1];
+
+    let key_binds = &config.key_binds;
+    let info = vec![
+        Line::from(Span::raw("Login using the link below")),
+        Line::from(Span::styled(
+            url.to_owned(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from(Span::styled(
+            format!(
+                "Waiting for the browser, {}:{:02} left. {} to cancel",
+                time_left.as_secs() / 60,
+                time_left.as_secs() % 60,
+                keycode_to_key(key_binds.close)
+            ),
+            Style::default().fg(config.colors.secondary),
+        )),
+    ];
+    let paste_form = vec![
+        Line::from(Span::raw(format!(
+            "Browser on another machine? Paste the address it ended up on and press {}",
+            keycode_to_key(key_binds.confirmation)
+        ))),
+        Line::from(vec![
+            Span::raw("Address: "),
+            Span::raw(popup.redirect_url.clone()),
+        ]),
+    ];
+    let paste_width = paste_form.last().map(Line::width).unwrap_or_default();
+    frame.set_cursor(
+        paste_area.x + u16::try_from(paste_width).unwrap_or_default(),
+        paste_area.y + 1,
     );
+    frame.render_widget(
+        Paragraph::new(info)
+            .wrap(Wrap { trim: true })
+            .alignment(Alignment::Center),
+        info_area,
+    );
+    frame.render_widget(Paragraph::new(paste_form), paste_area);
+}
+
+/// Returns the area inside the border
+fn render_block(frame: &mut Frame) -> Rect {
+    let area = centered_rect(70, 70, frame.size());
     let block = Block::default().title("Login").borders(Borders::ALL);
-    let form = Paragraph::new(login_info)
-        .wrap(Wrap { trim: true })
-        .alignment(Alignment::Center);
     frame.render_widget(Clear, area);
     frame.render_widget(block, area);
-    frame.render_widget(form, text_area);
+    area.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    })
+}
+
+fn build_credentials(frame: &mut Frame, popup: &LoginPopup, area: Rect) {
+    let main_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(2), Constraint::Percentage(85)].as_ref())
+        .split(area);
+    let title_area = main_chunks[0];
+    let text_area = main_chunks[1];
+
+    let title = Paragraph::new(Line::from(Span::raw("Log in with your email and password")))
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    let input_form = vec![
+        Line::from(vec![
+            Span::raw("Email: "),
+            Span::raw(popup.username.clone()),
+        ]),
+        Line::from(vec![
+            Span::raw("Password: "),
+            Span::raw("*".repeat(popup.password.chars().count())),
+        ]),
+    ];
+    frame.set_cursor(
+        text_area.x
+            + u16::try_from(
+                input_form
+                    .get(popup.selected_line)
+                    .map(Line::width)
+                    .unwrap_or_default(),
+            )
+            .unwrap_or_default(),
+        text_area.y + u16::try_from(popup.selected_line).unwrap_or_default(),
+    );
+    frame.render_widget(title, title_area);
+    frame.render_widget(Paragraph::new(input_form), text_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_credentials() {
+        let mut popup = LoginPopup::default();
+        "user".chars().for_each(|c| popup.push(c));
+        popup.switch_line();
+        "pass".chars().for_each(|c| popup.push(c));
+        popup.pop();
+        popup.switch_line();
+        popup.push('s');
+        assert_eq!(popup.username(), "users");
+        assert_eq!(popup.password(), "pas");
+    }
+
+    #[test]
+    fn paste_redirect() {
+        let mut popup = LoginPopup::default();
+        " http://localhost:2525/?code=ab"
+            .chars()
+            .for_each(|c| popup.push_redirect(c));
+        popup.pop_redirect();
+        assert_eq!(popup.redirect_url(), "http://localhost:2525/?code=a");
+        assert_eq!(popup.username(), "");
+    }
 }
@@ -0,0 +1,151 @@
+use super::centered_rect;
+use crate::app::App;
+use crate::ui::{self, widgets::ScrollableTable, SelectionDirection};
+use lma_lib::{ProgressChange, Service, SyncDirection};
+use ratatui::layout::{Constraint, Margin};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Clear, Row, TableState};
+use ratatui::Frame;
+
+#[derive(Default)]
+pub struct SyncPopup {
+    pub state: TableState,
+    changes: Vec<ProgressChange>,
+}
+
+impl SyncPopup {
+    pub fn new(changes: Vec<ProgressChange>) -> Self {
+        let mut default_state = TableState::default();
+        if !changes.is_empty() {
+            default_state.select(Some(0));
+        }
+        Self {
+            state: default_state,
+            changes,
+        }
+    }
+
+    pub fn move_selection(&mut self, direction: &SelectionDirection) {
+        let i = ui::select_element(self.changes.len(), self.state.selected(), direction);
+        self.state.select(Some(i));
+    }
+
+    pub fn resolve_selected(&mut self, direction: SyncDirection) {
+        if let Some(change) = self
+            .state
+            .selected()
+            .and_then(|index| self.changes.get_mut(index))
+        {
+            change.resolution = Some(direction);
+        }
+    }
+
+    pub fn changes(&self) -> &[ProgressChange] {
+        &self.changes
+    }
+}
+
+pub fn build<T: Service>(frame: &mut Frame, app: &mut App<T>) {
+    let area = centered_rect(70, 70, frame.size());
+    let list_area = area.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+
+    let items: Vec<_> = app
+        .sync_popup
+        .changes
+        .iter()
+        .map(|change| {
+            Row::new([
+                change.title.clone(),
+                change.local_progress.to_string(),
+                change.remote_progress.to_string(),
+                resolution_text(change.resolution).to_string(),
+            ])
+            .style(Style::default().fg(app.config.colors.text))
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Percentage(100),
+        Constraint::Min(6),
+        Constraint::Min(8),
+        Constraint::Min(12),
+    ];
+    let items = ScrollableTable::new(items)
+        .header(
+            Row::new(["Title", "Local", "Service", "Action"])
+                .style(Style::default().fg(app.config.colors.secondary)),
+        )
+        .widths(&widths)
+        .column_spacing(1)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Progress sync"),
+        )
+        .highlight_style(
+            Style::default()
+                .fg(app.config.colors.highlight)
+                .add_modifier(Modifier::BOLD),
+        );
+
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(items, list_area, &mut app.sync_popup.state);
+}
+
+const fn resolution_text(resolution: Option<SyncDirection>) -> &'static str {
+    match resolution {
+        Some(SyncDirection::Push) => "Keep local",
+        Some(SyncDirection::Pull) => "Use service",
+        None => "Skip",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_first() {
+        let mut popup = SyncPopup::new(generate_changes(3));
+        popup.resolve_selected(SyncDirection::Pull);
+        assert_eq!(
+            popup.changes()[0].resolution,
+            Some(SyncDirection::Pull),
+            "First change is selected by default"
+        );
+        assert_eq!(popup.changes()[1].resolution, None);
+    }
+
+    #[test]
+    fn resolve_after_wrap() {
+        let mut popup = SyncPopup::new(generate_changes(3));
+        popup.move_selection(&SelectionDirection::Previous);
+        popup.resolve_selected(SyncDirection::Push);
+        assert_eq!(popup.changes()[2].resolution, Some(SyncDirection::Push));
+    }
+
+    #[test]
+    fn resolve_empty() {
+        let mut popup = SyncPopup::new(Vec::new());
+        popup.move_selection(&SelectionDirection::Next);
+        popup.resolve_selected(SyncDirection::Push);
+        assert!(popup.changes().is_empty());
+    }
+
+    fn generate_changes(count: usize) -> Vec<ProgressChange> {
+        (1..=count)
+            .map(|i| ProgressChange {
+                local_id: i,
+                service_id: 100 + i,
+                title: format!("Test title {i}"),
+                local_progress: i,
+                remote_progress: i + 1,
+                remote_updated_at: None,
+                resolution: None,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,273 @@
+use super::centered_rect;
+use crate::app::App;
+use crate::ui::{self, widgets::ScrollableTable, SelectionDirection};
+use lma_lib::{AiringShow, MediaType, Season, Service, ServiceSearchPage, ServiceTitle};
+use ratatui::layout::{Alignment, Constraint, Direction, Layout, Margin};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::Frame;
+use time::macros::format_description;
+use time::{Duration, OffsetDateTime, UtcOffset};
+
+/// Shows starting in a season and when the next episodes of watched shows air
+pub struct SchedulePopup {
+    pub state: TableState,
+    season: Season,
+    titles: Vec<ServiceTitle>,
+    next_offset: Option<usize>,
+    /// None when the service doesn't know air dates
+    airing: Option<Vec<AiringShow>>,
+}
+
+impl Default for SchedulePopup {
+    fn default() -> Self {
+        Self::new(Season::current(), ServiceSearchPage::default(), None)
+    }
+}
+
+impl SchedulePopup {
+    pub fn new(season: Season, page: ServiceSearchPage, airing: Option<Vec<AiringShow>>) -> Self {
+        let mut popup = Self {
+            state: TableState::default(),
+            season,
+            titles: Vec::new(),
+            next_offset: None,
+            airing,
+        };
+        popup.set_season(season, page);
+        popup
+    }
+
+    pub const fn season(&self) -> Season {
+        self.season
+    }
+
+    /// Replaces the listed shows with the first page of another season
+    pub fn set_season(&mut self, season: Season, page: ServiceSearchPage) {
+        self.season = season;
+        self.titles.clear();
+        self.state.select(None);
+        self.add_page(page);
+    }
+
+    pub fn add_page(&mut self, page: ServiceSearchPage) {
+        self.titles.extend(page.titles);
+        self.next_offset = page.next_offset;
+        if self.state.selected().is_none() && !self.titles.is_empty() {
+            self.state.select(Some(0));
+        }
+    }
+
+    /// Season and offset of the next page, only when the last show is selected
+    pub fn next_page(&self) -> Option<(Season, usize)> {
+        let at_end = self.titles.is_empty() || self.state.selected() == Some(self.titles.len() - 1);
+        self.next_offset
+            .filter(|_| at_end)
+            .map(|offset| (self.season, offset))
+    }
+
+    pub fn move_selection(&mut self, direction: &SelectionDirection) {
+        let i = ui::select_element(self.titles.len(), self.state.selected(), direction);
+        self.state.select((!self.titles.is_empty()).then_some(i));
+    }
+
+    fn window_title(&self) -> String {
+        let mut title = format!("{} - {} shows", self.season, self.titles.len());
+        if self.next_offset.is_some() {
+            title += ", more below";
+        }
+        title
+    }
+}
+
+pub fn build<T: Service>(frame: &mut Frame, app: &mut App<T>) {
+    let area = centered_rect(80, 80, frame.size());
+    let inner_area = area.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Block::default().title("Schedule").borders(Borders::ALL),
+        area,
+    );
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+        .split(inner_area);
+
+    let airing_block = Block::default()
+        .borders(Borders::ALL)
+        .title("Airing next, times in UTC");
+    let now = OffsetDateTime::now_utc();
+    let text_style = Style::default().fg(app.config.colors.text);
+    match &app.schedule_popup.airing {
+        Some(airing) if !airing.is_empty() => {
+            let rows: Vec<_> = airing
+                .iter()
+                .map(|show| {
+                    let episode = show.episode.map_or_else(
+                        || String::from("Next episode"),
+                        |episode| format!("Episode {episode}"),
+                    );
+                    Row::new([
+                        show.title.clone(),
+                        episode,
+                        format_air_time(show.airs_at),
+                        format_time_left(show.airs_at - now),
+                    ])
+                    .style(text_style)
+                })
+                .collect();
+            let widths = [
+                Constraint::Percentage(100),
+                Constraint::Min(12),
+                Constraint::Min(16),
+                Constraint::Min(10),
+            ];
+            let table = Table::new(rows, widths)
+                .column_spacing(1)
+                .block(airing_block);
+            frame.render_widget(table, chunks[0]);
+        }
+        airing => {
+            let message = if airing.is_some() {
+                String::from("Nothing you're watching has an episode coming up")
+            } else {
+                format!(
+                    "{} doesn't know when episodes air",
+                    app.anime_list.service.get_service_type().as_str()
+                )
+            };
+            let paragraph = Paragraph::new(message)
+                .style(text_style)
+                .alignment(Alignment::Center)
+                .wrap(Wrap { trim: true })
+                .block(airing_block);
+            frame.render_widget(paragraph, chunks[0]);
+        }
+    }
+
+    let rows: Vec<_> = app
+        .schedule_popup
+        .titles
+        .iter()
+        .map(|service_title| {
+            Row::new([
+                service_title.title.clone(),
+                service_title
+                    .media_type
+                    .map_or("", MediaType::as_str)
+                    .to_owned(),
+                service_title
+                    .episodes
+                    .map(|episodes| episodes.to_string())
+                    .unwrap_or_default(),
+                if service_title.nsfw { "18+" } else { "" }.to_owned(),
+            ])
+            .style(text_style)
+        })
+        .collect();
+    let widths = [
+        Constraint::Percentage(100),
+        Constraint::Min(7),
+        Constraint::Min(8),
+        Constraint::Min(4),
+    ];
+    let table = ScrollableTable::new(rows)
+        .header(
+            Row::new(["Title", "Type", "Episodes", "NSFW"])
+                .style(Style::default().fg(app.config.colors.secondary)),
+        )
+        .widths(&widths)
+        .column_spacing(1)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(app.schedule_popup.window_title()),
+        )
+        .highlight_style(
+            Style::default()
+                .fg(app.config.colors.highlight)
+                .add_modifier(Modifier::BOLD),
+        );
+    frame.render_stateful_widget(table, chunks[1], &mut app.schedule_popup.state);
+}
+
+fn format_air_time(airs_at: OffsetDateTime) -> String {
+    airs_at
+        .to_offset(UtcOffset::UTC)
+        .format(format_description!(
+            "[weekday repr:short] [day] [month repr:short] [hour]:[minute]"
+        ))
+        .unwrap_or_default()
+}
+
+/// Like "2d 5h", minutes only once it's less than an hour away
+fn format_time_left(time_left: Duration) -> String {
+    if time_left.is_negative() {
+        return String::from("airing");
+    }
+    let days = time_left.whole_days();
+    let hours = time_left.whole_hours() % 24;
+    let minutes = time_left.whole_minutes() % 60;
+    match (days, hours) {
+        (0, 0) => format!("in {minutes}m"),
+        (0, _) => format!("in {hours}h {minutes}m"),
+        _ => format!("in {days}d {hours}h"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lma_lib::SeasonName;
+    use time::macros::datetime;
+
+    fn page(titles: &[&str], next_offset: Option<usize>) -> ServiceSearchPage {
+        ServiceSearchPage {
+            titles: titles
+                .iter()
+                .map(|title| ServiceTitle {
+                    title: (*title).to_owned(),
+                    ..Default::default()
+                })
+                .collect(),
+            next_offset,
+        }
+    }
+
+    #[test]
+    fn season_pages() {
+        let season = Season::new(SeasonName::Fall, 2023);
+        let mut popup = SchedulePopup::new(season, page(&["Frieren", "Apothecary"], Some(2)), None);
+        assert_eq!(popup.next_page(), None, "Only at the end");
+        popup.move_selection(&SelectionDirection::Next);
+        assert_eq!(popup.next_page(), Some((season, 2)));
+        popup.add_page(page(&["Shangri-La Frontier"], None));
+        assert_eq!(popup.state.selected(), Some(1));
+        popup.move_selection(&SelectionDirection::Next);
+        assert_eq!(popup.next_page(), None);
+        assert_eq!(popup.window_title(), "Fall 2023 - 3 shows");
+
+        popup.set_season(season.next(), page(&[], None));
+        assert_eq!(popup.season(), Season::new(SeasonName::Winter, 2024));
+        assert_eq!(popup.state.selected(), None);
+        popup.move_selection(&SelectionDirection::Next);
+        assert_eq!(popup.state.selected(), None);
+        assert_eq!(popup.season().previous(), season);
+    }
+
+    #[test]
+    fn air_times() {
+        assert_eq!(
+            format_air_time(datetime!(2023-10-27 23:00 +9)),
+            "Fri 27 Oct 14:00"
+        );
+        assert_eq!(format_time_left(Duration::minutes(42)), "in 42m");
+        assert_eq!(format_time_left(Duration::minutes(185)), "in 3h 5m");
+        assert_eq!(format_time_left(Duration::hours(53)), "in 2d 5h");
+        assert_eq!(format_time_left(Duration::minutes(-5)), "airing");
+    }
+}
@@ -0,0 +1,106 @@
+use super::centered_rect;
+use crate::app::App;
+use crate::ui::{self, SelectionDirection};
+use lma_lib::{Service, ServiceTitle};
+use ratatui::layout::{Alignment, Constraint, Direction, Layout, Margin};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+
+/// Offers to add sequels of a show that was just finished
+#[derive(Default)]
+pub struct SequelPopup {
+    finished_title: String,
+    sequels: Vec<ServiceTitle>,
+    state: ListState,
+}
+
+impl SequelPopup {
+    pub fn new(finished_title: String, sequels: Vec<ServiceTitle>) -> Self {
+        Self {
+            finished_title,
+            sequels,
+            state: ListState::default().with_selected(Some(0)),
+        }
+    }
+
+    pub fn move_selection(&mut self, direction: &SelectionDirection) {
+        let i = ui::select_element(self.sequels.len(), self.state.selected(), direction);
+        self.state.select(Some(i));
+    }
+
+    pub fn selected_sequel(&self) -> Option<&ServiceTitle> {
+        self.sequels.get(self.state.selected()?)
+    }
+}
+
+pub fn build<T: Service>(frame: &mut Frame, app: &mut App<T>) {
+    let area = centered_rect(60, 50, frame.size());
+    let inner_area = area.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    let block = Block::default().title("Sequel").borders(Borders::ALL);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(1)].as_ref())
+        .split(inner_area);
+
+    let popup = &app.sequel_popup;
+    let question = format!(
+        "You've finished {}, add its sequel to the list?",
+        popup.finished_title
+    );
+    frame.render_widget(
+        Paragraph::new(question)
+            .style(Style::default().fg(app.config.colors.text))
+            .wrap(Wrap { trim: true })
+            .alignment(Alignment::Center),
+        chunks[0],
+    );
+
+    let sequels: Vec<_> = popup
+        .sequels
+        .iter()
+        .map(|sequel| {
+            let info: Vec<String> = std::iter::once(sequel.title.clone())
+                .chain(
+                    sequel
+                        .media_type
+                        .map(|media_type| media_type.as_str().to_owned()),
+                )
+                .chain(sequel.year.map(|year| year.to_string()))
+                .collect();
+            ListItem::new(info.join(", ")).style(Style::default().fg(app.config.colors.text))
+        })
+        .collect();
+    let sequels = List::new(sequels).highlight_style(
+        Style::default()
+            .fg(app.config.colors.highlight)
+            .add_modifier(Modifier::BOLD),
+    );
+    frame.render_stateful_widget(sequels, chunks[1], &mut app.sequel_popup.state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequel_selection() {
+        let sequel = |service_id| ServiceTitle {
+            service_id,
+            ..Default::default()
+        };
+        let mut popup = SequelPopup::new(String::from("Show"), vec![sequel(1), sequel(2)]);
+        assert_eq!(popup.selected_sequel().map(|s| s.service_id), Some(1));
+        popup.move_selection(&SelectionDirection::Next);
+        assert_eq!(popup.selected_sequel().map(|s| s.service_id), Some(2));
+        popup.move_selection(&SelectionDirection::Next);
+        assert_eq!(popup.selected_sequel().map(|s| s.service_id), Some(1));
+        assert_eq!(SequelPopup::default().selected_sequel(), None);
+    }
+}
@@ -0,0 +1,61 @@
+use super::centered_rect;
+use crate::config::Config;
+use crate::ui::main_menu::help::keycode_to_key;
+use lma_lib::SyncProgress;
+use ratatui::layout::{Alignment, Constraint, Direction, Layout, Margin};
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Gauge, Paragraph};
+use ratatui::Frame;
+
+pub fn build(
+    frame: &mut Frame,
+    title: &str,
+    progress: &SyncProgress,
+    last_title: Option<&str>,
+    config: &Config,
+) {
+    let area = centered_rect(60, 30, frame.size());
+    let inner_area = area.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(2),
+                Constraint::Length(1),
+                Constraint::Min(1),
+            ]
+            .as_ref(),
+        )
+        .split(inner_area);
+
+    let info = vec![
+        Line::from(Span::raw(last_title.unwrap_or("Waiting for the service"))),
+        Line::from(Span::styled(
+            format!("{} to cancel", keycode_to_key(config.key_binds.close)),
+            Style::default().fg(config.colors.secondary),
+        )),
+    ];
+    let done = progress.done();
+    let total = progress.total();
+    #[allow(clippy::cast_precision_loss)]
+    let ratio = if total == 0 {
+        0.0
+    } else {
+        (done as f64 / total as f64).min(1.0)
+    };
+    let gauge = Gauge::default()
+        .gauge_style(Style::default().fg(config.colors.highlight))
+        .ratio(ratio)
+        .label(format!("{done}/{total}"));
+
+    let block = Block::default().title(title).borders(Borders::ALL);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+    frame.render_widget(Paragraph::new(info).alignment(Alignment::Center), chunks[0]);
+    frame.render_widget(gauge, chunks[1]);
+}
@@ -0,0 +1,101 @@
+use super::centered_rect;
+use crate::app::App;
+use crate::ui::{self, widgets::ScrollableTable, SelectionDirection};
+use lma_lib::{FieldChange, Service, SyncDirection};
+use ratatui::layout::{Constraint, Margin};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Clear, Row, TableState};
+use ratatui::Frame;
+
+#[derive(Default)]
+pub struct SummaryPopup {
+    pub state: TableState,
+    changes: Vec<FieldChange>,
+}
+
+impl SummaryPopup {
+    pub fn new(changes: Vec<FieldChange>) -> Self {
+        let mut default_state = TableState::default();
+        if !changes.is_empty() {
+            default_state.select(Some(0));
+        }
+        Self {
+            state: default_state,
+            changes,
+        }
+    }
+
+    pub fn move_selection(&mut self, direction: &SelectionDirection) {
+        let i = ui::select_element(self.changes.len(), self.state.selected(), direction);
+        self.state.select(Some(i));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+pub fn build<T: Service>(frame: &mut Frame, app: &mut App<T>) {
+    let area = centered_rect(70, 70, frame.size());
+    let list_area = area.inner(&Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+
+    let items: Vec<_> = app
+        .summary_popup
+        .changes
+        .iter()
+        .map(|change| {
+            Row::new([
+                change.title.clone(),
+                change.field.name().to_owned(),
+                format!(
+                    "{} -> {}",
+                    display_value(change.old_value.as_deref()),
+                    display_value(change.new_value.as_deref())
+                ),
+                direction_text(change.direction).to_owned(),
+            ])
+            .style(Style::default().fg(app.config.colors.text))
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Percentage(100),
+        Constraint::Min(11),
+        Constraint::Min(28),
+        Constraint::Min(8),
+    ];
+    let items = ScrollableTable::new(items)
+        .header(
+            Row::new(["Title", "Field", "Change", "Where"])
+                .style(Style::default().fg(app.config.colors.secondary)),
+        )
+        .widths(&widths)
+        .column_spacing(1)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Synchronized with the service"),
+        )
+        .highlight_style(
+            Style::default()
+                .fg(app.config.colors.highlight)
+                .add_modifier(Modifier::BOLD),
+        );
+
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(items, list_area, &mut app.summary_popup.state);
+}
+
+fn display_value(value: Option<&str>) -> &str {
+    value.unwrap_or("-")
+}
+
+const fn direction_text(direction: SyncDirection) -> &'static str {
+    match direction {
+        SyncDirection::Push => "Service",
+        SyncDirection::Pull => "Local",
+    }
+}
@@ -2,8 +2,8 @@ use crate::{
     app::App,
     ui::{self, widgets::ScrollableTable, SelectionDirection},
 };
-use lma_lib::{Service, ServiceTitle};
-use ratatui::layout::Margin;
+use lma_lib::{MediaType, Service, ServiceSearchPage, ServiceTitle};
+use ratatui::layout::{Constraint, Margin};
 use ratatui::style::{Modifier, Style};
 use ratatui::widgets::{Block, Borders, Clear, Row, TableState};
 use ratatui::Frame;
@@ -12,33 +12,97 @@ use ratatui::Frame;
 pub struct TitlesPopup {
     pub state: TableState,
     service_titles: Vec<ServiceTitle>,
+    query: String,
+    next_offset: Option<usize>,
+    media_type: Option<MediaType>,
+    hide_nsfw: bool,
 }
 
 impl TitlesPopup {
-    pub fn new(titles: Vec<ServiceTitle>) -> Self {
-        let mut default_state = TableState::default();
-        // first item always selected when possible
-        if !titles.is_empty() {
-            default_state.select(Some(0));
-        }
-        Self {
-            state: default_state,
-            service_titles: titles,
-        }
+    pub fn new(query: &str, page: ServiceSearchPage) -> Self {
+        let mut popup = Self {
+            query: query.to_owned(),
+            ..Default::default()
+        };
+        popup.add_page(page);
+        popup
+    }
+
+    pub fn add_page(&mut self, page: ServiceSearchPage) {
+        self.service_titles.extend(page.titles);
+        self.next_offset = page.next_offset;
+        self.fix_selection();
+    }
+
+    /// Query and offset of the next page, only when the last title is selected
+    pub fn next_page(&self) -> Option<(String, usize)> {
+        let visible = self.visible_titles().len();
+        let at_end = visible == 0 || self.state.selected() == Some(visible - 1);
+        self.next_offset
+            .filter(|_| at_end)
+            .map(|offset| (self.query.clone(), offset))
     }
 
     pub fn move_selection(&mut self, direction: &SelectionDirection) {
-        let i = ui::select_element(self.service_titles.len(), self.state.selected(), direction);
+        let i = ui::select_element(
+            self.visible_titles().len(),
+            self.state.selected(),
+            direction,
+        );
         self.state.select(Some(i));
     }
 
     pub fn selected_show(&mut self) -> Option<&ServiceTitle> {
-        if self.service_titles.is_empty() {
-            None
-        } else {
-            let index = self.state.selected().unwrap_or_default();
-            self.service_titles.get(index)
+        let index = self.state.selected().unwrap_or_default();
+        self.visible_titles().get(index).copied()
+    }
+
+    /// Goes through every media type, starting with all of them
+    pub fn cycle_media_type(&mut self, direction: &SelectionDirection) {
+        let current = self
+            .media_type
+            .and_then(|media_type| MediaType::ALL.iter().position(|other| *other == media_type))
+            .map_or(0, |index| index + 1);
+        let next = ui::select_element(MediaType::ALL.len() + 1, Some(current), direction);
+        self.media_type = next.checked_sub(1).map(|index| MediaType::ALL[index]);
+        self.fix_selection();
+    }
+
+    pub fn toggle_nsfw(&mut self) {
+        self.hide_nsfw = !self.hide_nsfw;
+        self.fix_selection();
+    }
+
+    fn visible_titles(&self) -> Vec<&ServiceTitle> {
+        self.service_titles
+            .iter()
+            .filter(|title| {
+                (self.media_type.is_none() || title.media_type == self.media_type)
+                    && !(self.hide_nsfw && title.nsfw)
+            })
+            .collect()
+    }
+
+    /// First title always selected when possible
+    fn fix_selection(&mut self) {
+        let visible = self.visible_titles().len();
+        let selected = self.state.selected().unwrap_or_default();
+        self.state
+            .select((visible > 0).then(|| selected.min(visible - 1)));
+    }
+
+    fn window_title(&self) -> String {
+        let mut title = format!(
+            "Select correct title - {}",
+            self.media_type.map_or("All types", MediaType::as_str)
+        );
+        if self.hide_nsfw {
+            title += ", NSFW hidden";
+        }
+        if self.next_offset.is_some() {
+            title += ", more below";
         }
+        title
     }
 }
 
@@ -53,19 +117,47 @@ pub fn build<T: Service>(frame: &mut Frame, app: &mut App<T>) {
 
     let items: Vec<_> = app
         .titles_popup
-        .service_titles
-        .iter()
+        .visible_titles()
+        .into_iter()
         .map(|service_title| {
-            Row::new([service_title.title.clone()])
-                .style(Style::default().fg(app.config.colors.text))
+            Row::new([
+                service_title.title.clone(),
+                service_title
+                    .media_type
+                    .map_or("", MediaType::as_str)
+                    .to_owned(),
+                service_title
+                    .year
+                    .map(|year| year.to_string())
+                    .unwrap_or_default(),
+                service_title
+                    .episodes
+                    .map(|episodes| episodes.to_string())
+                    .unwrap_or_default(),
+                if service_title.nsfw { "18+" } else { "" }.to_owned(),
+            ])
+            .style(Style::default().fg(app.config.colors.text))
         })
         .collect();
 
+    let widths = [
+        Constraint::Percentage(100),
+        Constraint::Min(7),
+        Constraint::Min(4),
+        Constraint::Min(8),
+        Constraint::Min(4),
+    ];
     let items = ScrollableTable::new(items)
+        .header(
+            Row::new(["Title", "Type", "Year", "Episodes", "NSFW"])
+                .style(Style::default().fg(app.config.colors.secondary)),
+        )
+        .widths(&widths)
+        .column_spacing(1)
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title("Select correct title"),
+                .title(app.titles_popup.window_title()),
         )
         .highlight_style(
             Style::default()
@@ -89,13 +181,13 @@ mod tests {
 
     #[test]
     fn empty_first_selection() {
-        let mut list = TitlesPopup::new(Vec::new());
+        let mut list = TitlesPopup::new("", ServiceSearchPage::default());
         assert!(list.selected_show().is_none());
     }
 
     #[test]
     fn empty_move_selection() {
-        let mut list = TitlesPopup::new(Vec::new());
+        let mut list = TitlesPopup::new("", ServiceSearchPage::default());
         list.move_selection(&SelectionDirection::Previous);
         list.move_selection(&SelectionDirection::Previous);
         list.move_selection(&SelectionDirection::Next);
@@ -121,8 +213,57 @@ mod tests {
         assert_eq!(selected_show.service_id, 1);
     }
 
+    #[test]
+    fn filter_media_type() {
+        let mut list = generate_title_popup(6);
+        list.cycle_media_type(&SelectionDirection::Next);
+        assert_eq!(list.visible_titles().len(), 3, "Only TV");
+        list.move_selection(&SelectionDirection::Previous);
+        let selected_show = list.selected_show().expect("Last TV show");
+        assert_eq!(selected_show.service_id, 5);
+        list.cycle_media_type(&SelectionDirection::Previous);
+        assert_eq!(list.visible_titles().len(), 6, "All types");
+    }
+
+    #[test]
+    fn hide_nsfw() {
+        let mut list = generate_title_popup(6);
+        list.move_selection(&SelectionDirection::Previous);
+        list.toggle_nsfw();
+        assert_eq!(list.visible_titles().len(), 5);
+        let selected_show = list.selected_show().expect("Selection moved up");
+        assert_eq!(selected_show.service_id, 5);
+    }
+
+    #[test]
+    fn next_page_at_end() {
+        let mut list = TitlesPopup::new(
+            "Test",
+            ServiceSearchPage {
+                titles: generate_title_services(2),
+                next_offset: Some(2),
+            },
+        );
+        assert_eq!(list.next_page(), None);
+        list.move_selection(&SelectionDirection::Next);
+        assert_eq!(list.next_page(), Some((String::from("Test"), 2)));
+        list.add_page(ServiceSearchPage {
+            titles: generate_title_services(2),
+            next_offset: None,
+        });
+        list.move_selection(&SelectionDirection::Next);
+        assert_eq!(list.selected_show().map(|show| show.service_id), Some(1));
+        assert_eq!(list.state.selected(), Some(2), "Moved to the new page");
+    }
+
     fn generate_title_popup(count: usize) -> TitlesPopup {
-        TitlesPopup::new(generate_title_services(count))
+        TitlesPopup::new(
+            "Test",
+            ServiceSearchPage {
+                titles: generate_title_services(count),
+                next_offset: None,
+            },
+        )
     }
 
     fn generate_title_services(count: usize) -> Vec<ServiceTitle> {
@@ -131,6 +272,13 @@ mod tests {
             service_titles.push(ServiceTitle {
                 service_id: i,
                 title: format!("Test title {i}"),
+                media_type: Some(if i % 2 == 1 {
+                    MediaType::TV
+                } else {
+                    MediaType::Movie
+                }),
+                nsfw: i == 6,
+                ..Default::default()
             });
         }
         service_titles